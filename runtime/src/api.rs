@@ -1,32 +1,77 @@
 //! HTTP API for Nexo Serverless Runtime
 //!
 //! 提供 RESTful API 用于：
+//! - 项目管理
 //! - 函数管理（CRUD）
 //! - 函数调用
 //! - 运行时统计
+//!
+//! 管理 API 挂载在 `/api/projects/:project/...` 下，
+//! 不带项目的 `/api/...` 路径作用于默认项目。
+//! 管理 API 需要 API Key，`/fn/*` 和 `/site/*` 网关默认公开。
 
 use axum::{
-    extract::{Path, State, Query},
-    http::{StatusCode, Method, HeaderMap},
-    response::Json,
+    async_trait,
+    body::Bytes,
+    extract::{
+        ConnectInfo, DefaultBodyLimit, FromRequest, FromRequestParts, Multipart, Path, Query, RawQuery,
+        State,
+    },
+    middleware,
+    http::{request::Parts, StatusCode, Method, HeaderMap, header},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Json,
+    },
     routing::{get, post, put, delete},
     Router,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::collections::HashMap;
-use tower_http::cors::{CorsLayer, Any};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+use tokio_util::io::ReaderStream;
+use tracing::Instrument;
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer, Any};
 use tower_http::trace::TraceLayer;
 
-use crate::function::{CreateFunctionRequest, UpdateFunctionRequest, Function};
-use crate::runtime::{NexoRuntime, FunctionRequest};
-use crate::pool::PoolStats;
-use crate::site::{SiteStore, CreateSiteRequest, Site};
+use crate::auth::{self, ApiKeyInfo, ApiKeyStore, CreateApiKeyRequest, CreatedApiKey};
+use crate::health::{self, CheckResult, ProbeCache, ReadinessReport};
+use crate::function::{CreateFunctionRequest, UpdateFunctionRequest, Function, FunctionStatus};
+use crate::logs::{LogPage, LogQuery};
+use crate::runtime::{NexoRuntime, FunctionRequest, FunctionResponse, GatewayError};
+use crate::metrics::{self, FunctionMetrics, GatewayMetrics, MetricsSnapshot, SiteMetrics};
+use crate::pool::{PoolConfig, PoolStats, Priority};
+use crate::project::{
+    CreateProjectRequest, Project, ProjectStore, UpdateProjectRequest, DEFAULT_PROJECT,
+};
+use crate::rules::{RedirectMatch, SiteRules};
+use crate::secret::{SecretInfo, SetSecretRequest};
+use crate::shutdown::{self, Shutdown};
+use crate::cache;
+use crate::edge::{self, SiteMiddleware};
+use crate::archive::{self, ArchiveEntry, ArchiveFormat, ArchiveLimits};
+use crate::site::{
+    self, CreateSiteRequest, DeployPlan, DeploymentInfo, Site, SiteFile, SiteManifest, SiteMode, SiteStore,
+    SiteUpload,
+};
+use crate::telemetry;
 
 /// 应用状态
 pub struct AppState {
     pub runtime: NexoRuntime,
     pub sites: SiteStore,
+    pub projects: ProjectStore,
+    pub api_keys: ApiKeyStore,
+    pub metrics: GatewayMetrics,
+    pub shutdown: Shutdown,
+    /// 就绪检查的 V8 探测结果
+    pub v8_probe: ProbeCache,
 }
 
 /// API 响应包装
@@ -57,6 +102,46 @@ impl<T: Serialize> ApiResponse<T> {
     }
 }
 
+/// 管理 API 的项目作用域（路径中没有项目时为默认项目）
+pub struct ProjectScope(pub Project);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for ProjectScope {
+    type Rejection = (StatusCode, Json<ApiResponse<()>>);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let params = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .map(|Path(params)| params)
+            .unwrap_or_default();
+        let project_id = params
+            .get("project")
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_PROJECT);
+
+        match state.projects.get(project_id).await {
+            Some(project) => Ok(ProjectScope(project)),
+            None => Err((StatusCode::NOT_FOUND, ApiResponse::err("Project not found"))),
+        }
+    }
+}
+
+/// 资源 ID 路径参数
+#[derive(Deserialize)]
+struct IdPath {
+    id: String,
+}
+
+/// 密钥路径参数
+#[derive(Deserialize)]
+struct SecretPath {
+    id: String,
+    name: String,
+}
+
 /// 健康检查响应
 #[derive(Serialize)]
 pub struct HealthResponse {
@@ -65,6 +150,12 @@ pub struct HealthResponse {
     pub runtime: String,
 }
 
+/// 存活检查响应
+#[derive(Serialize)]
+pub struct LivenessResponse {
+    pub status: String,
+}
+
 /// 启动 API 服务器
 pub async fn start_server() -> anyhow::Result<()> {
     let max_concurrent = std::env::var("NEXO_MAX_CONCURRENT")
@@ -73,51 +164,83 @@ pub async fn start_server() -> anyhow::Result<()> {
         .unwrap_or(100);
 
     let state = Arc::new(AppState {
-        runtime: NexoRuntime::new(max_concurrent),
+        runtime: NexoRuntime::with_pool_config(PoolConfig::from_env(max_concurrent)),
         sites: SiteStore::new(),
+        projects: ProjectStore::new(),
+        api_keys: ApiKeyStore::new(),
+        metrics: GatewayMetrics::new(),
+        shutdown: Shutdown::new(),
+        v8_probe: ProbeCache::from_env(),
     });
 
+    state.api_keys.ensure_bootstrap_key().await;
+
+    // 同步项目配额到 Isolate 池
+    for project in state.projects.list().await {
+        state.runtime.pool().set_project_quotas(&project.id, &project.quotas);
+    }
+
     tracing::info!("📊 Max concurrent isolates: {}", max_concurrent);
 
-    // CORS 配置
+    // CORS 配置（NEXO_CORS_ORIGINS 为逗号分隔的来源列表，未设置时允许任意来源）
+    let allow_origin = match std::env::var("NEXO_CORS_ORIGINS") {
+        Ok(origins) if !origins.trim().is_empty() && origins.trim() != "*" => AllowOrigin::list(
+            origins
+                .split(',')
+                .filter_map(|o| o.trim().parse().ok())
+                .collect::<Vec<_>>(),
+        ),
+        _ => AllowOrigin::from(Any),
+    };
     let cors = CorsLayer::new()
-        .allow_origin(Any)
+        .allow_origin(allow_origin)
         .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE, Method::OPTIONS])
         .allow_headers(Any);
 
+    // 管理路由（需要 API Key）
+    let management = Router::new()
+        .route("/stats", get(stats_handler))
+        .route("/metrics", get(metrics_handler))
+
+        // API Key 管理
+        .route("/api/keys", get(list_api_keys))
+        .route("/api/keys", post(create_api_key))
+        .route("/api/keys/:id", delete(delete_api_key))
+
+        // 项目管理 API
+        .route("/api/projects", get(list_projects))
+        .route("/api/projects", post(create_project))
+        .route("/api/projects/:project", get(get_project))
+        .route("/api/projects/:project", put(update_project))
+        .route("/api/projects/:project", delete(delete_project))
+
+        // 函数和站点管理 API（按项目划分，/api 下为默认项目）
+        .nest("/api/projects/:project", management_routes())
+        .nest("/api", management_routes())
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_api_key));
+
     // 构建路由
     let app = Router::new()
-        // 健康检查和统计
+        // 健康检查
         .route("/health", get(health_handler))
-        .route("/stats", get(stats_handler))
-        
-        // 函数管理 API
-        .route("/api/functions", get(list_functions))
-        .route("/api/functions", post(create_function))
-        .route("/api/functions/:id", get(get_function))
-        .route("/api/functions/:id", put(update_function))
-        .route("/api/functions/:id", delete(delete_function))
-        .route("/api/functions/:id/invoke", post(invoke_function))
-        .route("/api/functions/:id/stats", get(function_stats))
-        
-        // 静态站点 API
-        .route("/api/sites", get(list_sites))
-        .route("/api/sites", post(create_site))
-        .route("/api/sites/:id", get(get_site))
-        .route("/api/sites/:id", delete(delete_site))
+        .route("/health/live", get(liveness_handler))
+        .route("/health/ready", get(readiness_handler))
+        .merge(management)
         
         // 静态站点访问
         .route("/site/*path", get(serve_site))
+        .route("/preview/:id", get(serve_preview_root))
+        .route("/preview/:id/*path", get(serve_preview))
         
         // 函数调用网关
-        .route("/fn/*path", get(invoke_by_route))
-        .route("/fn/*path", post(invoke_by_route))
-        .route("/fn/*path", put(invoke_by_route))
-        .route("/fn/*path", delete(invoke_by_route))
+        .merge(gateway_routes())
+        
+        // 绑定域名的站点
+        .fallback(serve_host_site)
         
         .layer(cors)
         .layer(TraceLayer::new_for_http())
-        .with_state(state);
+        .with_state(state.clone());
 
     let addr = std::env::var("NEXO_ADDR").unwrap_or_else(|_| "0.0.0.0:3000".to_string());
     let listener = tokio::net::TcpListener::bind(&addr).await?;
@@ -125,19 +248,128 @@ pub async fn start_server() -> anyhow::Result<()> {
     tracing::info!("🌐 API server listening on http://{}", addr);
     tracing::info!("📚 Endpoints:");
     tracing::info!("   GET  /health          - Health check");
+    tracing::info!("   GET  /health/live     - Liveness probe");
+    tracing::info!("   GET  /health/ready    - Readiness probe (V8, storage, pool)");
     tracing::info!("   GET  /stats           - Runtime statistics");
+    tracing::info!("   *    /api/*, /stats   - Require API key (Bearer or X-API-Key)");
+    tracing::info!("   GET  /api/projects    - List projects");
     tracing::info!("   GET  /api/functions   - List functions");
     tracing::info!("   POST /api/functions   - Create function");
     tracing::info!("   GET  /api/sites       - List static sites");
     tracing::info!("   POST /api/sites       - Deploy static site");
+    tracing::info!("   ANY  /api/projects/:project/... - Project scoped management API");
+    tracing::info!("   PUT  /api/sites/:id   - Redeploy static site");
+    tracing::info!("   POST /api/sites/:id/rollback - Roll back to a deployment");
+    tracing::info!("   PUT  /api/sites/:id/middleware - Run a function before the site");
     tracing::info!("   GET  /site/*          - Serve static site");
+    tracing::info!("   GET  /preview/:id/*   - Preview a deployment");
     tracing::info!("   ANY  /fn/*            - Invoke function by route");
 
-    axum::serve(listener, app).await?;
+    // 收到停机信号后先让就绪检查失败，继续服务一段时间等负载均衡摘除实例，
+    // 再停止接受新连接，等待进行中的请求完成（最多排空超时）
+    let shutdown_delay = Shutdown::shutdown_delay_from_env();
+    let drain_timeout = Shutdown::drain_timeout_from_env();
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown({
+            let state = state.clone();
+            async move {
+                shutdown::signal().await;
+                state.shutdown.begin_drain();
+                tracing::info!("🛑 Shutdown signal received, not ready; still serving for {:?}", shutdown_delay);
+                tokio::time::sleep(shutdown_delay).await;
+                tracing::info!("🛑 Stopped accepting connections, draining (timeout {:?})", drain_timeout);
+            }
+        });
+
+    tokio::select! {
+        result = server => result?,
+        _ = async {
+            state.shutdown.draining().await;
+            tokio::time::sleep(shutdown_delay + drain_timeout).await;
+        } => {
+            let stats = state.runtime.get_pool_stats().await;
+            tracing::warn!(
+                "⏱️ Drain timeout, abandoning {} running and {} queued executions",
+                stats.current_concurrent,
+                stats.queue_depth
+            );
+        }
+    }
+
+    // 保存内存中的调用和访问计数
+    state.runtime.functions.flush().await;
+    state.sites.flush().await;
+    tracing::info!("👋 Server stopped");
 
     Ok(())
 }
 
+/// 函数调用网关路由，NEXO_FN_COMPRESSION=1 时按 Accept-Encoding 压缩函数响应
+fn gateway_routes() -> Router<Arc<AppState>> {
+    let compress = std::env::var("NEXO_FN_COMPRESSION").is_ok_and(|v| v == "1" || v == "true");
+
+    Router::new()
+        .route("/fn/*path", get(invoke_by_route))
+        .route("/fn/*path", post(invoke_by_route))
+        .route("/fn/*path", put(invoke_by_route))
+        .route("/fn/*path", delete(invoke_by_route))
+        // 关闭时不启用任何编码，响应原样返回；已带 Content-Encoding 的响应不会重复压缩
+        .layer(CompressionLayer::new().gzip(compress).br(compress))
+}
+
+/// 站点上传请求体的大小上限：NEXO_SITE_MAX_UPLOAD_MB（默认 100）
+fn max_site_upload_bytes() -> usize {
+    std::env::var("NEXO_SITE_MAX_UPLOAD_MB")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .filter(|&mb| mb > 0)
+        .unwrap_or(100)
+        * 1024
+        * 1024
+}
+
+/// 函数和站点管理路由
+fn management_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        // 函数管理 API
+        .route("/functions", get(list_functions))
+        .route("/functions", post(create_function))
+        .route("/functions/:id", get(get_function))
+        .route("/functions/:id", put(update_function))
+        .route("/functions/:id", delete(delete_function))
+        .route("/functions/:id/invoke", post(invoke_function))
+        .route("/functions/:id/stats", get(function_stats))
+        .route("/functions/:id/logs", get(function_logs))
+        .route("/functions/:id/logs/tail", get(tail_logs))
+        .route("/functions/:id/secrets", get(list_secrets))
+        .route("/functions/:id/secrets/:name", put(set_secret))
+        .route("/functions/:id/secrets/:name", delete(delete_secret))
+
+        // 静态站点 API
+        .route("/sites", get(list_sites))
+        .route(
+            "/sites",
+            post(create_site).layer(DefaultBodyLimit::max(max_site_upload_bytes())),
+        )
+        .route("/sites/deploys", post(begin_site_deploy))
+        .route(
+            "/sites/deploys/:id/blobs/:hash",
+            put(upload_site_blob).layer(DefaultBodyLimit::max(max_site_upload_bytes())),
+        )
+        .route("/sites/deploys/:id/finalize", post(finalize_site_deploy))
+        .route("/sites/:id", get(get_site))
+        .route(
+            "/sites/:id",
+            put(update_site).layer(DefaultBodyLimit::max(max_site_upload_bytes())),
+        )
+        .route("/sites/:id", delete(delete_site))
+        .route("/sites/:id/deployments", get(list_site_deployments))
+        .route("/sites/:id/rollback", post(rollback_site))
+        .route("/sites/:id/domains", put(set_site_domains))
+        .route("/sites/:id/middleware", put(set_site_middleware))
+        .route("/sites/:id/middleware", delete(delete_site_middleware))
+}
+
 /// 健康检查
 async fn health_handler() -> Json<HealthResponse> {
     Json(HealthResponse {
@@ -147,6 +379,40 @@ async fn health_handler() -> Json<HealthResponse> {
     })
 }
 
+/// 存活检查：进程和事件循环在响应
+async fn liveness_handler() -> Json<LivenessResponse> {
+    Json(LivenessResponse { status: "alive".to_string() })
+}
+
+/// 就绪检查：任一检查失败或正在排空时返回 503 及各项详情
+async fn readiness_handler(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ReadinessReport>) {
+    let pool = state.runtime.pool();
+    let storage_dir = state.runtime.functions.storage_dir().to_path_buf();
+
+    let (v8, storage, stats) = tokio::join!(
+        state.v8_probe.get_or_run(|| health::check_v8(pool, health::probe_deadline_from_env())),
+        async {
+            tokio::task::spawn_blocking(move || health::check_storage(&storage_dir))
+                .await
+                .unwrap_or_else(|e| CheckResult::fail("storage", e.to_string(), Duration::ZERO))
+        },
+        pool.get_stats(),
+    );
+
+    let report = ReadinessReport::new(
+        state.shutdown.is_draining(),
+        vec![v8, storage, health::check_pool(&stats)],
+    );
+    let status = if report.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
+
 /// 运行时统计
 async fn stats_handler(
     State(state): State<Arc<AppState>>,
@@ -155,20 +421,78 @@ async fn stats_handler(
     ApiResponse::ok(stats)
 }
 
+/// Prometheus 指标
+async fn metrics_handler(
+    State(state): State<Arc<AppState>>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    let pool = state.runtime.pool();
+    let function_stats = pool.get_all_function_stats().await;
+    let mut durations = pool.execution_histograms();
+
+    let functions = state
+        .runtime
+        .functions
+        .list_all()
+        .await
+        .into_iter()
+        .map(|f| FunctionMetrics {
+            stats: function_stats.get(&f.id).cloned().unwrap_or_default(),
+            duration: durations.remove(&f.id).unwrap_or_default(),
+            id: f.id,
+            name: f.name,
+        })
+        .collect();
+
+    let sites = state
+        .sites
+        .list_all()
+        .await
+        .into_iter()
+        .map(|s| SiteMetrics { id: s.id, name: s.name, visits: s.visits })
+        .collect();
+
+    let snapshot = MetricsSnapshot {
+        pool: state.runtime.get_pool_stats().await,
+        functions,
+        responses: state.metrics.responses(),
+        sites,
+    };
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        metrics::render(&snapshot),
+    )
+        .into_response()
+}
+
+/// 获取项目内的函数
+async fn scoped_function(state: &AppState, project: &Project, id: &str) -> Option<Function> {
+    state
+        .runtime
+        .functions
+        .get(id)
+        .await
+        .filter(|f| f.project_id == project.id)
+}
+
 /// 列出所有函数
 async fn list_functions(
     State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
 ) -> Json<ApiResponse<Vec<Function>>> {
-    let functions = state.runtime.functions.list().await;
+    let functions = state.runtime.functions.list(&project.id).await;
     ApiResponse::ok(functions)
 }
 
 /// 获取单个函数
 async fn get_function(
     State(state): State<Arc<AppState>>,
-    Path(id): Path<String>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
 ) -> Result<Json<ApiResponse<Function>>, StatusCode> {
-    match state.runtime.functions.get(&id).await {
+    match scoped_function(&state, &project, &id).await {
         Some(function) => Ok(ApiResponse::ok(function)),
         None => Err(StatusCode::NOT_FOUND),
     }
@@ -177,9 +501,29 @@ async fn get_function(
 /// 创建函数
 async fn create_function(
     State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
     Json(req): Json<CreateFunctionRequest>,
-) -> Result<Json<ApiResponse<Function>>, (StatusCode, Json<ApiResponse<()>>)> {
-    match state.runtime.functions.create(req).await {
+) -> Result<Json<ApiResponse<Function>>, (StatusCode, Json<ApiResponse<Function>>)> {
+    let max_memory_mb = req.limits.clone().unwrap_or_default().max_memory_mb;
+    if let Err(e) = project.check_memory_limit(max_memory_mb) {
+        return Err((StatusCode::BAD_REQUEST, ApiResponse::err(e)));
+    }
+
+    match state.runtime.deploy_function(&project.id, req).await {
+        // 编译失败的函数保留为 Error 状态，随错误一起返回
+        Ok(function) if function.status == FunctionStatus::Error => {
+            let error = function
+                .deploy_error
+                .as_ref()
+                .map(|e| format!("Deployment failed: {}", e))
+                .unwrap_or_else(|| "Deployment failed".to_string());
+            tracing::warn!("❌ Function {} ({}) failed to deploy: {}", function.name, function.id, error);
+            Err((StatusCode::UNPROCESSABLE_ENTITY, Json(ApiResponse {
+                success: false,
+                data: Some(function),
+                error: Some(error),
+            })))
+        }
         Ok(function) => {
             tracing::info!("✅ Created function: {} ({})", function.name, function.id);
             Ok(ApiResponse::ok(function))
@@ -191,10 +535,20 @@ async fn create_function(
 /// 更新函数
 async fn update_function(
     State(state): State<Arc<AppState>>,
-    Path(id): Path<String>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
     Json(req): Json<UpdateFunctionRequest>,
 ) -> Result<Json<ApiResponse<Function>>, (StatusCode, Json<ApiResponse<()>>)> {
-    match state.runtime.functions.update(&id, req).await {
+    if scoped_function(&state, &project, &id).await.is_none() {
+        return Err((StatusCode::NOT_FOUND, ApiResponse::err("Function not found")));
+    }
+    if let Some(limits) = &req.limits {
+        if let Err(e) = project.check_memory_limit(limits.max_memory_mb) {
+            return Err((StatusCode::BAD_REQUEST, ApiResponse::err(e)));
+        }
+    }
+
+    match state.runtime.update_function(&id, req).await {
         Ok(function) => {
             tracing::info!("✅ Updated function: {} ({})", function.name, function.id);
             Ok(ApiResponse::ok(function))
@@ -206,10 +560,16 @@ async fn update_function(
 /// 删除函数
 async fn delete_function(
     State(state): State<Arc<AppState>>,
-    Path(id): Path<String>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
 ) -> Result<Json<ApiResponse<()>>, (StatusCode, Json<ApiResponse<()>>)> {
-    match state.runtime.functions.delete(&id).await {
+    if scoped_function(&state, &project, &id).await.is_none() {
+        return Err((StatusCode::NOT_FOUND, ApiResponse::err("Function not found")));
+    }
+
+    match state.runtime.delete_function(&id).await {
         Ok(_) => {
+            state.metrics.remove_function(&id);
             tracing::info!("🗑️ Deleted function: {}", id);
             Ok(ApiResponse::ok(()))
         }
@@ -220,14 +580,102 @@ async fn delete_function(
 /// 函数统计
 async fn function_stats(
     State(state): State<Arc<AppState>>,
-    Path(id): Path<String>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
 ) -> Result<Json<ApiResponse<crate::pool::FunctionStats>>, StatusCode> {
+    if scoped_function(&state, &project, &id).await.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
     match state.runtime.pool().get_function_stats(&id).await {
         Some(stats) => Ok(ApiResponse::ok(stats)),
         None => Err(StatusCode::NOT_FOUND),
     }
 }
 
+/// 查询函数调用记录（最新的在前）
+async fn function_logs(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
+    Query(query): Query<LogQuery>,
+) -> Result<Json<ApiResponse<LogPage>>, StatusCode> {
+    if scoped_function(&state, &project, &id).await.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(ApiResponse::ok(state.runtime.logs.query(&id, &query).await))
+}
+
+/// 实时推送函数调用记录（SSE，每条记录一个 `invocation` 事件）
+async fn tail_logs(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode> {
+    if scoped_function(&state, &project, &id).await.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    // 订阅者跟不上时丢弃落后的记录
+    let stream = BroadcastStream::new(state.runtime.logs.subscribe()).filter_map(move |record| {
+        let record = record.ok().filter(|r| r.function_id == id)?;
+        Event::default().event("invocation").json_data(&record).ok().map(Ok)
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// 列出函数密钥（只返回名称，不返回值）
+async fn list_secrets(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<Json<ApiResponse<Vec<SecretInfo>>>, StatusCode> {
+    if scoped_function(&state, &project, &id).await.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(ApiResponse::ok(state.runtime.secrets.list(&id).await))
+}
+
+/// 设置函数密钥
+async fn set_secret(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Path(SecretPath { id, name }): Path<SecretPath>,
+    Json(req): Json<SetSecretRequest>,
+) -> Result<Json<ApiResponse<SecretInfo>>, (StatusCode, Json<ApiResponse<()>>)> {
+    if scoped_function(&state, &project, &id).await.is_none() {
+        return Err((StatusCode::NOT_FOUND, ApiResponse::err("Function not found")));
+    }
+
+    match state.runtime.secrets.set(&id, &name, &req.value).await {
+        Ok(info) => {
+            tracing::info!("🔐 Set secret {} for function {}", name, id);
+            Ok(ApiResponse::ok(info))
+        }
+        Err(e) => Err((StatusCode::BAD_REQUEST, ApiResponse::err(e))),
+    }
+}
+
+/// 删除函数密钥
+async fn delete_secret(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Path(SecretPath { id, name }): Path<SecretPath>,
+) -> Result<Json<ApiResponse<()>>, (StatusCode, Json<ApiResponse<()>>)> {
+    if scoped_function(&state, &project, &id).await.is_none() {
+        return Err((StatusCode::NOT_FOUND, ApiResponse::err("Function not found")));
+    }
+
+    match state.runtime.secrets.delete(&id, &name).await {
+        Ok(_) => {
+            tracing::info!("🗑️ Deleted secret {} for function {}", name, id);
+            Ok(ApiResponse::ok(()))
+        }
+        Err(e) => Err((StatusCode::NOT_FOUND, ApiResponse::err(e))),
+    }
+}
+
 /// 调用参数
 #[derive(Deserialize, Default)]
 pub struct InvokeParams {
@@ -238,30 +686,31 @@ pub struct InvokeParams {
 /// 通过 ID 调用函数
 async fn invoke_function(
     State(state): State<Arc<AppState>>,
-    Path(id): Path<String>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
     headers: HeaderMap,
     Query(params): Query<InvokeParams>,
     body: Option<String>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, Json<ApiResponse<()>>)> {
-    let function = match state.runtime.functions.get(&id).await {
+    let function = match scoped_function(&state, &project, &id).await {
         Some(f) => f,
         None => return Err((StatusCode::NOT_FOUND, ApiResponse::err("Function not found"))),
     };
 
     let request = FunctionRequest {
-        url: format!("/fn{}", function.route),
+        url: format!("/fn{}", project.public_path(&function.route)),
         method: "POST".to_string(),
-        headers: headers
-            .iter()
-            .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.to_string(), v.to_string())))
-            .collect(),
+        // 调用方用管理 API Key 认证，不把 Key 交给函数代码
+        headers: auth::function_headers(&headers),
         body,
         path_params: HashMap::new(),
         query_params: params.query,
         env: HashMap::new(),
+        auth: None,
+        client_ip: None,
     };
 
-    let response = state.runtime.execute_function(&function, request).await;
+    let response = state.runtime.execute_function(&function, request, Priority::Management).await;
 
     Ok(Json(serde_json::json!({
         "success": true,
@@ -271,69 +720,333 @@ async fn invoke_function(
             "execution_time_ms": response.execution_time_ms,
             "memory_used_bytes": response.memory_used_bytes,
             "function_id": response.function_id,
+            "request_id": response.request_id,
             "logs": response.logs,
         }
     })))
 }
 
+/// 客户端 IP：设置 NEXO_TRUST_PROXY 时从 X-Forwarded-For 右侧取地址
+///
+/// 左侧的条目由客户端控制，只有受信代理追加的右侧条目可信。`NEXO_TRUST_PROXY=1`（或 `true`）
+/// 表示一层代理，取最右边的地址；`NEXO_TRUST_PROXY=n` 表示 n 层代理，取右数第 n 个。
+fn client_ip(headers: &HeaderMap, remote_addr: SocketAddr) -> String {
+    let trusted_hops = match std::env::var("NEXO_TRUST_PROXY").as_deref() {
+        Ok("true") => 1,
+        Ok(v) => v.parse::<usize>().unwrap_or(0),
+        Err(_) => 0,
+    };
+    if trusted_hops > 0 {
+        if let Some(forwarded) = headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').nth(trusted_hops - 1))
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+        {
+            return forwarded.to_string();
+        }
+    }
+    remote_addr.ip().to_string()
+}
+
 /// 通过路由调用函数 - 直接返回函数响应内容（用于静态页面托管等）
 async fn invoke_by_route(
     State(state): State<Arc<AppState>>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
     Path(path): Path<String>,
     method: Method,
     headers: HeaderMap,
     Query(params): Query<InvokeParams>,
     body: Option<String>,
+) -> axum::response::Response {
+    invoke_gateway(&state, remote_addr, None, format!("/{}", path), method, headers, params.query, body).await
+}
+
+/// 网关调用（`/fn/*` 和站点重写规则共用），`path` 为 `/fn` 之后的部分。
+/// 未指定 `project_id` 时按 Host 和路由前缀确定项目
+#[allow(clippy::too_many_arguments)]
+async fn invoke_gateway(
+    state: &AppState,
+    remote_addr: SocketAddr,
+    project_id: Option<&str>,
+    path: String,
+    method: Method,
+    headers: HeaderMap,
+    query: HashMap<String, String>,
+    body: Option<String>,
+) -> axum::response::Response {
+    let request_headers: HashMap<String, String> = headers
+        .iter()
+        .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.to_string(), v.to_string())))
+        .collect();
+
+    // 网关 span，以上游的 traceparent 为父级
+    let span = tracing::info_span!(
+        "gateway.invoke",
+        otel.kind = "server",
+        http.method = %method,
+        http.target = %format!("/fn{}", path),
+        function_id = tracing::field::Empty,
+        http.status_code = tracing::field::Empty,
+    );
+    telemetry::set_parent_from_headers(&span, &request_headers);
+
+    route_request(state, remote_addr, project_id, path, method, headers, request_headers, query, body)
+        .instrument(span)
+        .await
+}
+
+/// 网关请求处理（在网关 span 内执行）
+#[allow(clippy::too_many_arguments)]
+async fn route_request(
+    state: &AppState,
+    remote_addr: SocketAddr,
+    project_id: Option<&str>,
+    path: String,
+    method: Method,
+    headers: HeaderMap,
+    mut request_headers: HashMap<String, String>,
+    query: HashMap<String, String>,
+    body: Option<String>,
 ) -> axum::response::Response {
     use axum::response::IntoResponse;
-    use axum::http::header;
-    
-    let route = format!("/{}", path);
+
+    // 按 Host 和路由前缀确定项目
+    let resolved = match project_id {
+        Some(project_id) => Some((project_id.to_string(), path.clone())),
+        None => {
+            let host = headers.get(header::HOST).and_then(|h| h.to_str().ok());
+            state.projects.resolve(host, &path).await.map(|(project, route)| (project.id, route))
+        }
+    };
+    let Some((project_id, route)) = resolved else {
+        state.metrics.record_response(None, StatusCode::NOT_FOUND.as_u16());
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({
+            "success": false,
+            "error": "Project not found"
+        }))).into_response();
+    };
+
+    let function = state.runtime.functions.get_by_route(&project_id, &route).await;
+    let function_id = function.as_ref().map(|f| f.id.clone());
+    if let Some(id) = &function_id {
+        tracing::Span::current().record("function_id", id.as_str());
+    }
+
+    // 要求 API Key 的函数：校验通过后只把 Key 的 ID 传给函数，不把 Key 本身交给函数代码
+    let mut api_key_auth = None;
+    if let Some(function) = function.filter(|f| f.require_api_key) {
+        let key = match auth::extract_token(&headers) {
+            Some(token) => state.api_keys.authenticate(token).await,
+            None => None,
+        };
+        let rejection = match &key {
+            None => Some((StatusCode::UNAUTHORIZED, "Missing or invalid API key")),
+            Some(key) if !key.allows_project(&project_id) => {
+                Some((StatusCode::FORBIDDEN, "API key is not valid for this project"))
+            }
+            Some(_) => None,
+        };
+        if let Some((status, error)) = rejection {
+            state.metrics.record_response(Some(&function.id), status.as_u16());
+            return (status, Json(serde_json::json!({
+                "success": false,
+                "error": error
+            }))).into_response();
+        }
+
+        request_headers = auth::function_headers(&headers);
+        api_key_auth = key.map(|key| serde_json::json!({ "type": "api_key", "key_id": key.id }));
+    }
+
+    // 函数看到的 traceparent 指向网关 span
+    if let Some(traceparent) = telemetry::traceparent(&tracing::Span::current()) {
+        request_headers.insert(telemetry::TRACEPARENT.to_string(), traceparent);
+    }
 
     let request = FunctionRequest {
-        url: format!("/fn{}", route),
+        url: format!("/fn{}", path),
         method: method.to_string(),
-        headers: headers
-            .iter()
-            .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.to_string(), v.to_string())))
-            .collect(),
+        headers: request_headers,
         body,
         path_params: HashMap::new(),
-        query_params: params.query,
+        query_params: query,
         env: HashMap::new(),
+        auth: api_key_auth,
+        client_ip: Some(client_ip(&headers, remote_addr)),
     };
 
-    match state.runtime.execute_by_route(&route, &method.to_string(), request).await {
-        Ok(response) => {
-            // 获取函数返回的 Content-Type
-            let content_type = response.headers
-                .get("Content-Type")
-                .or_else(|| response.headers.get("content-type"))
-                .cloned()
-                .unwrap_or_else(|| "application/json".to_string());
-            
-            // 直接返回函数的响应体
-            let body_str = match &response.body {
-                Some(serde_json::Value::String(s)) => s.clone(),
-                Some(v) => v.to_string(),
-                None => String::new(),
-            };
-            
-            axum::response::Response::builder()
-                .status(response.status)
-                .header(header::CONTENT_TYPE, content_type)
-                .header("X-Execution-Time-Ms", response.execution_time_ms.to_string())
-                .header("X-Function-Id", response.function_id)
-                .body(axum::body::Body::from(body_str))
-                .unwrap()
-                .into_response()
+    let response = match state.runtime.execute_by_route(&project_id, &route, method.as_str(), request).await {
+        Ok(response) => function_response(response),
+        Err(e) => gateway_error_response(&e),
+    };
+
+    state.metrics.record_response(function_id.as_deref(), response.status().as_u16());
+    tracing::Span::current().record("http.status_code", response.status().as_u16());
+    response
+}
+
+/// 网关拒绝请求时的响应，认证失败和限流时带上对应的响应头
+fn gateway_error_response(e: &GatewayError) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    let status = StatusCode::from_u16(e.status()).unwrap_or(StatusCode::NOT_FOUND);
+    let mut response = (status, Json(serde_json::json!({
+        "success": false,
+        "error": e.message()
+    }))).into_response();
+    match e {
+        GatewayError::Unauthorized { challenge, .. } => {
+            if let Ok(value) = header::HeaderValue::from_str(challenge) {
+                response.headers_mut().insert(header::WWW_AUTHENTICATE, value);
+            }
         }
-        Err(e) => {
-            (StatusCode::NOT_FOUND, Json(serde_json::json!({
-                "success": false,
-                "error": e
-            }))).into_response()
+        GatewayError::TooManyRequests { retry_after_secs, .. } => {
+            response.headers_mut().insert(header::RETRY_AFTER, (*retry_after_secs).into());
         }
+        _ => {}
+    }
+    response
+}
+
+/// 把函数响应转换为 HTTP 响应，直接返回函数的响应体和响应头
+fn function_response(response: FunctionResponse) -> axum::response::Response {
+    let forwarded = response.forwarded_headers();
+
+    // 直接返回函数的响应体
+    let body_str = match response.body {
+        Some(serde_json::Value::String(s)) => s,
+        Some(v) => v.to_string(),
+        None => String::new(),
+    };
+
+    let mut builder = axum::response::Response::builder().status(response.status);
+    // 函数没有指定 Content-Type 时按 JSON 返回
+    if !forwarded.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-type")) {
+        builder = builder.header(header::CONTENT_TYPE, "application/json");
+    }
+    for (name, value) in forwarded {
+        builder = builder.header(name, value);
+    }
+
+    builder
+        .header("X-Execution-Time-Ms", response.execution_time_ms.to_string())
+        .header("X-Function-Id", response.function_id)
+        .header("X-Request-Id", response.request_id)
+        .body(axum::body::Body::from(body_str))
+        .unwrap()
+}
+
+// ==================== API Key ====================
+
+/// 列出 API Key
+async fn list_api_keys(
+    State(state): State<Arc<AppState>>,
+) -> Json<ApiResponse<Vec<ApiKeyInfo>>> {
+    ApiResponse::ok(state.api_keys.list().await)
+}
+
+/// 创建 API Key（明文只在响应中返回一次）
+async fn create_api_key(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<CreateApiKeyRequest>,
+) -> Result<Json<ApiResponse<CreatedApiKey>>, (StatusCode, Json<ApiResponse<()>>)> {
+    if let Some(project_id) = &req.project_id {
+        if state.projects.get(project_id).await.is_none() {
+            return Err((StatusCode::BAD_REQUEST, ApiResponse::err("Project not found")));
+        }
+    }
+
+    match state.api_keys.create(req).await {
+        Ok(created) => {
+            tracing::info!("🔑 Created API key: {} ({})", created.info.name, created.info.id);
+            Ok(ApiResponse::ok(created))
+        }
+        Err(e) => Err((StatusCode::BAD_REQUEST, ApiResponse::err(e))),
+    }
+}
+
+/// 删除 API Key
+async fn delete_api_key(
+    State(state): State<Arc<AppState>>,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<Json<ApiResponse<()>>, (StatusCode, Json<ApiResponse<()>>)> {
+    match state.api_keys.delete(&id).await {
+        Ok(_) => {
+            tracing::info!("🗑️ Deleted API key: {}", id);
+            Ok(ApiResponse::ok(()))
+        }
+        Err(e) => Err((StatusCode::NOT_FOUND, ApiResponse::err(e))),
+    }
+}
+
+// ==================== 项目 API ====================
+
+/// 列出所有项目
+async fn list_projects(
+    State(state): State<Arc<AppState>>,
+) -> Json<ApiResponse<Vec<Project>>> {
+    let projects = state.projects.list().await;
+    ApiResponse::ok(projects)
+}
+
+/// 创建项目
+async fn create_project(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<CreateProjectRequest>,
+) -> Result<Json<ApiResponse<Project>>, (StatusCode, Json<ApiResponse<()>>)> {
+    match state.projects.create(req).await {
+        Ok(project) => {
+            state.runtime.pool().set_project_quotas(&project.id, &project.quotas);
+            tracing::info!("✅ Created project: {}", project.id);
+            Ok(ApiResponse::ok(project))
+        }
+        Err(e) => Err((StatusCode::BAD_REQUEST, ApiResponse::err(e))),
+    }
+}
+
+/// 获取项目详情
+async fn get_project(
+    ProjectScope(project): ProjectScope,
+) -> Json<ApiResponse<Project>> {
+    ApiResponse::ok(project)
+}
+
+/// 更新项目
+async fn update_project(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Json(req): Json<UpdateProjectRequest>,
+) -> Result<Json<ApiResponse<Project>>, (StatusCode, Json<ApiResponse<()>>)> {
+    match state.projects.update(&project.id, req).await {
+        Ok(project) => {
+            state.runtime.pool().set_project_quotas(&project.id, &project.quotas);
+            tracing::info!("✅ Updated project: {}", project.id);
+            Ok(ApiResponse::ok(project))
+        }
+        Err(e) => Err((StatusCode::BAD_REQUEST, ApiResponse::err(e))),
+    }
+}
+
+/// 删除项目（项目下必须没有函数和站点）
+async fn delete_project(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+) -> Result<Json<ApiResponse<()>>, (StatusCode, Json<ApiResponse<()>>)> {
+    if !state.runtime.functions.list(&project.id).await.is_empty()
+        || !state.sites.list(&project.id).await.is_empty()
+    {
+        return Err((StatusCode::CONFLICT, ApiResponse::err("Project still has functions or sites")));
+    }
+
+    match state.projects.delete(&project.id).await {
+        Ok(_) => {
+            state.runtime.pool().remove_project_quotas(&project.id);
+            tracing::info!("🗑️ Deleted project: {}", project.id);
+            Ok(ApiResponse::ok(()))
+        }
+        Err(e) => Err((StatusCode::BAD_REQUEST, ApiResponse::err(e))),
     }
 }
 
@@ -342,8 +1055,9 @@ async fn invoke_by_route(
 /// 列出所有站点
 async fn list_sites(
     State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
 ) -> Json<ApiResponse<Vec<Site>>> {
-    let sites = state.sites.list().await;
+    let sites = state.sites.list(&project.id).await;
     ApiResponse::ok(sites)
 }
 
@@ -355,34 +1069,334 @@ struct CreateSiteResponse {
     route: String,
     url: String,
     files_count: usize,
+    deployment_id: String,
+    /// 该部署的预览地址（回滚或再次部署后仍可访问）
+    preview_url: String,
+}
+
+impl CreateSiteResponse {
+    fn new(project: &Project, site: Site) -> Self {
+        // 构建访问 URL
+        let addr = std::env::var("NEXO_ADDR").unwrap_or_else(|_| "localhost:3000".to_string());
+        let host = if addr.starts_with("0.0.0.0") {
+            format!("localhost:{}", addr.split(':').last().unwrap_or("3000"))
+        } else {
+            addr
+        };
+
+        Self {
+            url: format!("http://{}/site{}", host, project.public_path(&site.route)),
+            preview_url: format!("http://{}/preview/{}/", host, site.active_deployment),
+            deployment_id: site.active_deployment,
+            files_count: site.files.len(),
+            id: site.id,
+            name: site.name,
+            route: site.route,
+        }
+    }
+}
+
+/// 归档包上传的站点参数（通过查询参数或 multipart 文本字段传入）
+#[derive(Deserialize, Default)]
+struct SiteUploadParams {
+    name: Option<String>,
+    route: Option<String>,
+    project_type: Option<String>,
+    mode: Option<SiteMode>,
+    #[serde(default)]
+    rewrite_assets: bool,
+}
+
+impl SiteUploadParams {
+    fn into_upload(self, files: Vec<ArchiveEntry>) -> SiteUpload {
+        SiteUpload {
+            name: self.name,
+            route: self.route,
+            project_type: self.project_type.unwrap_or_else(site::default_project_type),
+            files,
+            rules: SiteRules::default(),
+            mode: self.mode,
+            asset_prefix: self.rewrite_assets.then(|| site::SITE_PREFIX.to_string()),
+        }
+    }
+}
+
+/// 解压归档包，格式按 Content-Type 或文件头判断
+fn extract_archive(bytes: &[u8], content_type: Option<&str>) -> Result<Vec<ArchiveEntry>, String> {
+    let format = content_type
+        .and_then(ArchiveFormat::from_content_type)
+        .or_else(|| ArchiveFormat::detect(bytes))
+        .ok_or("Unsupported archive format, expected tar.gz or zip")?;
+
+    archive::extract(bytes, format, &ArchiveLimits::from_env())
+}
+
+/// 从 multipart 表单读取归档包（`archive` 或 `file` 字段）和站点参数
+async fn read_multipart_upload(mut multipart: Multipart) -> Result<SiteUpload, String> {
+    let mut params = SiteUploadParams::default();
+    let mut files = None;
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| e.to_string())? {
+        let name = field.name().unwrap_or_default().to_string();
+        match name.as_str() {
+            "archive" | "file" => {
+                let content_type = field.content_type().map(str::to_string);
+                let bytes = field.bytes().await.map_err(|e| e.to_string())?;
+                let entries = tokio::task::spawn_blocking(move || {
+                    extract_archive(&bytes, content_type.as_deref())
+                })
+                .await
+                .map_err(|e| e.to_string())??;
+                files = Some(entries);
+            }
+            "name" | "route" | "project_type" | "mode" | "rewrite_assets" => {
+                let value = field.text().await.map_err(|e| e.to_string())?;
+                match name.as_str() {
+                    "name" => params.name = Some(value),
+                    "route" => params.route = Some(value),
+                    "rewrite_assets" => params.rewrite_assets = value == "1" || value == "true",
+                    "mode" => {
+                        let mode = serde_json::from_value(serde_json::Value::String(value))
+                            .map_err(|_| "Invalid mode, expected 'spa', 'static' or '404.html'")?;
+                        params.mode = Some(mode);
+                    }
+                    _ => params.project_type = Some(value),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let files = files.ok_or("Missing 'archive' field")?;
+    Ok(params.into_upload(files))
+}
+
+/// 读取站点上传的请求体
+///
+/// 接受三种请求体：
+/// - `application/json`：文件内联在 JSON 中（二进制文件用 base64）
+/// - `application/gzip` / `application/zip`：构建产物的归档包，站点参数放在查询参数中
+/// - `multipart/form-data`：`archive` 字段为归档包，其他字段为站点参数
+async fn read_site_upload(
+    state: &Arc<AppState>,
+    params: SiteUploadParams,
+    request: axum::extract::Request,
+) -> Result<SiteUpload, String> {
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    if content_type.starts_with("application/json") {
+        let Json(req) = Json::<CreateSiteRequest>::from_request(request, state)
+            .await
+            .map_err(|e| e.body_text())?;
+        req.into_upload()
+    } else if content_type.starts_with("multipart/form-data") {
+        let multipart = Multipart::from_request(request, state)
+            .await
+            .map_err(|e| e.body_text())?;
+        read_multipart_upload(multipart).await
+    } else {
+        let bytes = Bytes::from_request(request, state)
+            .await
+            .map_err(|e| e.body_text())?;
+        let files = tokio::task::spawn_blocking(move || extract_archive(&bytes, Some(&content_type)))
+            .await
+            .map_err(|e| e.to_string())??;
+        Ok(params.into_upload(files))
+    }
+}
+
+/// 项目内站点的挂载前缀（站点路由之前的部分）
+fn site_asset_prefix(project: &Project) -> String {
+    format!("{}{}", site::SITE_PREFIX, project.public_path(""))
 }
 
 /// 创建/部署站点
 async fn create_site(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<CreateSiteRequest>,
+    ProjectScope(project): ProjectScope,
+    Query(params): Query<SiteUploadParams>,
+    request: axum::extract::Request,
 ) -> Result<Json<ApiResponse<CreateSiteResponse>>, (StatusCode, Json<ApiResponse<()>>)> {
-    let files_count = req.files.len();
+    let mut upload = read_site_upload(&state, params, request)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, ApiResponse::err(e)))?;
+    upload.asset_prefix = upload.asset_prefix.map(|_| site_asset_prefix(&project));
+    let files_count = upload.files.len();
     
-    match state.sites.create(req).await {
+    match state.sites.create(&project.id, upload).await {
         Ok(site) => {
             tracing::info!("✅ Created site: {} ({}) with {} files", site.name, site.id, files_count);
-            
-            // 构建访问 URL
-            let addr = std::env::var("NEXO_ADDR").unwrap_or_else(|_| "localhost:3000".to_string());
-            let host = if addr.starts_with("0.0.0.0") {
-                format!("localhost:{}", addr.split(':').last().unwrap_or("3000"))
-            } else {
-                addr
-            };
-            
-            Ok(ApiResponse::ok(CreateSiteResponse {
-                id: site.id,
-                name: site.name,
-                route: site.route.clone(),
-                url: format!("http://{}/site{}", host, site.route),
-                files_count,
-            }))
+            Ok(ApiResponse::ok(CreateSiteResponse::new(&project, site)))
+        }
+        Err(e) => Err((StatusCode::BAD_REQUEST, ApiResponse::err(e))),
+    }
+}
+
+/// 重新部署站点：新的文件集合整体替换当前部署，旧部署保留用于回滚和预览
+async fn update_site(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
+    Query(params): Query<SiteUploadParams>,
+    request: axum::extract::Request,
+) -> Result<Json<ApiResponse<CreateSiteResponse>>, (StatusCode, Json<ApiResponse<()>>)> {
+    if state.sites.get(&id).await.filter(|s| s.project_id == project.id).is_none() {
+        return Err((StatusCode::NOT_FOUND, ApiResponse::err("Site not found")));
+    }
+
+    let mut upload = read_site_upload(&state, params, request)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, ApiResponse::err(e)))?;
+    upload.asset_prefix = upload.asset_prefix.map(|_| site_asset_prefix(&project));
+
+    match state.sites.update(&project.id, &id, upload).await {
+        Ok(site) => {
+            tracing::info!("🔄 Redeployed site: {} ({}) as {}", site.name, site.id, site.active_deployment);
+            Ok(ApiResponse::ok(CreateSiteResponse::new(&project, site)))
+        }
+        Err(e) => Err((StatusCode::BAD_REQUEST, ApiResponse::err(e))),
+    }
+}
+
+/// 列出站点的部署历史
+async fn list_site_deployments(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<Json<ApiResponse<Vec<DeploymentInfo>>>, StatusCode> {
+    match state.sites.get(&id).await.filter(|s| s.project_id == project.id) {
+        Some(site) => Ok(ApiResponse::ok(SiteStore::deployments(&site))),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// 站点域名请求
+#[derive(Deserialize)]
+struct SiteDomainsRequest {
+    domains: Vec<String>,
+}
+
+/// 设置站点绑定的域名，这些域名的请求直接由站点处理
+async fn set_site_domains(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
+    Json(req): Json<SiteDomainsRequest>,
+) -> Result<Json<ApiResponse<Site>>, (StatusCode, Json<ApiResponse<()>>)> {
+    match state.sites.set_domains(&project, &id, req.domains).await {
+        Ok(site) => {
+            tracing::info!("🌐 Site {} domains: {:?}", site.id, site.domains);
+            Ok(ApiResponse::ok(site))
+        }
+        Err(e) if e == "Site not found" => Err((StatusCode::NOT_FOUND, ApiResponse::err(e))),
+        Err(e) if e.contains("is not bound") => Err((StatusCode::BAD_REQUEST, ApiResponse::err(e))),
+        Err(e) => Err((StatusCode::CONFLICT, ApiResponse::err(e))),
+    }
+}
+
+/// 设置站点的中间件函数，函数必须属于同一项目。
+/// 函数中的 `ctx.next()` / `env.ASSETS.fetch()` 只返回指令，拿不到静态文件的内容（见 edge 模块）
+async fn set_site_middleware(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
+    Json(middleware): Json<SiteMiddleware>,
+) -> Result<Json<ApiResponse<Site>>, (StatusCode, Json<ApiResponse<()>>)> {
+    if scoped_function(&state, &project, &middleware.function_id).await.is_none() {
+        return Err((StatusCode::BAD_REQUEST, ApiResponse::err("Function not found")));
+    }
+
+    match state.sites.set_middleware(&project.id, &id, Some(middleware)).await {
+        Ok(site) => {
+            tracing::info!("🧩 Site {} middleware: {:?}", site.id, site.middleware);
+            Ok(ApiResponse::ok(site))
+        }
+        Err(e) if e == "Site not found" => Err((StatusCode::NOT_FOUND, ApiResponse::err(e))),
+        Err(e) => Err((StatusCode::BAD_REQUEST, ApiResponse::err(e))),
+    }
+}
+
+/// 移除站点的中间件函数
+async fn delete_site_middleware(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<Json<ApiResponse<Site>>, (StatusCode, Json<ApiResponse<()>>)> {
+    match state.sites.set_middleware(&project.id, &id, None).await {
+        Ok(site) => Ok(ApiResponse::ok(site)),
+        Err(e) => Err((StatusCode::NOT_FOUND, ApiResponse::err(e))),
+    }
+}
+
+/// 回滚请求
+#[derive(Deserialize)]
+struct RollbackRequest {
+    deployment_id: String,
+}
+
+/// 回滚到历史部署
+async fn rollback_site(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
+    Json(req): Json<RollbackRequest>,
+) -> Result<Json<ApiResponse<CreateSiteResponse>>, (StatusCode, Json<ApiResponse<()>>)> {
+    match state.sites.rollback(&project.id, &id, &req.deployment_id).await {
+        Ok(site) => {
+            tracing::info!("⏪ Rolled back site: {} ({}) to {}", site.name, site.id, site.active_deployment);
+            Ok(ApiResponse::ok(CreateSiteResponse::new(&project, site)))
+        }
+        Err(e) => Err((StatusCode::NOT_FOUND, ApiResponse::err(e))),
+    }
+}
+
+/// 增量部署路径参数
+#[derive(Deserialize)]
+struct DeployBlobPath {
+    id: String,
+    hash: String,
+}
+
+/// 开始增量部署：提交文件清单，返回需要上传的内容哈希
+async fn begin_site_deploy(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Json(manifest): Json<SiteManifest>,
+) -> Result<Json<ApiResponse<DeployPlan>>, (StatusCode, Json<ApiResponse<()>>)> {
+    match state.sites.begin_deploy(&project.id, manifest).await {
+        Ok(plan) => Ok(ApiResponse::ok(plan)),
+        Err(e) => Err((StatusCode::BAD_REQUEST, ApiResponse::err(e))),
+    }
+}
+
+/// 上传增量部署缺少的内容
+async fn upload_site_blob(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Path(DeployBlobPath { id, hash }): Path<DeployBlobPath>,
+    body: Bytes,
+) -> Result<Json<ApiResponse<()>>, (StatusCode, Json<ApiResponse<()>>)> {
+    match state.sites.upload_blob(&project.id, &id, &hash, &body).await {
+        Ok(()) => Ok(ApiResponse::ok(())),
+        Err(e) => Err((StatusCode::BAD_REQUEST, ApiResponse::err(e))),
+    }
+}
+
+/// 完成增量部署
+async fn finalize_site_deploy(
+    State(state): State<Arc<AppState>>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
+) -> Result<Json<ApiResponse<CreateSiteResponse>>, (StatusCode, Json<ApiResponse<()>>)> {
+    match state.sites.finalize_deploy(&project.id, &id).await {
+        Ok(site) => {
+            tracing::info!("✅ Deployed site: {} ({}) with {} files", site.name, site.id, site.files.len());
+            Ok(ApiResponse::ok(CreateSiteResponse::new(&project, site)))
         }
         Err(e) => Err((StatusCode::BAD_REQUEST, ApiResponse::err(e))),
     }
@@ -391,9 +1405,10 @@ async fn create_site(
 /// 获取站点详情
 async fn get_site(
     State(state): State<Arc<AppState>>,
-    Path(id): Path<String>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
 ) -> Result<Json<ApiResponse<Site>>, StatusCode> {
-    match state.sites.get(&id).await {
+    match state.sites.get(&id).await.filter(|s| s.project_id == project.id) {
         Some(site) => Ok(ApiResponse::ok(site)),
         None => Err(StatusCode::NOT_FOUND),
     }
@@ -402,8 +1417,13 @@ async fn get_site(
 /// 删除站点
 async fn delete_site(
     State(state): State<Arc<AppState>>,
-    Path(id): Path<String>,
+    ProjectScope(project): ProjectScope,
+    Path(IdPath { id }): Path<IdPath>,
 ) -> Result<Json<ApiResponse<()>>, (StatusCode, Json<ApiResponse<()>>)> {
+    if state.sites.get(&id).await.filter(|s| s.project_id == project.id).is_none() {
+        return Err((StatusCode::NOT_FOUND, ApiResponse::err("Site not found")));
+    }
+
     match state.sites.delete(&id).await {
         Ok(_) => {
             tracing::info!("🗑️ Deleted site: {}", id);
@@ -413,60 +1433,446 @@ async fn delete_site(
     }
 }
 
+/// 从 BlobStore 流式返回站点文件
+///
+/// 按 `Accept-Encoding` 选择预压缩的版本，处理条件请求（304）和单个 Range（206），
+/// HEAD 请求只返回响应头。
+async fn site_file_response(
+    state: &AppState,
+    site_id: &str,
+    file: &SiteFile,
+    last_modified: DateTime<Utc>,
+    method: &Method,
+    headers: &HeaderMap,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let header_str = |name| headers.get(name).and_then(|v: &header::HeaderValue| v.to_str().ok());
+
+    // Range 请求使用未压缩的内容，避免按压缩后的字节偏移切片
+    let range = header_str(header::RANGE).filter(|_| *method == Method::GET);
+    let variant = match range {
+        Some(_) => None,
+        None => file.negotiate(header_str(header::ACCEPT_ENCODING).unwrap_or_default()),
+    };
+    let (path, hash, size) = match variant {
+        Some(variant) => (state.sites.variant_path(variant), &variant.hash, variant.size),
+        None => (state.sites.blob_path(file), &file.hash, file.size),
+    };
+    let etag = cache::etag(hash);
+
+    let mut builder = axum::response::Response::builder()
+        .header(header::CONTENT_TYPE, &file.mime_type)
+        .header(header::ETAG, &etag)
+        .header(header::LAST_MODIFIED, cache::http_date(last_modified))
+        .header(header::CACHE_CONTROL, cache::cache_control(&file.path, &file.mime_type))
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header("X-Site-Id", site_id);
+    if let Some(variant) = variant {
+        builder = builder.header(header::CONTENT_ENCODING, variant.encoding.as_str());
+    }
+    // 有压缩版本时响应随 Accept-Encoding 变化
+    if !file.variants.is_empty() {
+        builder = builder.header(header::VARY, "Accept-Encoding");
+    }
+
+    // 条件请求：有 If-None-Match 时忽略 If-Modified-Since
+    let not_modified = match header_str(header::IF_NONE_MATCH) {
+        Some(if_none_match) => cache::none_match(if_none_match, &etag),
+        None => header_str(header::IF_MODIFIED_SINCE)
+            .is_some_and(|since| cache::not_modified_since(since, last_modified)),
+    };
+    if not_modified {
+        return builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(axum::body::Body::empty())
+            .unwrap()
+            .into_response();
+    }
+
+    // If-Range 与当前 ETag 不一致时返回完整内容
+    let range = range
+        .filter(|_| header_str(header::IF_RANGE).is_none_or(|if_range| if_range == etag))
+        .map(|range| cache::parse_range(range, size))
+        .unwrap_or(cache::RangeRequest::Ignore);
+    let (status, offset, length) = match range {
+        cache::RangeRequest::Satisfiable { start, end } => {
+            builder = builder.header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, size));
+            (StatusCode::PARTIAL_CONTENT, start, end - start + 1)
+        }
+        cache::RangeRequest::Unsatisfiable => {
+            return builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+                .body(axum::body::Body::empty())
+                .unwrap()
+                .into_response();
+        }
+        cache::RangeRequest::Ignore => (StatusCode::OK, 0, size),
+    };
+    let builder = builder.status(status).header(header::CONTENT_LENGTH, length);
+
+    if *method == Method::HEAD {
+        return builder.body(axum::body::Body::empty()).unwrap().into_response();
+    }
+
+    let mut blob = match tokio::fs::File::open(&path).await {
+        Ok(blob) => blob,
+        Err(e) => {
+            tracing::error!("Missing blob {} for {}: {}", hash, file.path, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    if offset > 0 {
+        if let Err(e) = blob.seek(std::io::SeekFrom::Start(offset)).await {
+            tracing::error!("Failed to seek blob {}: {}", hash, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    builder
+        .body(axum::body::Body::from_stream(ReaderStream::new(blob.take(length))))
+        .unwrap()
+        .into_response()
+}
+
+/// 重定向规则的响应；站点内的目标加上站点根路径，原请求的查询参数在目标未指定时保留
+fn redirect_response(rule: &RedirectMatch, base: &str, query: Option<&str>) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    let mut location = if rule.to.starts_with('/') && !rule.to.starts_with("/fn/") {
+        format!("{}{}", base, rule.to)
+    } else {
+        rule.to.clone()
+    };
+    if let Some(query) = query.filter(|_| !location.contains('?')) {
+        location = format!("{}?{}", location, query);
+    }
+
+    let status = StatusCode::from_u16(rule.status).unwrap_or(StatusCode::MOVED_PERMANENTLY);
+    match header::HeaderValue::from_str(&location) {
+        Ok(location) => (status, [(header::LOCATION, location)]).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// 把站点请求重写给站点所属项目中的 `/fn/*` 函数，目标中的查询参数覆盖原请求的同名参数
+async fn rewrite_to_function(
+    state: &AppState,
+    site: &Site,
+    remote_addr: SocketAddr,
+    target: &str,
+    query: Option<&str>,
+    method: Method,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    let (path, target_query) = target.split_once('?').unwrap_or((target, ""));
+    let path = path.strip_prefix("/fn").unwrap_or(path).to_string();
+
+    let mut params = parse_query(query.unwrap_or_default());
+    params.extend(parse_query(target_query));
+
+    invoke_gateway(state, remote_addr, Some(&site.project_id), path, method, headers, params, None).await
+}
+
+/// 解析原始查询字符串
+fn parse_query(query: &str) -> HashMap<String, String> {
+    format!("/?{}", query)
+        .parse::<axum::http::Uri>()
+        .ok()
+        .and_then(|uri| Query::<HashMap<String, String>>::try_from_uri(&uri).ok())
+        .map(|Query(params)| params)
+        .unwrap_or_default()
+}
+
+/// 预览部署的首页
+async fn serve_preview_root(
+    State(state): State<Arc<AppState>>,
+    Path(IdPath { id }): Path<IdPath>,
+    method: Method,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    preview_response(&state, &id, "", &method, &headers).await
+}
+
+/// 预览部署的文件
+async fn serve_preview(
+    State(state): State<Arc<AppState>>,
+    Path((id, path)): Path<(String, String)>,
+    method: Method,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    preview_response(&state, &id, &path, &method, &headers).await
+}
+
+/// 按部署 ID 提供文件，不受站点当前生效部署的影响
+async fn preview_response(
+    state: &AppState,
+    deployment_id: &str,
+    path: &str,
+    method: &Method,
+    headers: &HeaderMap,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    match state.sites.get_deployment_file(deployment_id, path).await {
+        Some((site_id, created_at, file)) => {
+            let mut response =
+                site_file_response(state, &site_id, &file, created_at, method, headers).await;
+            response
+                .headers_mut()
+                .insert(header::CACHE_CONTROL, header::HeaderValue::from_static("no-cache"));
+            response
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// 站点请求
+struct SiteRequest {
+    remote_addr: SocketAddr,
+    query: Option<String>,
+    method: Method,
+    headers: HeaderMap,
+}
+
 /// 提供静态站点文件
 async fn serve_site(
     State(state): State<Arc<AppState>>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
     Path(path): Path<String>,
+    RawQuery(query): RawQuery,
+    method: Method,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    // 按 Host 和路由前缀确定项目
+    let host = headers.get(header::HOST).and_then(|h| h.to_str().ok());
+    let full_path = format!("/{}", path.trim_start_matches('/'));
+    let (project_id, path) = match state.projects.resolve(host, &full_path).await {
+        Some((project, path)) => (project.id, path),
+        None => (DEFAULT_PROJECT.to_string(), full_path.clone()),
+    };
+
+    // 解析路径：/site/{site_route}/{file_path}，路由为 `/` 的站点接收其他站点之外的路径
+    let Some((site, file_path)) = state.sites.resolve(&project_id, &path).await else {
+        return not_found_page();
+    };
+
+    // 站点根路径，规则中以 `/` 开头的目标相对于它
+    let request_path = format!("{}{}", site::SITE_PREFIX, full_path);
+    let base = request_path
+        .strip_suffix(file_path.as_str())
+        .unwrap_or(&request_path)
+        .trim_end_matches('/');
+
+    let request = SiteRequest { remote_addr, query, method, headers };
+    serve_site_file(&state, &site, &file_path, base, &request).await
+}
+
+/// 绑定了域名的站点：其他路由都不匹配的请求按 Host 查找站点，在根路径下提供
+async fn serve_host_site(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    uri: axum::http::Uri,
+    method: Method,
+    headers: HeaderMap,
 ) -> axum::response::Response {
     use axum::response::IntoResponse;
-    use axum::http::header;
-    
-    // 解析路径：/site/{site_route}/{file_path}
-    // path 格式: "1234567890/index.html" 或 "1234567890"
-    let path = path.trim_start_matches('/');
-    
-    // 尝试找到匹配的站点
-    let route = format!("/{}", path.split('/').next().unwrap_or(""));
-    
-    if let Some(site) = state.sites.get_by_route(&route).await {
-        // 获取文件路径（移除站点路由部分）
-        let file_path = path.strip_prefix(route.trim_start_matches('/'))
-            .unwrap_or("")
-            .trim_start_matches('/');
-        
-        // 记录访问
-        state.sites.record_visit(&site.id).await;
-        
-        if let Some(file) = state.sites.get_file(&site.id, file_path).await {
-            return axum::response::Response::builder()
-                .status(StatusCode::OK)
-                .header(header::CONTENT_TYPE, &file.mime_type)
-                .header(header::CACHE_CONTROL, "public, max-age=31536000")
-                .header("X-Site-Id", &site.id)
-                .body(axum::body::Body::from(file.content))
-                .unwrap()
-                .into_response();
+
+    let host = headers.get(header::HOST).and_then(|h| h.to_str().ok());
+    let site = match host {
+        Some(host) => state.sites.get_by_domain(host).await,
+        None => None,
+    };
+    let Some(site) = site else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    // 项目解绑域名后，站点上残留的绑定不再生效
+    let owned = match (state.projects.get(&site.project_id).await, host) {
+        (Some(project), Some(host)) => project.domains.contains(&ProjectStore::host_name(host)),
+        _ => false,
+    };
+    if !owned {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if method != Method::GET && method != Method::HEAD {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+
+    let file_path = uri.path().trim_start_matches('/').to_string();
+    let query = uri.query().map(str::to_string);
+    let request = SiteRequest { remote_addr, query, method, headers };
+    serve_site_file(&state, &site, &file_path, "", &request).await
+}
+
+/// 按站点规则提供站点内的文件，`base` 为站点根路径
+async fn serve_site_file(
+    state: &AppState,
+    site: &Site,
+    file_path: &str,
+    base: &str,
+    request: &SiteRequest,
+) -> axum::response::Response {
+    let SiteRequest { remote_addr, query, method, headers } = request;
+
+    // 记录访问
+    state.sites.record_visit(&site.id).await;
+
+    // 中间件函数：直接响应，或交回站点并改写路径、追加响应头
+    let mut next = edge::Next::default();
+    if let Some(middleware) = site.middleware.as_ref().filter(|m| m.matches(&format!("/{}", file_path))) {
+        match run_site_middleware(state, site, middleware, file_path, base, request).await {
+            Ok(directive) => next = directive,
+            Err(response) => return response,
         }
     }
-    
-    // 如果通过路由没找到，尝试在所有站点中搜索该文件
-    // 这处理了构建工具生成的绝对路径资源引用（如 /assets/index.js）
-    let sites = state.sites.list().await;
-    for site in sites {
-        if let Some(file) = state.sites.get_file(&site.id, path).await {
-            state.sites.record_visit(&site.id).await;
-            return axum::response::Response::builder()
-                .status(StatusCode::OK)
-                .header(header::CONTENT_TYPE, &file.mime_type)
-                .header(header::CACHE_CONTROL, "public, max-age=31536000")
-                .header("X-Site-Id", &site.id)
-                .body(axum::body::Body::from(file.content))
-                .unwrap()
-                .into_response();
+    let file_path = next.path.as_deref().unwrap_or(file_path);
+    let rule_path = format!("/{}", file_path);
+
+    // 重定向和重写规则默认只在文件不存在时生效，`!` 时总是生效
+    let exact = state.sites.get_exact_file(&site.id, file_path).await;
+    let rule = site.rules.redirect(&rule_path).filter(|r| r.force || exact.is_none());
+
+    let mut response = match rule {
+        Some(rule) if rule.is_redirect() => redirect_response(&rule, base, query.as_deref()),
+        Some(rule) if rule.to.starts_with("/fn/") => {
+            let (method, headers) = (method.clone(), headers.clone());
+            rewrite_to_function(state, site, *remote_addr, &rule.to, query.as_deref(), method, headers).await
+        }
+        Some(rule) => {
+            // 重写为站点内的其他文件；404 规则用指定页面作为错误页
+            let target = rule.to.split('?').next().unwrap_or("/");
+            match state.sites.get_file(&site.id, target).await {
+                Some(file) if rule.status == 404 => {
+                    not_found_response(state, site, Some(file), method, headers).await
+                }
+                Some(file) => {
+                    site_file_response(state, &site.id, &file, site.updated_at, method, headers).await
+                }
+                None => site_not_found(state, site, method, headers).await,
+            }
+        }
+        None => {
+            let file = match exact {
+                Some(file) => Some(file),
+                None => state.sites.get_file(&site.id, file_path).await,
+            };
+            match file {
+                Some(file) => {
+                    site_file_response(state, &site.id, &file, site.updated_at, method, headers).await
+                }
+                None => site_not_found(state, site, method, headers).await,
+            }
+        }
+    };
+
+    let middleware_headers = next.headers.iter().map(|(name, value)| (name.as_str(), value.as_str()));
+    for (name, value) in site.rules.headers_for(&rule_path).into_iter().chain(middleware_headers) {
+        if let (Ok(name), Ok(value)) = (
+            header::HeaderName::from_bytes(name.as_bytes()),
+            header::HeaderValue::from_str(value),
+        ) {
+            response.headers_mut().insert(name, value);
         }
     }
-    
-    // 404
+    response
+}
+
+/// 执行站点的中间件函数，返回 `Err` 时把其中的响应直接返回给客户端
+async fn run_site_middleware(
+    state: &AppState,
+    site: &Site,
+    middleware: &SiteMiddleware,
+    file_path: &str,
+    base: &str,
+    request: &SiteRequest,
+) -> Result<edge::Next, axum::response::Response> {
+    use axum::response::IntoResponse;
+
+    let error = |status: StatusCode, error: String| {
+        (status, Json(serde_json::json!({
+            "success": false,
+            "error": error
+        }))).into_response()
+    };
+
+    // 函数被删除或停用时不再放行，避免绕过认证
+    let function = state
+        .runtime
+        .functions
+        .get(&middleware.function_id)
+        .await
+        .filter(|f| f.project_id == site.project_id && f.status == FunctionStatus::Active);
+    let Some(function) = function else {
+        return Err(error(StatusCode::SERVICE_UNAVAILABLE, "Site middleware is unavailable".to_string()));
+    };
+
+    let function_request = FunctionRequest {
+        url: format!("{}/{}", base, file_path),
+        method: request.method.to_string(),
+        headers: request
+            .headers
+            .iter()
+            .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.to_string(), v.to_string())))
+            .collect(),
+        body: None,
+        path_params: HashMap::new(),
+        query_params: parse_query(request.query.as_deref().unwrap_or_default()),
+        env: HashMap::new(),
+        auth: None,
+        client_ip: Some(client_ip(&request.headers, request.remote_addr)),
+    };
+
+    let response = state.runtime.execute_function(&function, function_request, Priority::Public).await;
+    state.metrics.record_response(Some(&function.id), response.status);
+
+    match edge::next_directive(response.body.as_ref().filter(|_| response.status == 200)) {
+        Ok(Some(next)) => Ok(next),
+        Ok(None) => Err(function_response(response)),
+        Err(e) => {
+            tracing::warn!("Site {} middleware returned an invalid directive: {}", site.id, e);
+            Err(error(StatusCode::INTERNAL_SERVER_ERROR, e))
+        }
+    }
+}
+
+/// 站点内找不到文件：有 `404.html` 时用它作为响应体，否则返回默认 404 页面
+async fn site_not_found(
+    state: &AppState,
+    site: &Site,
+    method: &Method,
+    headers: &HeaderMap,
+) -> axum::response::Response {
+    let page = state.sites.get_not_found_page(&site.id).await;
+    not_found_response(state, site, page, method, headers).await
+}
+
+/// 以站点文件作为 404 响应体（不处理条件请求和 Range）
+async fn not_found_response(
+    state: &AppState,
+    site: &Site,
+    page: Option<SiteFile>,
+    method: &Method,
+    headers: &HeaderMap,
+) -> axum::response::Response {
+    let Some(page) = page else {
+        return not_found_page();
+    };
+
+    let mut accept = HeaderMap::new();
+    if let Some(value) = headers.get(header::ACCEPT_ENCODING) {
+        accept.insert(header::ACCEPT_ENCODING, value.clone());
+    }
+    let mut response = site_file_response(state, &site.id, &page, site.updated_at, method, &accept).await;
+    *response.status_mut() = StatusCode::NOT_FOUND;
+    response
+}
+
+/// 默认 404 页面
+fn not_found_page() -> axum::response::Response {
+    use axum::response::IntoResponse;
+
     axum::response::Response::builder()
         .status(StatusCode::NOT_FOUND)
         .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
@@ -0,0 +1,310 @@
+//! Site archives - 站点归档包解压
+//!
+//! 把上传的构建产物（tar.gz 或 zip）解压为站点文件列表。解压时拒绝绝对路径、
+//! `..`、符号链接和硬链接，并按实际读出的字节数检查文件数和大小，
+//! 不信任归档头中声明的大小。
+
+use flate2::read::GzDecoder;
+use std::io::{Cursor, Read};
+
+/// 归档格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// 按 Content-Type 判断格式
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+        match mime.as_str() {
+            "application/gzip" | "application/x-gzip" | "application/x-tar+gzip"
+            | "application/x-compressed-tar" => Some(Self::TarGz),
+            "application/zip" | "application/x-zip-compressed" => Some(Self::Zip),
+            _ => None,
+        }
+    }
+
+    /// 按文件头判断格式
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::TarGz)
+        } else if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// 解压限制
+#[derive(Debug, Clone)]
+pub struct ArchiveLimits {
+    /// 最多文件数
+    pub max_files: usize,
+    /// 单个文件的最大字节数
+    pub max_file_size: u64,
+    /// 解压后的最大总字节数
+    pub max_total_size: u64,
+}
+
+impl ArchiveLimits {
+    /// 从环境变量读取：NEXO_SITE_MAX_FILES（默认 10000）、
+    /// NEXO_SITE_MAX_FILE_MB（默认 50）、NEXO_SITE_MAX_TOTAL_MB（默认 200）
+    pub fn from_env() -> Self {
+        fn env(name: &str, default: u64) -> u64 {
+            std::env::var(name)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|&n| n > 0)
+                .unwrap_or(default)
+        }
+
+        Self {
+            max_files: env("NEXO_SITE_MAX_FILES", 10_000) as usize,
+            max_file_size: env("NEXO_SITE_MAX_FILE_MB", 50) * 1024 * 1024,
+            max_total_size: env("NEXO_SITE_MAX_TOTAL_MB", 200) * 1024 * 1024,
+        }
+    }
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
+
+/// 解压出的文件
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub content: Vec<u8>,
+}
+
+/// 解压归档
+///
+/// 所有文件都在同一个顶层目录下（如 `dist/`）时去掉这一层。
+pub fn extract(
+    bytes: &[u8],
+    format: ArchiveFormat,
+    limits: &ArchiveLimits,
+) -> Result<Vec<ArchiveEntry>, String> {
+    let mut collector = Collector {
+        limits,
+        entries: Vec::new(),
+        total: 0,
+    };
+
+    match format {
+        ArchiveFormat::TarGz => extract_tar_gz(bytes, &mut collector)?,
+        ArchiveFormat::Zip => extract_zip(bytes, &mut collector)?,
+    }
+
+    if collector.entries.is_empty() {
+        return Err("Archive contains no files".to_string());
+    }
+
+    let mut entries = collector.entries;
+    strip_common_root(&mut entries);
+    Ok(entries)
+}
+
+/// 按限制累计解压出的文件
+struct Collector<'a> {
+    limits: &'a ArchiveLimits,
+    entries: Vec<ArchiveEntry>,
+    total: u64,
+}
+
+impl Collector<'_> {
+    fn add(&mut self, raw_path: &str, reader: impl Read) -> Result<(), String> {
+        let path = sanitize_path(raw_path)?;
+
+        if self.entries.len() >= self.limits.max_files {
+            return Err(format!("Archive has more than {} files", self.limits.max_files));
+        }
+
+        // 多读一个字节以发现超限的文件
+        let mut content = Vec::new();
+        reader
+            .take(self.limits.max_file_size + 1)
+            .read_to_end(&mut content)
+            .map_err(|e| format!("Failed to read '{}': {}", path, e))?;
+        if content.len() as u64 > self.limits.max_file_size {
+            return Err(format!(
+                "File '{}' exceeds {}MB",
+                path,
+                self.limits.max_file_size / (1024 * 1024)
+            ));
+        }
+
+        self.total += content.len() as u64;
+        if self.total > self.limits.max_total_size {
+            return Err(format!(
+                "Archive exceeds {}MB when extracted",
+                self.limits.max_total_size / (1024 * 1024)
+            ));
+        }
+
+        self.entries.push(ArchiveEntry { path, content });
+        Ok(())
+    }
+}
+
+fn extract_tar_gz(bytes: &[u8], collector: &mut Collector) -> Result<(), String> {
+    let mut archive = tar::Archive::new(GzDecoder::new(bytes));
+    let entries = archive
+        .entries()
+        .map_err(|e| format!("Invalid tar.gz archive: {}", e))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Invalid tar.gz archive: {}", e))?;
+        let path = entry
+            .path()
+            .map_err(|e| format!("Invalid path in archive: {}", e))?
+            .to_string_lossy()
+            .to_string();
+
+        let kind = entry.header().entry_type();
+        if kind.is_symlink() || kind.is_hard_link() {
+            return Err(format!("Links are not allowed in site archives: '{}'", path));
+        }
+        // 目录和 pax/GNU 扩展头等非普通文件直接跳过
+        if !kind.is_file() {
+            continue;
+        }
+
+        collector.add(&path, entry)?;
+    }
+    Ok(())
+}
+
+fn extract_zip(bytes: &[u8], collector: &mut Collector) -> Result<(), String> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| format!("Invalid zip archive: {}", e))?;
+
+    for i in 0..archive.len() {
+        let file = archive
+            .by_index(i)
+            .map_err(|e| format!("Invalid zip archive: {}", e))?;
+        let path = file.name().to_string();
+
+        if file.is_symlink() {
+            return Err(format!("Links are not allowed in site archives: '{}'", path));
+        }
+        if file.is_dir() {
+            continue;
+        }
+
+        collector.add(&path, file)?;
+    }
+    Ok(())
+}
+
+/// 规范化站点内的文件路径，拒绝会逃出站点目录的路径
+pub fn sanitize_path(raw: &str) -> Result<String, String> {
+    let normalized = raw.replace('\\', "/");
+    if normalized.starts_with('/') || normalized.contains(':') {
+        return Err(format!("Absolute paths are not allowed in sites: '{}'", raw));
+    }
+
+    let mut parts = Vec::new();
+    for part in normalized.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => return Err(format!("Path traversal is not allowed in sites: '{}'", raw)),
+            part => parts.push(part),
+        }
+    }
+
+    if parts.is_empty() {
+        return Err(format!("Invalid site file path: '{}'", raw));
+    }
+    Ok(parts.join("/"))
+}
+
+/// 所有文件都在同一个顶层目录下时去掉这一层
+fn strip_common_root(entries: &mut [ArchiveEntry]) {
+    let Some((root, _)) = entries[0].path.split_once('/') else {
+        return;
+    };
+    let prefix = format!("{}/", root);
+    if !entries.iter().all(|e| e.path.starts_with(&prefix)) {
+        return;
+    }
+    for entry in entries.iter_mut() {
+        entry.path = entry.path[prefix.len()..].to_string();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    fn tar_gz(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), flate2::Compression::fast()));
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *content).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (path, content) in files {
+            writer.start_file(*path, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_extract_archives() {
+        let limits = ArchiveLimits {
+            max_files: 10,
+            max_file_size: 1024,
+            max_total_size: 4096,
+        };
+        let png: &[u8] = &[0x89, b'P', b'N', b'G', 0x00, 0xff];
+
+        let bytes = tar_gz(&[("dist/index.html", b"<h1>hi</h1>"), ("dist/img/logo.png", png)]);
+        assert_eq!(ArchiveFormat::detect(&bytes), Some(ArchiveFormat::TarGz));
+        let entries = extract(&bytes, ArchiveFormat::TarGz, &limits).unwrap();
+        let paths: Vec<_> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["index.html", "img/logo.png"]);
+        assert_eq!(entries[1].content, png);
+
+        let bytes = zip(&[("index.html", b"<h1>hi</h1>"), ("app.js", b"1")]);
+        assert_eq!(ArchiveFormat::detect(&bytes), Some(ArchiveFormat::Zip));
+        assert_eq!(extract(&bytes, ArchiveFormat::Zip, &limits).unwrap().len(), 2);
+
+        // 路径穿越
+        let bytes = zip(&[("../etc/passwd", b"x")]);
+        assert!(extract(&bytes, ArchiveFormat::Zip, &limits).unwrap_err().contains("traversal"));
+
+        // 超过单文件大小
+        let big = vec![0u8; 2048];
+        let bytes = tar_gz(&[("big.bin", &big)]);
+        assert!(extract(&bytes, ArchiveFormat::TarGz, &limits).unwrap_err().contains("exceeds"));
+    }
+
+    #[test]
+    fn test_reject_symlinks() {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), flate2::Compression::fast()));
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        builder.append_link(&mut header, "index.html", "/etc/passwd").unwrap();
+        let bytes = builder.into_inner().unwrap().finish().unwrap();
+
+        let err = extract(&bytes, ArchiveFormat::TarGz, &ArchiveLimits::default()).unwrap_err();
+        assert!(err.contains("Links are not allowed"));
+    }
+}
@@ -0,0 +1,454 @@
+//! Auth - 管理 API 的 API Key 认证
+//!
+//! 管理接口（`/api/*`、`/stats`）要求 `Authorization: Bearer <key>` 或 `X-API-Key` 头。
+//! Key 只保存 SHA-256 哈希，明文仅在创建时返回一次。每个 Key 有一个权限范围
+//! （read < deploy < admin），并可以限定在某个项目内。
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::api::AppState;
+use crate::project::DEFAULT_PROJECT;
+
+/// API Key 前缀，便于识别和扫描泄露
+const TOKEN_PREFIX: &str = "nexo_";
+
+/// 权限范围（按权限从低到高排序）
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// 只读
+    Read,
+    /// 部署和调用
+    Deploy,
+    /// 管理项目和 API Key
+    Admin,
+}
+
+/// 已保存的 API Key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    /// 明文的前几位，用于在列表中辨认
+    pub prefix: String,
+    /// 明文的 SHA-256（hex）
+    hash: String,
+    pub scope: Scope,
+    /// 限定的项目，None 表示所有项目
+    pub project_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// API Key 信息（不含哈希）
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scope: Scope,
+    pub project_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&ApiKey> for ApiKeyInfo {
+    fn from(key: &ApiKey) -> Self {
+        Self {
+            id: key.id.clone(),
+            name: key.name.clone(),
+            prefix: key.prefix.clone(),
+            scope: key.scope,
+            project_id: key.project_id.clone(),
+            created_at: key.created_at,
+        }
+    }
+}
+
+impl ApiKey {
+    /// 是否允许访问指定项目
+    pub fn allows_project(&self, project_id: &str) -> bool {
+        self.project_id.as_deref().is_none_or(|p| p == project_id)
+    }
+}
+
+/// 创建 API Key 请求
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub scope: Scope,
+    #[serde(default)]
+    pub project_id: Option<String>,
+}
+
+/// 创建 API Key 响应（明文只返回这一次）
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub info: ApiKeyInfo,
+    pub token: String,
+}
+
+/// 持久化数据
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct PersistedKeys {
+    keys: Vec<ApiKey>,
+}
+
+/// API Key 存储
+#[derive(Clone)]
+pub struct ApiKeyStore {
+    /// hash -> key
+    keys: Arc<RwLock<HashMap<String, ApiKey>>>,
+    /// 来自 NEXO_ADMIN_KEY 的管理员 Key 哈希（不落盘）
+    bootstrap_hash: Option<String>,
+    storage_path: PathBuf,
+}
+
+impl ApiKeyStore {
+    pub fn new() -> Self {
+        let storage_path = std::env::current_dir()
+            .unwrap_or_else(|_| PathBuf::from("."))
+            .join("data")
+            .join("api_keys.json");
+
+        println!("[ApiKeyStore] 数据存储路径: {}", storage_path.display());
+
+        let admin_key = std::env::var("NEXO_ADMIN_KEY").ok().filter(|k| !k.is_empty());
+        Self::with_storage_path(storage_path, admin_key.as_deref())
+    }
+
+    pub fn with_storage_path(storage_path: PathBuf, admin_key: Option<&str>) -> Self {
+        let keys = match Self::load_from_path(&storage_path) {
+            Ok(data) => data.keys.into_iter().map(|k| (k.hash.clone(), k)).collect(),
+            Err(e) => {
+                eprintln!("[ApiKeyStore] 加载失败: {}", e);
+                HashMap::new()
+            }
+        };
+
+        Self {
+            keys: Arc::new(RwLock::new(keys)),
+            bootstrap_hash: admin_key.map(Self::hash_token),
+            storage_path,
+        }
+    }
+
+    /// 从文件加载数据（静态方法）
+    fn load_from_path(path: &PathBuf) -> Result<PersistedKeys, String> {
+        if !path.exists() {
+            return Ok(PersistedKeys::default());
+        }
+
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read storage file: {}", e))?;
+
+        serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse storage file: {}", e))
+    }
+
+    async fn save(&self) -> Result<(), String> {
+        let data = PersistedKeys {
+            keys: self.keys.read().await.values().cloned().collect(),
+        };
+
+        if let Some(parent) = self.storage_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create storage directory: {}", e))?;
+        }
+
+        let content = serde_json::to_string_pretty(&data)
+            .map_err(|e| format!("Failed to serialize data: {}", e))?;
+
+        std::fs::write(&self.storage_path, content)
+            .map_err(|e| format!("Failed to write storage file: {}", e))?;
+
+        Ok(())
+    }
+
+    fn hash_token(token: &str) -> String {
+        Sha256::digest(token.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn generate_token() -> String {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{}{}", TOKEN_PREFIX, hex)
+    }
+
+    /// 没有任何可用的管理员凭据时，生成一个管理员 Key 并打印一次
+    pub async fn ensure_bootstrap_key(&self) {
+        if self.bootstrap_hash.is_some() || !self.keys.read().await.is_empty() {
+            return;
+        }
+
+        match self
+            .create(CreateApiKeyRequest {
+                name: "bootstrap".to_string(),
+                scope: Scope::Admin,
+                project_id: None,
+            })
+            .await
+        {
+            Ok(created) => {
+                println!("[ApiKeyStore] 已生成管理员 API Key（只显示这一次）: {}", created.token);
+            }
+            Err(e) => eprintln!("[ApiKeyStore] 生成管理员 API Key 失败: {}", e),
+        }
+    }
+
+    /// 创建 API Key
+    pub async fn create(&self, req: CreateApiKeyRequest) -> Result<CreatedApiKey, String> {
+        if req.name.is_empty() {
+            return Err("API key name cannot be empty".to_string());
+        }
+
+        let token = Self::generate_token();
+        let key = ApiKey {
+            id: Uuid::new_v4().to_string(),
+            name: req.name,
+            prefix: token.chars().take(TOKEN_PREFIX.len() + 6).collect(),
+            hash: Self::hash_token(&token),
+            scope: req.scope,
+            project_id: req.project_id,
+            created_at: Utc::now(),
+        };
+
+        self.keys.write().await.insert(key.hash.clone(), key.clone());
+        self.save().await?;
+
+        Ok(CreatedApiKey {
+            info: ApiKeyInfo::from(&key),
+            token,
+        })
+    }
+
+    /// 列出 API Key
+    pub async fn list(&self) -> Vec<ApiKeyInfo> {
+        let mut keys: Vec<ApiKeyInfo> = self.keys.read().await.values().map(ApiKeyInfo::from).collect();
+        keys.sort_by_key(|k| k.created_at);
+        keys
+    }
+
+    /// 删除 API Key
+    pub async fn delete(&self, id: &str) -> Result<(), String> {
+        let mut keys = self.keys.write().await;
+        let hash = keys
+            .values()
+            .find(|k| k.id == id)
+            .map(|k| k.hash.clone())
+            .ok_or("API key not found")?;
+        keys.remove(&hash);
+
+        drop(keys);
+        self.save().await
+    }
+
+    /// 校验明文 Key
+    pub async fn authenticate(&self, token: &str) -> Option<ApiKey> {
+        let hash = Self::hash_token(token);
+
+        if self.bootstrap_hash.as_deref() == Some(hash.as_str()) {
+            return Some(ApiKey {
+                id: "env".to_string(),
+                name: "NEXO_ADMIN_KEY".to_string(),
+                prefix: String::new(),
+                hash,
+                scope: Scope::Admin,
+                project_id: None,
+                created_at: Utc::now(),
+            });
+        }
+
+        self.keys.read().await.get(&hash).cloned()
+    }
+}
+
+impl Default for ApiKeyStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 从请求头中取出 API Key（Bearer 或 X-API-Key）
+pub fn extract_token(headers: &HeaderMap) -> Option<&str> {
+    if let Some(bearer) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    {
+        return Some(bearer.trim());
+    }
+
+    headers
+        .get("x-api-key")
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+}
+
+/// 交给函数的请求头：去掉携带 API Key 的 `Authorization` 和 `X-API-Key`，
+/// 函数代码拿不到调用方的管理密钥
+pub fn function_headers(headers: &HeaderMap) -> HashMap<String, String> {
+    headers
+        .iter()
+        .filter(|(name, _)| *name != header::AUTHORIZATION && name.as_str() != "x-api-key")
+        .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.to_string(), v.to_string())))
+        .collect()
+}
+
+/// 请求所需的权限范围
+fn required_scope(method: &Method, path: &str) -> Scope {
+    let is_project_admin = path
+        .strip_prefix("/api/projects")
+        .is_some_and(|rest| rest.trim_matches('/').split('/').count() <= 1 && method != Method::GET);
+
+    if path.starts_with("/api/keys") || is_project_admin {
+        Scope::Admin
+    } else if method == Method::GET || method == Method::HEAD {
+        Scope::Read
+    } else {
+        Scope::Deploy
+    }
+}
+
+/// 请求作用的项目；None 表示跨项目的接口（项目列表、Key 管理、统计）
+fn request_project(path: &str) -> Option<String> {
+    if let Some(rest) = path.strip_prefix("/api/projects/") {
+        return rest.split('/').next().filter(|p| !p.is_empty()).map(String::from);
+    }
+    if path.starts_with("/api/functions") || path.starts_with("/api/sites") {
+        return Some(DEFAULT_PROJECT.to_string());
+    }
+    None
+}
+
+fn auth_error(status: StatusCode, message: &str) -> Response {
+    let mut response = (
+        status,
+        Json(serde_json::json!({
+            "success": false,
+            "error": message
+        })),
+    )
+        .into_response();
+
+    if status == StatusCode::UNAUTHORIZED {
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Bearer"));
+    }
+    response
+}
+
+/// 管理 API 认证中间件
+pub async fn require_api_key(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    // CORS 预检请求不带凭据
+    if req.method() == Method::OPTIONS {
+        return next.run(req).await;
+    }
+
+    let Some(token) = extract_token(req.headers()) else {
+        return auth_error(StatusCode::UNAUTHORIZED, "Missing API key");
+    };
+    let Some(key) = state.api_keys.authenticate(token).await else {
+        return auth_error(StatusCode::UNAUTHORIZED, "Invalid API key");
+    };
+
+    let path = req.uri().path();
+    if key.scope < required_scope(req.method(), path) {
+        return auth_error(StatusCode::FORBIDDEN, "API key scope is insufficient");
+    }
+
+    let allowed = match request_project(path) {
+        Some(project_id) => key.allows_project(&project_id),
+        None => key.project_id.is_none(),
+    };
+    if !allowed {
+        return auth_error(StatusCode::FORBIDDEN, "API key is not valid for this project");
+    }
+
+    req.extensions_mut().insert(key);
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(admin_key: Option<&str>) -> ApiKeyStore {
+        let path = std::env::temp_dir().join(format!("nexo-keys-{}.json", Uuid::new_v4()));
+        ApiKeyStore::with_storage_path(path, admin_key)
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_hashed() {
+        let store = temp_store(None);
+        let created = store
+            .create(CreateApiKeyRequest {
+                name: "ci".to_string(),
+                scope: Scope::Deploy,
+                project_id: Some("acme".to_string()),
+            })
+            .await
+            .unwrap();
+
+        let key = store.authenticate(&created.token).await.unwrap();
+        assert_eq!(key.scope, Scope::Deploy);
+        assert!(key.allows_project("acme"));
+        assert!(!key.allows_project(DEFAULT_PROJECT));
+        assert!(store.authenticate("nexo_wrong").await.is_none());
+
+        let content = std::fs::read_to_string(&store.storage_path).unwrap();
+        assert!(!content.contains(&created.token));
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_admin_key() {
+        let store = temp_store(Some("admin-secret"));
+        let key = store.authenticate("admin-secret").await.unwrap();
+        assert_eq!(key.scope, Scope::Admin);
+    }
+
+    #[test]
+    fn test_function_headers_drop_keys() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer nexo_admin_secret".parse().unwrap());
+        headers.insert("X-API-Key", "nexo_deploy_secret".parse().unwrap());
+        headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+
+        let forwarded = function_headers(&headers);
+        assert_eq!(forwarded.len(), 1);
+        assert_eq!(forwarded["content-type"], "application/json");
+        assert!(!forwarded.values().any(|v| v.contains("secret")));
+    }
+
+    #[test]
+    fn test_required_scope() {
+        assert_eq!(required_scope(&Method::GET, "/api/functions"), Scope::Read);
+        assert_eq!(required_scope(&Method::POST, "/api/functions"), Scope::Deploy);
+        assert_eq!(required_scope(&Method::POST, "/api/projects/acme/functions"), Scope::Deploy);
+        assert_eq!(required_scope(&Method::POST, "/api/projects"), Scope::Admin);
+        assert_eq!(required_scope(&Method::DELETE, "/api/projects/acme"), Scope::Admin);
+        assert_eq!(required_scope(&Method::GET, "/api/keys"), Scope::Admin);
+    }
+}
@@ -0,0 +1,108 @@
+//! Blob storage - 内容寻址的文件存储
+//!
+//! 文件按内容的 SHA-256 存放在 `data/blobs/{前两位}/{哈希}`，相同内容只存一份，
+//! 在站点之间和多次部署之间共享。元数据（路径、MIME 类型）只引用哈希。
+
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// 内容寻址存储
+#[derive(Debug, Clone)]
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+impl BlobStore {
+    pub fn with_storage_path(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// 内容的哈希（小写十六进制 SHA-256）
+    pub fn hash(content: &[u8]) -> String {
+        Sha256::digest(content)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// 哈希对应的文件路径
+    pub fn path(&self, hash: &str) -> PathBuf {
+        let prefix = hash.get(..2).unwrap_or("00");
+        self.dir.join(prefix).join(hash)
+    }
+
+    /// 写入内容，返回哈希；内容已存在时不重复写入
+    pub fn put(&self, content: &[u8]) -> Result<String, String> {
+        let hash = Self::hash(content);
+        let path = self.path(&hash);
+        if path.exists() {
+            return Ok(hash);
+        }
+
+        let parent = path.parent().unwrap_or(&self.dir);
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create blob directory: {}", e))?;
+
+        // 先写临时文件再重命名，读者不会看到写了一半的文件
+        let tmp = parent.join(format!(".{}.{}", hash, uuid::Uuid::new_v4()));
+        std::fs::write(&tmp, content).map_err(|e| format!("Failed to write blob: {}", e))?;
+        std::fs::rename(&tmp, &path).map_err(|e| {
+            let _ = std::fs::remove_file(&tmp);
+            format!("Failed to store blob: {}", e)
+        })?;
+
+        Ok(hash)
+    }
+
+    /// 删除不在 `live` 中的内容，返回删除的数量
+    pub fn gc(&self, live: &HashSet<String>) -> usize {
+        let Ok(prefixes) = std::fs::read_dir(&self.dir) else {
+            return 0;
+        };
+
+        let mut removed = 0;
+        for prefix in prefixes.flatten() {
+            let Ok(entries) = std::fs::read_dir(prefix.path()) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                // 跳过正在写入的临时文件
+                if name.starts_with('.') || live.contains(&name) {
+                    continue;
+                }
+                match std::fs::remove_file(entry.path()) {
+                    Ok(()) => removed += 1,
+                    Err(e) => eprintln!("[BlobStore] 删除失败: {}", e),
+                }
+            }
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_put_dedup_and_gc() {
+        let dir = std::env::temp_dir().join(format!("nexo-blobs-{}", uuid::Uuid::new_v4()));
+        let store = BlobStore::with_storage_path(dir.clone());
+
+        // 二进制内容原样保存
+        let png = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0xff];
+        let hash = store.put(&png).unwrap();
+        assert_eq!(store.put(&png).unwrap(), hash);
+        assert_eq!(std::fs::read(store.path(&hash)).unwrap(), png);
+
+        let other = store.put(b"body {}").unwrap();
+        let live: HashSet<String> = [hash.clone()].into_iter().collect();
+        assert_eq!(store.gc(&live), 1);
+        assert!(!store.path(&other).exists());
+        assert!(store.path(&hash).exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
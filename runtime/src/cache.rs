@@ -0,0 +1,155 @@
+//! HTTP caching - 站点响应的缓存策略、条件请求和 Range
+//!
+//! ETag 直接使用内容哈希（强校验），HTML 每次都要重新验证，文件名带构建哈希的
+//! 资源可以永久缓存，其他文件短时间缓存。
+
+use chrono::{DateTime, Utc};
+
+/// 带哈希的资源：内容变化时文件名随之变化，可以永久缓存
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+/// HTML 入口：重新部署后浏览器要能立即看到新版本
+const REVALIDATE: &str = "no-cache";
+/// 其他文件
+const SHORT: &str = "public, max-age=3600";
+
+/// 根据文件路径和 MIME 类型选择 Cache-Control
+pub fn cache_control(path: &str, mime_type: &str) -> &'static str {
+    if mime_type.starts_with("text/html") {
+        REVALIDATE
+    } else if is_hashed_asset(path) {
+        IMMUTABLE
+    } else {
+        SHORT
+    }
+}
+
+/// 文件名是否带有构建工具生成的哈希，如 `index-D8b4DUFo.js`、`main.3f2a1b9c.css`
+fn is_hashed_asset(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let Some(hash) = stem.rsplit(['.', '-']).next().filter(|h| h.len() < stem.len()) else {
+        return false;
+    };
+    hash.len() >= 8
+        && hash.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && hash.chars().any(|c| c.is_ascii_digit())
+}
+
+/// 由内容哈希生成的强 ETag
+pub fn etag(hash: &str) -> String {
+    format!("\"{}\"", hash)
+}
+
+/// `If-None-Match` 是否命中（弱比较，`*` 匹配任意）
+pub fn none_match(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.trim_start_matches("W/") == etag
+    })
+}
+
+/// HTTP 日期（IMF-fixdate）
+pub fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// 解析 HTTP 日期
+pub fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value.trim())
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// `If-Modified-Since` 之后是否未修改（HTTP 日期精确到秒）
+pub fn not_modified_since(if_modified_since: &str, last_modified: DateTime<Utc>) -> bool {
+    parse_http_date(if_modified_since).is_some_and(|since| last_modified.timestamp() <= since.timestamp())
+}
+
+/// Range 请求的解析结果
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// 单个范围 [start, end]（含两端）
+    Satisfiable { start: u64, end: u64 },
+    /// 范围超出文件大小，返回 416
+    Unsatisfiable,
+    /// 无法解析或包含多个范围，忽略并返回完整内容
+    Ignore,
+}
+
+/// 解析 `Range: bytes=...`，只支持单个范围
+pub fn parse_range(value: &str, size: u64) -> RangeRequest {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Ignore;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Ignore;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Ignore;
+    };
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        // 最后 n 个字节
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return RangeRequest::Unsatisfiable,
+            Ok(n) => (size.saturating_sub(n), size.saturating_sub(1)),
+            Err(_) => return RangeRequest::Ignore,
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => (start, size.saturating_sub(1)),
+            Err(_) => return RangeRequest::Ignore,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(size.saturating_sub(1))),
+            _ => return RangeRequest::Ignore,
+        },
+    };
+
+    if size == 0 || start >= size {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Satisfiable { start, end }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_control() {
+        assert_eq!(cache_control("index.html", "text/html; charset=utf-8"), REVALIDATE);
+        assert_eq!(cache_control("assets/index-D8b4DUFo.js", "application/javascript"), IMMUTABLE);
+        assert_eq!(cache_control("static/main.3f2a1b9c.css", "text/css"), IMMUTABLE);
+        assert_eq!(cache_control("favicon.ico", "image/x-icon"), SHORT);
+        assert_eq!(cache_control("assets/background.png", "image/png"), SHORT);
+        assert_eq!(cache_control("12345678.png", "image/png"), SHORT);
+    }
+
+    #[test]
+    fn test_conditional_headers() {
+        let tag = etag("abc");
+        assert!(none_match("\"abc\"", &tag));
+        assert!(none_match("\"x\", W/\"abc\"", &tag));
+        assert!(none_match("*", &tag));
+        assert!(!none_match("\"abcd\"", &tag));
+
+        let time = DateTime::parse_from_rfc3339("2024-05-01T10:20:30.500Z").unwrap().with_timezone(&Utc);
+        let date = http_date(time);
+        assert_eq!(date, "Wed, 01 May 2024 10:20:30 GMT");
+        assert!(not_modified_since(&date, time));
+        assert!(!not_modified_since("Wed, 01 May 2024 10:20:29 GMT", time));
+        assert!(!not_modified_since("garbage", time));
+    }
+
+    #[test]
+    fn test_parse_range() {
+        use RangeRequest::*;
+        assert_eq!(parse_range("bytes=0-99", 1000), Satisfiable { start: 0, end: 99 });
+        assert_eq!(parse_range("bytes=900-", 1000), Satisfiable { start: 900, end: 999 });
+        assert_eq!(parse_range("bytes=-100", 1000), Satisfiable { start: 900, end: 999 });
+        assert_eq!(parse_range("bytes=500-5000", 1000), Satisfiable { start: 500, end: 999 });
+        assert_eq!(parse_range("bytes=1000-", 1000), Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), Ignore);
+        assert_eq!(parse_range("items=0-1", 1000), Ignore);
+    }
+}
@@ -0,0 +1,176 @@
+//! Compression - 内容压缩与编码协商
+//!
+//! 站点文件在部署时按 MIME 类型预先压缩为 gzip 和 brotli，只保留比原文件小的版本；
+//! 请求时按 `Accept-Encoding` 选择最合适的版本。压缩是 CPU 密集的同步操作，
+//! 调用方需要在阻塞线程上执行。
+
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// 小于这个大小的文件不压缩
+const MIN_COMPRESS_SIZE: usize = 256;
+/// brotli 压缩级别（11 的压缩率只高一点，耗时却多一个数量级）
+const BROTLI_QUALITY: u32 = 9;
+/// brotli 窗口大小（2^22 字节）
+const BROTLI_WINDOW_BITS: u32 = 22;
+
+/// 内容编码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[serde(rename = "br")]
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// 优先级从高到低
+    pub const ALL: [Encoding; 2] = [Encoding::Brotli, Encoding::Gzip];
+
+    /// `Content-Encoding` 中的名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// 压缩内容
+    pub fn compress(&self, content: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder
+                    .write_all(content)
+                    .and_then(|_| encoder.finish())
+                    .map_err(|e| format!("gzip failed: {}", e))
+            }
+            Encoding::Brotli => {
+                let mut output = Vec::new();
+                {
+                    let mut encoder = brotli::CompressorWriter::new(&mut output, 4096, BROTLI_QUALITY, BROTLI_WINDOW_BITS);
+                    encoder
+                        .write_all(content)
+                        .map_err(|e| format!("brotli failed: {}", e))?;
+                }
+                Ok(output)
+            }
+        }
+    }
+}
+
+/// MIME 类型是否值得压缩（图片、字体等已压缩的格式不再压缩）
+pub fn is_compressible(mime_type: &str) -> bool {
+    let mime = mime_type.split(';').next().unwrap_or("").trim();
+    mime.starts_with("text/")
+        || matches!(
+            mime,
+            "application/javascript"
+                | "application/json"
+                | "application/xml"
+                | "application/wasm"
+                | "image/svg+xml"
+                | "image/x-icon"
+                | "font/ttf"
+        )
+}
+
+/// 压缩后的版本（小于原文件时才保留）
+pub fn compress_variants(mime_type: &str, content: &[u8]) -> Vec<(Encoding, Vec<u8>)> {
+    if content.len() < MIN_COMPRESS_SIZE || !is_compressible(mime_type) {
+        return Vec::new();
+    }
+
+    Encoding::ALL
+        .iter()
+        .filter_map(|encoding| match encoding.compress(content) {
+            Ok(compressed) if compressed.len() < content.len() => Some((*encoding, compressed)),
+            Ok(_) => None,
+            Err(e) => {
+                eprintln!("[Compress] {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// 按 `Accept-Encoding` 从可用编码中选择一个
+///
+/// q 值相同时按 [`Encoding::ALL`] 的顺序优先；`q=0` 表示拒绝，`*` 匹配其他编码。
+pub fn negotiate(accept_encoding: &str, available: &[Encoding]) -> Option<Encoding> {
+    let mut wildcard = None;
+    let mut accepted = Vec::new();
+
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if name == "*" {
+            wildcard = Some(q);
+        } else if let Some(encoding) = Encoding::ALL.iter().find(|e| e.as_str() == name) {
+            accepted.push((*encoding, q));
+        }
+    }
+
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in Encoding::ALL.iter().filter(|e| available.contains(e)) {
+        let q = accepted
+            .iter()
+            .find(|(e, _)| e == encoding)
+            .map(|(_, q)| *q)
+            .or(wildcard)
+            .unwrap_or(0.0);
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((*encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_negotiate() {
+        let both = [Encoding::Gzip, Encoding::Brotli];
+        assert_eq!(negotiate("gzip, deflate, br", &both), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip", &both), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0.5, gzip", &both), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0, *", &both), Some(Encoding::Gzip));
+        assert_eq!(negotiate("identity", &both), None);
+        assert_eq!(negotiate("br", &[Encoding::Gzip]), None);
+        assert_eq!(negotiate("", &both), None);
+    }
+
+    #[test]
+    fn test_compress_variants() {
+        let css = "body { color: red; }\n".repeat(100);
+        let variants = compress_variants("text/css; charset=utf-8", css.as_bytes());
+        assert_eq!(variants.len(), 2);
+
+        let (_, gzip) = variants.iter().find(|(e, _)| *e == Encoding::Gzip).unwrap();
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(gzip.as_slice())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, css);
+
+        let (_, br) = variants.iter().find(|(e, _)| *e == Encoding::Brotli).unwrap();
+        let mut decoded = String::new();
+        brotli::Decompressor::new(br.as_slice(), 4096)
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, css);
+
+        // 已压缩的格式和小文件不压缩
+        assert!(compress_variants("image/png", css.as_bytes()).is_empty());
+        assert!(compress_variants("text/css", b"a{}").is_empty());
+    }
+}
@@ -0,0 +1,152 @@
+//! Edge middleware - 站点前的中间件函数
+//!
+//! 站点可以绑定一个同项目的函数，在匹配的路径上先于静态文件执行。函数可以直接
+//! 返回 `Response`（重定向、要求认证、完整响应），也可以调用 `ctx.next()` 或
+//! `env.ASSETS.fetch()` 交回站点继续提供静态文件，同时改写路径、追加响应头。
+//!
+//! Isolate 内的 handler 是同步执行的，`ctx.next()` 和 `env.ASSETS.fetch()` 不会真正
+//! 取回文件，而是返回一个指令，函数结束后由网关按指令提供文件。因此函数拿不到静态
+//! 文件的内容、状态码和响应头，不能读取或改写文件内容，必须把指令作为返回值；
+//! `ASSETS.fetch()` 只接受站点内路径，传入 request 时总是提供原路径：
+//!
+//! ```js
+//! function handler(request, ctx) {
+//!     if (!request.headers['cookie']) return ctx.next({ path: '/login.html' });
+//!     return ctx.next({ headers: { 'X-Frame-Options': 'DENY' } });
+//! }
+//! ```
+
+use crate::rules;
+use serde::{Deserialize, Serialize};
+
+/// 站点中间件配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SiteMiddleware {
+    /// 执行的函数
+    pub function_id: String,
+    /// 生效的路径模式（与 `_headers` 相同的语法），默认所有路径
+    #[serde(default = "default_paths")]
+    pub paths: Vec<String>,
+}
+
+fn default_paths() -> Vec<String> {
+    vec!["/*".to_string()]
+}
+
+impl SiteMiddleware {
+    /// 校验路径模式
+    pub fn validate(&self) -> Result<(), String> {
+        if self.function_id.trim().is_empty() {
+            return Err("function_id is required".to_string());
+        }
+        if self.paths.is_empty() {
+            return Err("paths must not be empty".to_string());
+        }
+        self.paths.iter().try_for_each(|p| rules::validate_pattern(p))
+    }
+
+    /// 站点内路径（以 `/` 开头）是否需要经过中间件
+    pub fn matches(&self, path: &str) -> bool {
+        self.paths.iter().any(|p| rules::match_path(p, path).is_some())
+    }
+}
+
+/// 中间件交回站点时的指令
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Next {
+    /// 改写后的站点内路径（不含开头的 `/`），`None` 时提供原路径
+    pub path: Option<String>,
+    /// 追加到站点响应的响应头
+    pub headers: Vec<(String, String)>,
+}
+
+/// 解析中间件函数的输出：调用了 `ctx.next()` 时返回指令，
+/// 返回 `Ok(None)` 表示函数的响应直接返回给客户端
+pub fn next_directive(output: Option<&serde_json::Value>) -> Result<Option<Next>, String> {
+    let Some(output) = output.filter(|o| o.get("__isNext").and_then(|v| v.as_bool()) == Some(true)) else {
+        return Ok(None);
+    };
+
+    let path = match output.get("path").and_then(|v| v.as_str()) {
+        Some(path) => Some(site_path(path)?),
+        None => None,
+    };
+
+    let mut headers = Vec::new();
+    if let Some(h) = output.get("headers").and_then(|v| v.as_object()) {
+        for (name, value) in h {
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            rules::validate_header(name, &value)?;
+            headers.push((name.clone(), value));
+        }
+    }
+
+    Ok(Some(Next { path, headers }))
+}
+
+/// 改写目标必须是站点内的路径（去掉查询参数，不允许 `..`）
+fn site_path(path: &str) -> Result<String, String> {
+    let path = path.split(['?', '#']).next().unwrap_or("");
+    let Some(relative) = path.strip_prefix('/') else {
+        return Err(format!("next path '{}' must start with '/'", path));
+    };
+    if relative.split('/').any(|segment| segment == "..") {
+        return Err(format!("next path '{}' must stay inside the site", path));
+    }
+    Ok(relative.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_middleware_paths() {
+        let all: SiteMiddleware = serde_json::from_value(json!({ "function_id": "f" })).unwrap();
+        assert!(all.validate().is_ok());
+        assert!(all.matches("/"));
+        assert!(all.matches("/assets/app.js"));
+
+        let admin = SiteMiddleware {
+            function_id: "f".to_string(),
+            paths: vec!["/admin/*".to_string(), "/account".to_string()],
+        };
+        assert!(admin.matches("/admin"));
+        assert!(admin.matches("/admin/users/1"));
+        assert!(admin.matches("/account/"));
+        assert!(!admin.matches("/about"));
+
+        let invalid = SiteMiddleware { function_id: "f".to_string(), paths: vec!["admin".to_string()] };
+        assert!(invalid.validate().is_err());
+        let empty = SiteMiddleware { function_id: "f".to_string(), paths: Vec::new() };
+        assert!(empty.validate().is_err());
+    }
+
+    #[test]
+    fn test_next_directive() {
+        let output = json!({ "__isNext": true, "path": "/login.html?from=x", "headers": { "X-Frame-Options": "DENY" } });
+        assert_eq!(
+            next_directive(Some(&output)).unwrap(),
+            Some(Next {
+                path: Some("login.html".to_string()),
+                headers: vec![("X-Frame-Options".to_string(), "DENY".to_string())],
+            })
+        );
+
+        // `env.ASSETS.fetch(request)` 提供原路径
+        let output = json!({ "__isNext": true, "path": null, "headers": {} });
+        assert_eq!(next_directive(Some(&output)).unwrap(), Some(Next::default()));
+
+        // 普通响应直接返回
+        assert_eq!(next_directive(Some(&json!({ "__isResponse": true }))).unwrap(), None);
+        assert_eq!(next_directive(None).unwrap(), None);
+
+        assert!(next_directive(Some(&json!({ "__isNext": true, "path": "/../secret" }))).is_err());
+        assert!(next_directive(Some(&json!({ "__isNext": true, "path": "login.html" }))).is_err());
+        assert!(next_directive(Some(&json!({ "__isNext": true, "headers": { "bad name": "x" } }))).is_err());
+    }
+}
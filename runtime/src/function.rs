@@ -3,26 +3,46 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::sync::RwLock;
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
+use crate::isolate::ScriptError;
+use crate::policy::AuthPolicy;
+use crate::ratelimit::RateLimit;
+use crate::project::default_project_id;
+
 /// 已部署的函数
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Function {
     /// 函数 ID
     pub id: String,
+    /// 所属项目
+    #[serde(default = "default_project_id")]
+    pub project_id: String,
     /// 函数名称
     pub name: String,
     /// 函数代码
     pub code: String,
+    /// 代码版本（每次更新代码加 1）
+    #[serde(default = "default_version")]
+    pub version: u32,
     /// 路由路径
     pub route: String,
     /// 允许的 HTTP 方法
     pub methods: Vec<String>,
     /// 环境变量
     pub env: HashMap<String, String>,
+    /// 网关调用是否需要管理 API Key（默认公开）
+    #[serde(default)]
+    pub require_api_key: bool,
+    /// 网关认证策略（可选）
+    #[serde(default)]
+    pub auth: Option<AuthPolicy>,
+    /// 按客户端的限流配置（可选）
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
     /// 资源限制
     pub limits: FunctionLimits,
     /// 创建时间
@@ -35,6 +55,9 @@ pub struct Function {
     pub invocations: u64,
     /// 最后调用时间
     pub last_invoked_at: Option<DateTime<Utc>>,
+    /// 最近一次部署失败的错误（部署成功后清除）
+    #[serde(default)]
+    pub deploy_error: Option<ScriptError>,
 }
 
 /// 函数资源限制
@@ -46,6 +69,9 @@ pub struct FunctionLimits {
     pub max_memory_mb: u32,
     /// 最大请求体大小（KB）
     pub max_request_body_kb: u32,
+    /// 最大并发执行数（None 表示只受全局并发限制）
+    #[serde(default)]
+    pub max_concurrency: Option<u32>,
 }
 
 impl Default for FunctionLimits {
@@ -54,6 +80,7 @@ impl Default for FunctionLimits {
             max_execution_time_ms: 50,
             max_memory_mb: 128,
             max_request_body_kb: 1024,
+            max_concurrency: None,
         }
     }
 }
@@ -79,13 +106,29 @@ pub struct CreateFunctionRequest {
     #[serde(default)]
     pub env: HashMap<String, String>,
     #[serde(default)]
+    pub require_api_key: bool,
+    #[serde(default)]
+    pub auth: Option<AuthPolicy>,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    #[serde(default)]
     pub limits: Option<FunctionLimits>,
+    /// 密钥（只写，加密保存在密钥存储中，不会出现在函数数据里）
+    #[serde(default, skip_serializing)]
+    pub secrets: HashMap<String, String>,
+    /// 部署时的冒烟测试请求（可选），handler 抛出异常则部署失败
+    #[serde(default)]
+    pub smoke_test: Option<serde_json::Value>,
 }
 
 fn default_methods() -> Vec<String> {
     vec!["GET".to_string(), "POST".to_string()]
 }
 
+fn default_version() -> u32 {
+    1
+}
+
 /// 更新函数请求
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UpdateFunctionRequest {
@@ -94,8 +137,26 @@ pub struct UpdateFunctionRequest {
     pub route: Option<String>,
     pub methods: Option<Vec<String>>,
     pub env: Option<HashMap<String, String>>,
+    pub require_api_key: Option<bool>,
+    /// 认证策略：省略表示不变，`null` 表示移除
+    #[serde(default, deserialize_with = "double_option")]
+    pub auth: Option<Option<AuthPolicy>>,
+    /// 限流配置：省略表示不变，`null` 表示移除
+    #[serde(default, deserialize_with = "double_option")]
+    pub rate_limit: Option<Option<RateLimit>>,
     pub limits: Option<FunctionLimits>,
     pub status: Option<FunctionStatus>,
+    #[serde(default)]
+    pub smoke_test: Option<serde_json::Value>,
+}
+
+/// 区分字段缺省（None）和显式的 null（Some(None)）
+fn double_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
 }
 
 /// 持久化数据结构
@@ -109,7 +170,7 @@ struct PersistedData {
 #[derive(Clone)]
 pub struct FunctionStore {
     functions: Arc<RwLock<HashMap<String, Function>>>,
-    routes: Arc<RwLock<HashMap<String, String>>>, // route -> function_id
+    routes: Arc<RwLock<HashMap<String, String>>>, // project_id:route -> function_id
     storage_path: PathBuf,
 }
 
@@ -129,13 +190,19 @@ impl FunctionStore {
     
     pub fn with_storage_path(storage_path: PathBuf) -> Self {
         // 先同步加载数据
-        let (functions_data, routes_data) = match Self::load_from_path(&storage_path) {
+        let functions_data = match Self::load_from_path(&storage_path) {
             Ok(data) => {
                 println!("[FunctionStore] 已加载 {} 个函数", data.functions.len());
-                (data.functions, data.routes)
+                data.functions
             }
-            Err(_) => (HashMap::new(), HashMap::new()),
+            Err(_) => HashMap::new(),
         };
+
+        // 从函数重建路由表（兼容没有项目前缀的旧数据）
+        let routes_data = functions_data
+            .values()
+            .map(|f| (Self::route_key(&f.project_id, &f.route), f.id.clone()))
+            .collect();
         
         Self {
             functions: Arc::new(RwLock::new(functions_data)),
@@ -182,13 +249,31 @@ impl FunctionStore {
         Ok(())
     }
 
-    /// 创建函数
-    pub async fn create(&self, req: CreateFunctionRequest) -> Result<Function, String> {
+    /// 数据目录
+    pub fn storage_dir(&self) -> &Path {
+        self.storage_path.parent().unwrap_or_else(|| Path::new("."))
+    }
+
+    /// 路由表的键：路由在项目内唯一
+    fn route_key(project_id: &str, route: &str) -> String {
+        format!("{}:{}", project_id, route)
+    }
+
+    /// 创建函数（状态为 Deploying，编译检查通过后由运行时置为 Active）
+    pub async fn create(&self, project_id: &str, mut req: CreateFunctionRequest) -> Result<Function, String> {
+        if let Some(policy) = req.auth.as_mut() {
+            policy.prepare()?;
+        }
+        if let Some(rate_limit) = &req.rate_limit {
+            rate_limit.validate()?;
+        }
+
         let mut functions = self.functions.write().await;
         let mut routes = self.routes.write().await;
 
         // 检查路由是否已存在
-        if routes.contains_key(&req.route) {
+        let route_key = Self::route_key(project_id, &req.route);
+        if routes.contains_key(&route_key) {
             return Err(format!("Route '{}' is already in use", req.route));
         }
 
@@ -207,20 +292,26 @@ impl FunctionStore {
 
         let function = Function {
             id: id.clone(),
+            project_id: project_id.to_string(),
             name: req.name,
             code: req.code,
+            version: 1,
             route: req.route.clone(),
             methods: req.methods,
             env: req.env,
+            require_api_key: req.require_api_key,
+            auth: req.auth,
+            rate_limit: req.rate_limit,
             limits: req.limits.unwrap_or_default(),
             created_at: now,
             updated_at: now,
-            status: FunctionStatus::Active,
+            status: FunctionStatus::Deploying,
             invocations: 0,
             last_invoked_at: None,
+            deploy_error: None,
         };
 
-        routes.insert(req.route, id.clone());
+        routes.insert(route_key, id.clone());
         functions.insert(id, function.clone());
         
         // 释放锁后保存
@@ -238,18 +329,18 @@ impl FunctionStore {
         self.functions.read().await.get(id).cloned()
     }
 
-    /// 通过路由获取函数
-    pub async fn get_by_route(&self, route: &str) -> Option<Function> {
+    /// 通过路由获取项目内的函数
+    pub async fn get_by_route(&self, project_id: &str, route: &str) -> Option<Function> {
         let routes = self.routes.read().await;
         
         // 精确匹配
-        if let Some(id) = routes.get(route) {
+        if let Some(id) = routes.get(&Self::route_key(project_id, route)) {
             return self.functions.read().await.get(id).cloned();
         }
 
         // 模式匹配
         let functions = self.functions.read().await;
-        for func in functions.values() {
+        for func in functions.values().filter(|f| f.project_id == project_id) {
             if Self::route_matches(&func.route, route) {
                 return Some(func.clone());
             }
@@ -290,13 +381,31 @@ impl FunctionStore {
         true
     }
 
-    /// 列出所有函数
-    pub async fn list(&self) -> Vec<Function> {
+    /// 列出所有项目的函数
+    pub async fn list_all(&self) -> Vec<Function> {
         self.functions.read().await.values().cloned().collect()
     }
 
+    /// 列出项目内的所有函数
+    pub async fn list(&self, project_id: &str) -> Vec<Function> {
+        self.functions
+            .read()
+            .await
+            .values()
+            .filter(|f| f.project_id == project_id)
+            .cloned()
+            .collect()
+    }
+
     /// 更新函数
-    pub async fn update(&self, id: &str, req: UpdateFunctionRequest) -> Result<Function, String> {
+    pub async fn update(&self, id: &str, mut req: UpdateFunctionRequest) -> Result<Function, String> {
+        if let Some(Some(policy)) = req.auth.as_mut() {
+            policy.prepare()?;
+        }
+        if let Some(Some(rate_limit)) = &req.rate_limit {
+            rate_limit.validate()?;
+        }
+
         let mut functions = self.functions.write().await;
         let mut routes = self.routes.write().await;
 
@@ -305,11 +414,12 @@ impl FunctionStore {
         // 更新路由
         if let Some(new_route) = &req.route {
             if new_route != &function.route {
-                if routes.contains_key(new_route) {
+                let new_key = Self::route_key(&function.project_id, new_route);
+                if routes.contains_key(&new_key) {
                     return Err(format!("Route '{}' is already in use", new_route));
                 }
-                routes.remove(&function.route);
-                routes.insert(new_route.clone(), id.to_string());
+                routes.remove(&Self::route_key(&function.project_id, &function.route));
+                routes.insert(new_key, id.to_string());
                 function.route = new_route.clone();
             }
         }
@@ -319,6 +429,12 @@ impl FunctionStore {
         }
         if let Some(code) = req.code {
             function.code = code;
+            function.version += 1;
+            // 新代码已通过检查，清除上次的部署错误
+            function.deploy_error = None;
+            if req.status.is_none() && function.status != FunctionStatus::Inactive {
+                function.status = FunctionStatus::Active;
+            }
         }
         if let Some(methods) = req.methods {
             function.methods = methods;
@@ -326,6 +442,15 @@ impl FunctionStore {
         if let Some(env) = req.env {
             function.env = env;
         }
+        if let Some(require_api_key) = req.require_api_key {
+            function.require_api_key = require_api_key;
+        }
+        if let Some(auth) = req.auth {
+            function.auth = auth;
+        }
+        if let Some(rate_limit) = req.rate_limit {
+            function.rate_limit = rate_limit;
+        }
         if let Some(limits) = req.limits {
             function.limits = limits;
         }
@@ -353,7 +478,7 @@ impl FunctionStore {
         let mut routes = self.routes.write().await;
 
         if let Some(function) = functions.remove(id) {
-            routes.remove(&function.route);
+            routes.remove(&Self::route_key(&function.project_id, &function.route));
             
             // 释放锁后保存
             drop(functions);
@@ -368,6 +493,55 @@ impl FunctionStore {
         }
     }
 
+    /// 完成部署：成功则置为 Active，失败则置为 Error 并记录错误
+    pub async fn finish_deploy(&self, id: &str, error: Option<ScriptError>) -> Result<Function, String> {
+        let mut functions = self.functions.write().await;
+        let function = functions.get_mut(id).ok_or("Function not found")?;
+
+        function.status = if error.is_some() {
+            FunctionStatus::Error
+        } else {
+            FunctionStatus::Active
+        };
+        function.deploy_error = error;
+        function.updated_at = Utc::now();
+
+        let result = function.clone();
+
+        drop(functions);
+        if let Err(e) = self.save().await {
+            eprintln!("[FunctionStore] 保存失败: {}", e);
+        }
+
+        Ok(result)
+    }
+
+    /// 记录更新失败的部署错误，已在运行的旧代码继续提供服务
+    pub async fn record_deploy_error(&self, id: &str, error: ScriptError) {
+        let mut functions = self.functions.write().await;
+        let Some(function) = functions.get_mut(id) else {
+            return;
+        };
+
+        if function.status == FunctionStatus::Deploying {
+            function.status = FunctionStatus::Error;
+        }
+        function.deploy_error = Some(error);
+
+        drop(functions);
+        if let Err(e) = self.save().await {
+            eprintln!("[FunctionStore] 保存失败: {}", e);
+        }
+    }
+
+    /// 保存只在内存中累加的调用次数
+    pub async fn flush(&self) {
+        match self.save().await {
+            Ok(()) => println!("[FunctionStore] 已保存"),
+            Err(e) => eprintln!("[FunctionStore] 保存失败: {}", e),
+        }
+    }
+
     /// 记录调用
     pub async fn record_invocation(&self, id: &str) {
         if let Some(function) = self.functions.write().await.get_mut(id) {
@@ -386,6 +560,7 @@ impl Default for FunctionStore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::project::DEFAULT_PROJECT;
 
     #[tokio::test]
     async fn test_create_function() {
@@ -397,12 +572,18 @@ mod tests {
             route: "/api/test".to_string(),
             methods: vec!["GET".to_string()],
             env: HashMap::new(),
+            require_api_key: false,
+            auth: None,
+            rate_limit: None,
             limits: None,
+            secrets: HashMap::new(),
+            smoke_test: None,
         };
 
-        let function = store.create(req).await.unwrap();
+        let function = store.create(DEFAULT_PROJECT, req).await.unwrap();
         assert_eq!(function.name, "test-fn");
         assert_eq!(function.route, "/api/test");
+        assert_eq!(function.status, FunctionStatus::Deploying);
     }
 
     #[tokio::test]
@@ -415,7 +596,12 @@ mod tests {
             route: "/api/test".to_string(),
             methods: vec![],
             env: HashMap::new(),
+            require_api_key: false,
+            auth: None,
+            rate_limit: None,
             limits: None,
+            secrets: HashMap::new(),
+            smoke_test: None,
         };
 
         let req2 = CreateFunctionRequest {
@@ -424,11 +610,46 @@ mod tests {
             route: "/api/test".to_string(),
             methods: vec![],
             env: HashMap::new(),
+            require_api_key: false,
+            auth: None,
+            rate_limit: None,
+            limits: None,
+            secrets: HashMap::new(),
+            smoke_test: None,
+        };
+
+        store.create(DEFAULT_PROJECT, req1).await.unwrap();
+        assert!(store.create(DEFAULT_PROJECT, req2).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_update_keeps_active() {
+        let store = FunctionStore::with_storage_path(
+            std::env::temp_dir().join(format!("nexo-functions-{}.json", Uuid::new_v4())),
+        );
+
+        let req = CreateFunctionRequest {
+            name: "deploy-fn".to_string(),
+            code: "function handler() {}".to_string(),
+            route: "/api/deploy".to_string(),
+            methods: vec![],
+            env: HashMap::new(),
+            require_api_key: false,
+            auth: None,
+            rate_limit: None,
             limits: None,
+            secrets: HashMap::new(),
+            smoke_test: None,
         };
 
-        store.create(req1).await.unwrap();
-        assert!(store.create(req2).await.is_err());
+        let function = store.create(DEFAULT_PROJECT, req).await.unwrap();
+        store.finish_deploy(&function.id, None).await.unwrap();
+
+        store.record_deploy_error(&function.id, ScriptError::new("SyntaxError")).await;
+        let function = store.get(&function.id).await.unwrap();
+        assert_eq!(function.status, FunctionStatus::Active);
+        assert_eq!(function.code, "function handler() {}");
+        assert!(function.deploy_error.is_some());
     }
 
     #[tokio::test]
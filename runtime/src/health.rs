@@ -0,0 +1,240 @@
+//! Health - 存活与就绪检查
+//!
+//! 存活检查只说明进程和事件循环在响应；就绪检查实际运行各项检查：
+//! 在新建的 Isolate 中执行探测脚本、确认数据目录可写、确认池没有饱和，
+//! 任何一项失败（或正在停机排空）时返回未就绪及各项详情。
+//!
+//! 就绪检查不需要认证，V8 探测的结果在短时间内复用（NEXO_PROBE_CACHE_MS，默认 2 秒），
+//! 同一时间只运行一次探测，频繁请求不会占用 V8 工作线程。
+
+use crate::pool::{IsolatePool, PoolStats};
+use serde::Serialize;
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// V8 探测脚本的默认时限（毫秒）
+const DEFAULT_PROBE_DEADLINE_MS: u64 = 1000;
+/// V8 探测结果的默认复用时间（毫秒）
+const DEFAULT_PROBE_CACHE_MS: u64 = 2000;
+
+/// 单项检查结果
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub ok: bool,
+    /// 失败原因或补充信息
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// 检查耗时
+    pub duration_ms: u64,
+}
+
+impl CheckResult {
+    pub fn pass(name: &str, duration: Duration) -> Self {
+        Self {
+            name: name.to_string(),
+            ok: true,
+            detail: None,
+            duration_ms: duration.as_millis() as u64,
+        }
+    }
+
+    pub fn fail(name: &str, detail: impl Into<String>, duration: Duration) -> Self {
+        Self {
+            name: name.to_string(),
+            ok: false,
+            detail: Some(detail.into()),
+            duration_ms: duration.as_millis() as u64,
+        }
+    }
+}
+
+/// 就绪检查报告
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessReport {
+    /// ready、degraded 或 draining
+    pub status: String,
+    pub checks: Vec<CheckResult>,
+}
+
+impl ReadinessReport {
+    /// 汇总各项检查
+    pub fn new(draining: bool, checks: Vec<CheckResult>) -> Self {
+        let status = if draining {
+            "draining"
+        } else if checks.iter().all(|c| c.ok) {
+            "ready"
+        } else {
+            "degraded"
+        };
+        Self {
+            status: status.to_string(),
+            checks,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.status == "ready"
+    }
+}
+
+/// 从环境变量 NEXO_PROBE_DEADLINE_MS 读取 V8 探测时限
+pub fn probe_deadline_from_env() -> Duration {
+    let ms = std::env::var("NEXO_PROBE_DEADLINE_MS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|&ms: &u64| ms > 0)
+        .unwrap_or(DEFAULT_PROBE_DEADLINE_MS);
+    Duration::from_millis(ms)
+}
+
+/// 复用最近一次的探测结果：过期前直接返回，过期后只有一个请求运行探测，
+/// 并发的请求等待并共用它的结果
+pub struct ProbeCache {
+    ttl: Duration,
+    last: tokio::sync::Mutex<Option<(Instant, CheckResult)>>,
+}
+
+impl ProbeCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            last: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// 从环境变量 NEXO_PROBE_CACHE_MS 读取复用时间
+    pub fn from_env() -> Self {
+        let ms = std::env::var("NEXO_PROBE_CACHE_MS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_PROBE_CACHE_MS);
+        Self::new(Duration::from_millis(ms))
+    }
+
+    pub async fn get_or_run<F, Fut>(&self, probe: F) -> CheckResult
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = CheckResult>,
+    {
+        // 持有锁运行探测，等待的请求拿到锁时结果已经更新
+        let mut last = self.last.lock().await;
+        if let Some((_, result)) = last.as_ref().filter(|(at, _)| at.elapsed() < self.ttl) {
+            return result.clone();
+        }
+        let result = probe().await;
+        *last = Some((Instant::now(), result.clone()));
+        result
+    }
+}
+
+/// 在新建的 Isolate 中运行探测脚本
+pub async fn check_v8(pool: &IsolatePool, deadline: Duration) -> CheckResult {
+    let start = std::time::Instant::now();
+    match pool.probe(deadline).await {
+        Ok(duration) => CheckResult::pass("v8", duration),
+        Err(e) => CheckResult::fail("v8", e, start.elapsed()),
+    }
+}
+
+/// 数据目录可写：写入并删除一个探测文件
+pub fn check_storage(dir: &Path) -> CheckResult {
+    let start = std::time::Instant::now();
+    let probe = dir.join(format!(".health-{}", uuid::Uuid::new_v4()));
+
+    let result = std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&probe, b"ok"))
+        .and_then(|_| std::fs::remove_file(&probe));
+
+    match result {
+        Ok(()) => CheckResult::pass("storage", start.elapsed()),
+        Err(e) => CheckResult::fail(
+            "storage",
+            format!("{} is not writable: {}", dir.display(), e),
+            start.elapsed(),
+        ),
+    }
+}
+
+/// 池饱和：并发已满且排队队列也已满时新的请求会被直接拒绝
+pub fn check_pool(stats: &PoolStats) -> CheckResult {
+    let saturated = stats.current_concurrent >= stats.max_concurrent
+        && stats.queue_depth >= stats.max_queue;
+    if saturated {
+        CheckResult::fail(
+            "pool",
+            format!(
+                "saturated: {}/{} running, {}/{} queued",
+                stats.current_concurrent, stats.max_concurrent, stats.queue_depth, stats.max_queue
+            ),
+            Duration::ZERO,
+        )
+    } else {
+        CheckResult::pass("pool", Duration::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readiness_report() {
+        let dir = std::env::temp_dir().join(format!("nexo-health-{}", uuid::Uuid::new_v4()));
+        let storage = check_storage(&dir);
+        assert!(storage.ok);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        let saturated = PoolStats {
+            current_concurrent: 4,
+            max_concurrent: 4,
+            queue_depth: 10,
+            max_queue: 10,
+            ..Default::default()
+        };
+        let pool = check_pool(&saturated);
+        assert!(!pool.ok);
+
+        let report = ReadinessReport::new(false, vec![storage.clone()]);
+        assert!(report.is_ready());
+        let report = ReadinessReport::new(false, vec![storage.clone(), pool]);
+        assert_eq!(report.status, "degraded");
+        let report = ReadinessReport::new(true, vec![storage]);
+        assert_eq!(report.status, "draining");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_probe_cache() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let cache = Arc::new(ProbeCache::new(Duration::from_secs(60)));
+        let runs = Arc::new(AtomicUsize::new(0));
+        let probe = |runs: Arc<AtomicUsize>| async move {
+            runs.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            CheckResult::pass("v8", Duration::ZERO)
+        };
+
+        // 并发的检查只运行一次探测
+        let checks: Vec<_> = (0..10)
+            .map(|_| {
+                let (cache, runs) = (Arc::clone(&cache), Arc::clone(&runs));
+                tokio::spawn(async move { cache.get_or_run(|| probe(runs)).await })
+            })
+            .collect();
+        for check in checks {
+            assert!(check.await.unwrap().ok);
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // 过期后重新探测
+        let expired = ProbeCache::new(Duration::ZERO);
+        expired.get_or_run(|| probe(Arc::clone(&runs))).await;
+        expired.get_or_run(|| probe(Arc::clone(&runs))).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+}
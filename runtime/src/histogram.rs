@@ -0,0 +1,338 @@
+//! Histogram - 延迟与内存分布统计
+//!
+//! 使用 HDR 风格的对数分桶：每个 2 的幂区间再细分为 16 个子桶，相对误差约 6%，
+//! 桶以稀疏方式保存。按分钟滚动，可以查询最近 1 分钟、5 分钟、1 小时的
+//! p50/p90/p99/max。导出给 Prometheus 的执行时间另用与 `le` 上界对齐的
+//! [`DurationBuckets`] 计数，不受分桶误差影响。
+
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Instant;
+
+/// 每个 2 的幂区间的子桶数（2^SUB_BUCKET_BITS）
+const SUB_BUCKET_BITS: u32 = 4;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+
+/// 保留的分钟数（最大窗口）
+const WINDOW_MINUTES: u64 = 60;
+
+/// 导出的执行时间直方图的桶上界（毫秒）
+pub const DURATION_BUCKETS_MS: [u64; 12] = [1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// 值所在的桶
+fn bucket_index(value: u64) -> u32 {
+    if value < SUB_BUCKETS {
+        return value as u32;
+    }
+    let exponent = 63 - value.leading_zeros();
+    let shift = exponent - SUB_BUCKET_BITS;
+    let mantissa = ((value >> shift) & (SUB_BUCKETS - 1)) as u32;
+    SUB_BUCKETS as u32 + shift * SUB_BUCKETS as u32 + mantissa
+}
+
+/// 桶内的最大值
+fn bucket_upper_bound(index: u32) -> u64 {
+    if (index as u64) < SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = (index - SUB_BUCKETS as u32) / SUB_BUCKETS as u32;
+    let mantissa = ((index - SUB_BUCKETS as u32) % SUB_BUCKETS as u32) as u64;
+    let lower = (SUB_BUCKETS + mantissa) << shift;
+    lower + ((1u64 << shift) - 1)
+}
+
+/// 稀疏直方图
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    buckets: BTreeMap<u32, u64>,
+    count: u64,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: u64) {
+        *self.buckets.entry(bucket_index(value)).or_insert(0) += 1;
+        self.count += 1;
+        self.max = self.max.max(value);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (index, count) in &other.buckets {
+            *self.buckets.entry(*index).or_insert(0) += count;
+        }
+        self.count += other.count;
+        self.max = self.max.max(other.max);
+    }
+
+    /// 分位数（0.0 - 1.0），返回所在桶的上界（不超过最大值）
+    pub fn quantile(&self, q: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((q * self.count as f64).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        for (index, count) in &self.buckets {
+            seen += count;
+            if seen >= rank {
+                return bucket_upper_bound(*index).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    pub fn percentiles(&self) -> Percentiles {
+        Percentiles {
+            count: self.count,
+            p50: self.quantile(0.5),
+            p90: self.quantile(0.9),
+            p99: self.quantile(0.99),
+            max: self.max,
+        }
+    }
+}
+
+/// 按 [`DURATION_BUCKETS_MS`] 分桶的执行时间计数（Prometheus `le` 桶）
+#[derive(Debug, Clone, Default)]
+pub struct DurationBuckets {
+    /// 各上界对应区间的计数，最后一项为超过最大上界的值
+    counts: [u64; DURATION_BUCKETS_MS.len() + 1],
+    count: u64,
+    sum: u64,
+}
+
+impl DurationBuckets {
+    pub fn record(&mut self, value: u64) {
+        let index = DURATION_BUCKETS_MS.partition_point(|&bound| bound < value);
+        self.counts[index] += 1;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(value);
+    }
+
+    /// 小于等于各上界的累计计数，与 [`DURATION_BUCKETS_MS`] 一一对应
+    pub fn cumulative_counts(&self) -> Vec<u64> {
+        self.counts[..DURATION_BUCKETS_MS.len()]
+            .iter()
+            .scan(0, |total, count| {
+                *total += count;
+                Some(*total)
+            })
+            .collect()
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+}
+
+/// 分位数摘要
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq, Eq)]
+pub struct Percentiles {
+    pub count: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+/// 各滚动窗口的分位数
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WindowedPercentiles {
+    #[serde(rename = "1m")]
+    pub last_1m: Percentiles,
+    #[serde(rename = "5m")]
+    pub last_5m: Percentiles,
+    #[serde(rename = "1h")]
+    pub last_1h: Percentiles,
+}
+
+/// 按分钟滚动的直方图
+#[derive(Debug, Clone)]
+pub struct RollingHistogram {
+    start: Instant,
+    /// (分钟序号, 该分钟的直方图)，按时间排序
+    slots: VecDeque<(u64, Histogram)>,
+}
+
+impl RollingHistogram {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            slots: VecDeque::new(),
+        }
+    }
+
+    fn minute(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.start).as_secs() / 60
+    }
+
+    pub fn record(&mut self, value: u64) {
+        self.record_at(value, Instant::now());
+    }
+
+    fn record_at(&mut self, value: u64, now: Instant) {
+        let minute = self.minute(now);
+        while self
+            .slots
+            .front()
+            .is_some_and(|(m, _)| *m + WINDOW_MINUTES <= minute)
+        {
+            self.slots.pop_front();
+        }
+
+        match self.slots.back_mut() {
+            Some((m, histogram)) if *m == minute => histogram.record(value),
+            _ => {
+                let mut histogram = Histogram::default();
+                histogram.record(value);
+                self.slots.push_back((minute, histogram));
+            }
+        }
+    }
+
+    /// 最近 `minutes` 分钟（含当前分钟）的分位数
+    fn window_at(&self, minutes: u64, now: Instant) -> Percentiles {
+        let minute = self.minute(now);
+        let mut merged = Histogram::default();
+        for (m, histogram) in self.slots.iter().rev() {
+            if *m + minutes <= minute {
+                break;
+            }
+            merged.merge(histogram);
+        }
+        merged.percentiles()
+    }
+
+    pub fn snapshot(&self) -> WindowedPercentiles {
+        self.snapshot_at(Instant::now())
+    }
+
+    fn snapshot_at(&self, now: Instant) -> WindowedPercentiles {
+        WindowedPercentiles {
+            last_1m: self.window_at(1, now),
+            last_5m: self.window_at(5, now),
+            last_1h: self.window_at(WINDOW_MINUTES, now),
+        }
+    }
+}
+
+impl Default for RollingHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 执行延迟、排队等待和内存的分布
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LatencyStats {
+    pub execution_time_ms: WindowedPercentiles,
+    pub queue_wait_ms: WindowedPercentiles,
+    pub memory_used_bytes: WindowedPercentiles,
+}
+
+/// 分布记录器
+#[derive(Debug, Clone, Default)]
+pub struct LatencyRecorder {
+    /// 自启动以来的执行时间（不滚动）
+    execution_total: DurationBuckets,
+    execution_time_ms: RollingHistogram,
+    queue_wait_ms: RollingHistogram,
+    memory_used_bytes: RollingHistogram,
+}
+
+impl LatencyRecorder {
+    pub fn record_execution(&mut self, execution_time_ms: u64, memory_used_bytes: u64) {
+        self.execution_total.record(execution_time_ms);
+        self.execution_time_ms.record(execution_time_ms);
+        self.memory_used_bytes.record(memory_used_bytes);
+    }
+
+    pub fn record_queue_wait(&mut self, queue_wait_ms: u64) {
+        self.queue_wait_ms.record(queue_wait_ms);
+    }
+
+    pub fn execution_histogram(&self) -> &DurationBuckets {
+        &self.execution_total
+    }
+
+    pub fn snapshot(&self) -> LatencyStats {
+        LatencyStats {
+            execution_time_ms: self.execution_time_ms.snapshot(),
+            queue_wait_ms: self.queue_wait_ms.snapshot(),
+            memory_used_bytes: self.memory_used_bytes.snapshot(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_bucket_precision() {
+        for value in [0, 1, 15, 16, 17, 100, 1_000, 123_456, 128 * 1024 * 1024, u64::MAX] {
+            let upper = bucket_upper_bound(bucket_index(value));
+            assert!(upper >= value);
+            assert!((upper - value) as f64 <= value as f64 / SUB_BUCKETS as f64);
+        }
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut histogram = Histogram::default();
+        for value in 1..=100 {
+            histogram.record(value);
+        }
+
+        let p = histogram.percentiles();
+        assert_eq!(p.count, 100);
+        assert!((48..=53).contains(&p.p50));
+        assert!((88..=95).contains(&p.p90));
+        assert!((97..=100).contains(&p.p99));
+        assert_eq!(p.max, 100);
+    }
+
+    #[test]
+    fn test_duration_buckets_match_le() {
+        let mut buckets = DurationBuckets::default();
+        for value in [0, 1, 2, 999, 1000, 1001, 20000] {
+            buckets.record(value);
+        }
+
+        // 等于上界的值计入该 `le`
+        let counts = buckets.cumulative_counts();
+        assert_eq!(counts.len(), DURATION_BUCKETS_MS.len());
+        assert_eq!(counts[0], 2);
+        assert_eq!(counts[1], 3);
+        assert_eq!(counts[8], 5);
+        assert_eq!(counts[9], 6);
+        assert_eq!(counts[11], 6);
+        assert_eq!(buckets.count(), 7);
+        assert_eq!(buckets.sum(), 23003);
+    }
+
+    #[test]
+    fn test_rolling_windows() {
+        let mut rolling = RollingHistogram::new();
+        let start = rolling.start;
+
+        rolling.record_at(1000, start);
+        rolling.record_at(10, start + Duration::from_secs(4 * 60));
+
+        let now = start + Duration::from_secs(4 * 60 + 30);
+        let snapshot = rolling.snapshot_at(now);
+        assert_eq!(snapshot.last_1m.count, 1);
+        assert_eq!(snapshot.last_1m.max, 10);
+        assert_eq!(snapshot.last_5m.count, 2);
+        assert_eq!(snapshot.last_5m.max, 1000);
+
+        // 一小时后旧数据被丢弃
+        let later = start + Duration::from_secs(61 * 60);
+        rolling.record_at(5, later);
+        assert_eq!(rolling.snapshot_at(later).last_1h.count, 2);
+    }
+}
@@ -5,7 +5,11 @@
 
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Instant;
+use tokio::sync::Notify;
 use v8;
 
 /// V8 平台初始化（全局只需一次）
@@ -29,6 +33,9 @@ pub struct IsolateConfig {
     pub max_heap_size_bytes: usize,
     /// 函数 ID
     pub function_id: String,
+    /// 所属项目 ID（用于项目配额，空表示不限制）
+    #[serde(default)]
+    pub project_id: String,
 }
 
 impl Default for IsolateConfig {
@@ -37,10 +44,27 @@ impl Default for IsolateConfig {
             max_execution_time_ms: 50,
             max_heap_size_bytes: 128 * 1024 * 1024, // 128MB
             function_id: String::new(),
+            project_id: String::new(),
         }
     }
 }
 
+/// 失败类型
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// 脚本或运行时错误
+    Error,
+    /// 池已过载（排队已满或排队超时），请求未被执行
+    Overloaded,
+    /// 超过最大执行时间
+    Timeout,
+    /// 超过堆内存上限
+    OutOfMemory,
+    /// 客户端断开，执行被取消
+    Cancelled,
+}
+
 /// 执行结果
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecutionResult {
@@ -50,8 +74,233 @@ pub struct ExecutionResult {
     pub execution_time_ms: u64,
     pub memory_used_bytes: usize,
     pub logs: Vec<String>,
+    /// 失败类型（成功时为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure_kind: Option<FailureKind>,
+}
+
+impl ExecutionResult {
+    /// 未能执行时的失败结果
+    pub fn failure(error: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            output: None,
+            error: Some(error.into()),
+            execution_time_ms: 0,
+            memory_used_bytes: 0,
+            logs: vec![],
+            failure_kind: Some(FailureKind::Error),
+        }
+    }
+
+    /// 池过载时拒绝执行的结果
+    pub fn overloaded(error: impl Into<String>) -> Self {
+        Self {
+            failure_kind: Some(FailureKind::Overloaded),
+            ..Self::failure(error)
+        }
+    }
+
+    /// 是否因池过载被拒绝
+    pub fn is_overloaded(&self) -> bool {
+        self.failure_kind == Some(FailureKind::Overloaded)
+    }
+
+    /// 被取消（客户端断开或网关超时）时的结果
+    pub fn cancelled(kind: FailureKind, error: impl Into<String>) -> Self {
+        Self {
+            failure_kind: Some(kind),
+            ..Self::failure(error)
+        }
+    }
+}
+
+/// 取消状态
+#[derive(Default)]
+struct CancelState {
+    /// 取消原因，为空表示未取消
+    reason: Option<(FailureKind, String)>,
+    /// 正在执行的 Isolate
+    handle: Option<v8::IsolateHandle>,
+}
+
+/// 执行取消令牌
+///
+/// 网关持有一份，执行线程持有另一份。取消时如果脚本正在运行，
+/// 通过 `terminate_execution` 立即终止；还在排队的执行不会再开始。
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    state: Arc<parking_lot::Mutex<CancelState>>,
+    notify: Arc<Notify>,
 }
 
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 取消执行（只有第一次取消的原因生效）
+    pub fn cancel(&self, kind: FailureKind, error: impl Into<String>) {
+        let mut state = self.state.lock();
+        if state.reason.is_some() {
+            return;
+        }
+        state.reason = Some((kind, error.into()));
+        if let Some(handle) = &state.handle {
+            handle.terminate_execution();
+        }
+        drop(state);
+        self.notify.notify_waiters();
+    }
+
+    /// 已取消时返回对应的结果
+    pub fn cancelled_result(&self) -> Option<ExecutionResult> {
+        self.state
+            .lock()
+            .reason
+            .clone()
+            .map(|(kind, error)| ExecutionResult::cancelled(kind, error))
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.lock().reason.is_some()
+    }
+
+    /// 等待取消
+    pub async fn cancelled(&self) {
+        let notified = self.notify.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
+
+    /// 返回的守卫在未解除时被丢弃（如客户端断开导致请求的 future 被丢弃）会取消执行
+    pub fn drop_guard(&self) -> CancelOnDrop {
+        CancelOnDrop {
+            token: Some(self.clone()),
+        }
+    }
+
+    /// 关联正在执行的 Isolate；已取消时返回 false
+    fn attach(&self, handle: v8::IsolateHandle) -> bool {
+        let mut state = self.state.lock();
+        if state.reason.is_some() {
+            return false;
+        }
+        state.handle = Some(handle);
+        true
+    }
+
+    /// 解除关联，之后的取消不会再终止这个 Isolate
+    fn detach(&self) {
+        self.state.lock().handle = None;
+    }
+}
+
+/// 丢弃时取消执行的守卫
+pub struct CancelOnDrop {
+    token: Option<CancelToken>,
+}
+
+impl CancelOnDrop {
+    /// 执行已正常结束，丢弃时不再取消
+    pub fn disarm(mut self) {
+        self.token = None;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            token.cancel(FailureKind::Cancelled, "Execution cancelled: client disconnected");
+        }
+    }
+}
+
+/// 脚本错误（语法错误或冒烟测试失败），带用户代码中的位置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ScriptError {
+    /// 错误信息
+    pub message: String,
+    /// 行号（从 1 开始）
+    pub line: Option<usize>,
+    /// 列号（从 1 开始）
+    pub column: Option<usize>,
+}
+
+impl ScriptError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            line: None,
+            column: None,
+        }
+    }
+}
+
+impl std::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => {
+                write!(f, "{} (line {}, column {})", self.message, line, column)
+            }
+            (Some(line), None) => write!(f, "{} (line {})", self.message, line),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// 单次执行最多保留的日志条数
+const MAX_LOG_ENTRIES: usize = 1000;
+
+/// Isolate 槽中保存的日志缓冲区
+#[derive(Default)]
+struct CapturedLogs(Vec<String>);
+
+/// 收集一条 console 输出
+fn capture_log(scope: &mut v8::HandleScope, args: &v8::FunctionCallbackArguments, level: &str) {
+    let mut parts = Vec::new();
+    for i in 0..args.length() {
+        let arg = args.get(i);
+        if let Some(s) = arg.to_string(scope) {
+            parts.push(s.to_rust_string_lossy(scope));
+        }
+    }
+    let msg = format!("[{}] {}", level, parts.join(" "));
+    tracing::debug!("{}", msg);
+
+    if let Some(logs) = scope.get_slot_mut::<CapturedLogs>() {
+        if logs.0.len() < MAX_LOG_ENTRIES {
+            logs.0.push(msg);
+        }
+    }
+}
+
+/// 超时错误信息的前缀
+const TIMEOUT_ERROR: &str = "Execution timeout";
+
+/// 接近堆上限回调的状态
+struct HeapLimitState {
+    handle: v8::IsolateHandle,
+    reached: AtomicBool,
+}
+
+/// 堆即将超限：终止脚本并临时放宽上限，让 V8 有余量完成终止而不是直接崩溃
+extern "C" fn near_heap_limit(data: *mut c_void, current_heap_limit: usize, _initial: usize) -> usize {
+    // SAFETY: data 指向 execute_in 栈上的 HeapLimitState，回调只在其存活期间注册
+    let state = unsafe { &*(data as *const HeapLimitState) };
+    state.reached.store(true, Ordering::SeqCst);
+    state.handle.terminate_execution();
+    current_heap_limit * 2
+}
+
+/// 编译检查时包裹用户代码的前缀，与执行时一样放在函数体内，
+/// 以便 `return` 等语句的合法性与运行时一致
+const CHECK_PREFIX: &str = "(function() {\n";
+
 /// Nexo V8 Isolate - 轻量级 JavaScript 执行沙箱
 pub struct NexoIsolate {
     config: IsolateConfig,
@@ -64,17 +313,138 @@ impl NexoIsolate {
         Self { config }
     }
 
-    /// 执行 JavaScript 代码
+    /// Isolate 配置
+    pub fn config(&self) -> &IsolateConfig {
+        &self.config
+    }
+
+    /// 只编译不执行，用于部署时检查语法错误
+    pub fn check(&self, code: &str) -> Result<(), ScriptError> {
+        let create_params = v8::CreateParams::default()
+            .heap_limits(0, self.config.max_heap_size_bytes);
+        let isolate = &mut v8::Isolate::new(create_params);
+        let handle_scope = &mut v8::HandleScope::new(isolate);
+        let context = v8::Context::new(handle_scope);
+        let scope = &mut v8::ContextScope::new(handle_scope, context);
+        let tc = &mut v8::TryCatch::new(scope);
+
+        let wrapped_code = format!("{}{}\n}})", CHECK_PREFIX, code);
+        let source = v8::String::new(tc, &wrapped_code)
+            .ok_or_else(|| ScriptError::new("Failed to create code string"))?;
+
+        if v8::Script::compile(tc, source, None).is_some() {
+            return Ok(());
+        }
+
+        let message = tc
+            .exception()
+            .and_then(|e| e.to_string(tc))
+            .map(|s| s.to_rust_string_lossy(tc))
+            .unwrap_or_else(|| "SyntaxError".to_string());
+
+        // 换算回用户代码中的位置（前缀占一行）
+        let (line, column) = match tc.message() {
+            Some(m) => (
+                m.get_line_number(tc).map(|l| l.saturating_sub(1).max(1)),
+                Some(m.get_start_column() + 1),
+            ),
+            None => (None, None),
+        };
+
+        Err(ScriptError { message, line, column })
+    }
+
+    /// 执行 JavaScript 代码（使用新建的 V8 Isolate）
+    #[allow(dead_code)]
     pub fn execute(&self, code: &str, request_data: serde_json::Value) -> Result<ExecutionResult> {
-        let start_time = Instant::now();
-        let mut logs: Vec<String> = Vec::new();
+        let mut isolate = self.create_isolate();
+        self.execute_in(&mut isolate, code, request_data, &CancelToken::new())
+    }
 
-        // 创建 Isolate 参数，设置堆限制
+    /// 按配置的堆限制创建 V8 Isolate
+    pub fn create_isolate(&self) -> v8::OwnedIsolate {
         let create_params = v8::CreateParams::default()
             .heap_limits(0, self.config.max_heap_size_bytes);
+        v8::Isolate::new(create_params)
+    }
 
-        // 创建 Isolate
-        let isolate = &mut v8::Isolate::new(create_params);
+    /// 在已有的 V8 Isolate 中执行 JavaScript 代码
+    ///
+    /// 每次执行都创建新的 Context，全局对象不会在两次执行之间共享。
+    /// 执行期间 `cancel` 被取消时脚本会被终止，返回取消的结果。
+    pub fn execute_in(
+        &self,
+        isolate: &mut v8::Isolate,
+        code: &str,
+        request_data: serde_json::Value,
+        cancel: &CancelToken,
+    ) -> Result<ExecutionResult> {
+        if !cancel.attach(isolate.thread_safe_handle()) {
+            return Ok(cancel.cancelled_result().unwrap_or_else(|| ExecutionResult::failure("Cancelled")));
+        }
+        let result = self.execute_attached(isolate, code, request_data);
+        cancel.detach();
+
+        match cancel.cancelled_result() {
+            Some(mut cancelled) => {
+                // 脚本已被终止（或刚好执行完），清除终止状态以便复用 Isolate
+                isolate.cancel_terminate_execution();
+                if let Ok(result) = result {
+                    cancelled.execution_time_ms = result.execution_time_ms;
+                    cancelled.logs = result.logs;
+                }
+                Ok(cancelled)
+            }
+            None => result,
+        }
+    }
+
+    /// 执行并处理堆超限
+    fn execute_attached(
+        &self,
+        isolate: &mut v8::Isolate,
+        code: &str,
+        request_data: serde_json::Value,
+    ) -> Result<ExecutionResult> {
+        let heap_state = HeapLimitState {
+            handle: isolate.thread_safe_handle(),
+            reached: AtomicBool::new(false),
+        };
+        isolate.add_near_heap_limit_callback(
+            near_heap_limit,
+            std::ptr::addr_of!(heap_state) as *mut c_void,
+        );
+
+        let result = self.run_in_context(isolate, code, request_data);
+
+        isolate.remove_near_heap_limit_callback(near_heap_limit, self.config.max_heap_size_bytes);
+        if !heap_state.reached.load(Ordering::SeqCst) {
+            return result;
+        }
+
+        // 超出堆上限：脚本已被终止
+        isolate.cancel_terminate_execution();
+        let mut result = result?;
+        result.success = false;
+        result.output = None;
+        result.error = Some(format!(
+            "Memory limit exceeded: {}MB",
+            self.config.max_heap_size_bytes / (1024 * 1024)
+        ));
+        result.failure_kind = Some(FailureKind::OutOfMemory);
+        Ok(result)
+    }
+
+    /// 在新的 Context 中注入全局对象并执行代码
+    fn run_in_context(
+        &self,
+        isolate: &mut v8::Isolate,
+        code: &str,
+        request_data: serde_json::Value,
+    ) -> Result<ExecutionResult> {
+        let start_time = Instant::now();
+
+        isolate.set_slot(CapturedLogs::default());
 
         // 设置执行超时
         let timeout_ms = self.config.max_execution_time_ms;
@@ -87,10 +457,16 @@ impl NexoIsolate {
             let scope = &mut v8::ContextScope::new(handle_scope, context);
 
             // 注入全局对象和函数
-            self.inject_globals(scope, &request_data, &mut logs)?;
+            self.inject_globals(scope, &request_data)?;
 
             // 编译并执行代码
-            match self.compile_and_run(scope, code, &request_data, timeout_ms, start) {
+            let outcome = self.compile_and_run(scope, code, &request_data, timeout_ms, start);
+            let logs = scope
+                .remove_slot::<CapturedLogs>()
+                .map(|l| l.0)
+                .unwrap_or_default();
+
+            match outcome {
                 Ok(value) => {
                     let execution_time_ms = start_time.elapsed().as_millis() as u64;
                     
@@ -105,17 +481,25 @@ impl NexoIsolate {
                         execution_time_ms,
                         memory_used_bytes: stats.used_heap_size(),
                         logs,
+                        failure_kind: None,
                     }
                 }
                 Err(e) => {
                     let execution_time_ms = start_time.elapsed().as_millis() as u64;
+                    let error = e.to_string();
+                    let failure_kind = if error.starts_with(TIMEOUT_ERROR) {
+                        FailureKind::Timeout
+                    } else {
+                        FailureKind::Error
+                    };
                     ExecutionResult {
                         success: false,
                         output: None,
-                        error: Some(e.to_string()),
+                        error: Some(error),
                         execution_time_ms,
                         memory_used_bytes: 0,
                         logs,
+                        failure_kind: Some(failure_kind),
                     }
                 }
             }
@@ -129,7 +513,6 @@ impl NexoIsolate {
         &self,
         scope: &mut v8::ContextScope<v8::HandleScope>,
         request_data: &serde_json::Value,
-        _logs: &mut Vec<String>,
     ) -> Result<()> {
         let global = scope.get_current_context().global(scope);
 
@@ -189,28 +572,19 @@ impl NexoIsolate {
         Ok(())
     }
 
-    /// 注入 console 对象
+    /// 注入 console 对象，输出被收集到 Isolate 的日志槽中
     fn inject_console(
         &self,
         scope: &mut v8::ContextScope<v8::HandleScope>,
         global: v8::Local<v8::Object>,
     ) -> Result<()> {
         let console = v8::Object::new(scope);
-        
+
         // console.log
         let log_fn = v8::Function::new(scope, |scope: &mut v8::HandleScope,
                                         args: v8::FunctionCallbackArguments,
                                         mut _rv: v8::ReturnValue| {
-            let mut parts = Vec::new();
-            for i in 0..args.length() {
-                let arg = args.get(i);
-                if let Some(s) = arg.to_string(scope) {
-                    parts.push(s.to_rust_string_lossy(scope));
-                }
-            }
-            let msg = parts.join(" ");
-            // 在实际实现中，这里会收集日志
-            println!("[LOG] {}", msg);
+            capture_log(scope, &args, "LOG");
         }).unwrap();
 
         let log_key = v8::String::new(scope, "log").unwrap();
@@ -220,15 +594,7 @@ impl NexoIsolate {
         let error_fn = v8::Function::new(scope, |scope: &mut v8::HandleScope,
                                           args: v8::FunctionCallbackArguments,
                                           mut _rv: v8::ReturnValue| {
-            let mut parts = Vec::new();
-            for i in 0..args.length() {
-                let arg = args.get(i);
-                if let Some(s) = arg.to_string(scope) {
-                    parts.push(s.to_rust_string_lossy(scope));
-                }
-            }
-            let msg = parts.join(" ");
-            println!("[ERROR] {}", msg);
+            capture_log(scope, &args, "ERROR");
         }).unwrap();
 
         let error_key = v8::String::new(scope, "error").unwrap();
@@ -238,15 +604,7 @@ impl NexoIsolate {
         let warn_fn = v8::Function::new(scope, |scope: &mut v8::HandleScope,
                                          args: v8::FunctionCallbackArguments,
                                          mut _rv: v8::ReturnValue| {
-            let mut parts = Vec::new();
-            for i in 0..args.length() {
-                let arg = args.get(i);
-                if let Some(s) = arg.to_string(scope) {
-                    parts.push(s.to_rust_string_lossy(scope));
-                }
-            }
-            let msg = parts.join(" ");
-            println!("[WARN] {}", msg);
+            capture_log(scope, &args, "WARN");
         }).unwrap();
 
         let warn_key = v8::String::new(scope, "warn").unwrap();
@@ -281,16 +639,31 @@ impl NexoIsolate {
                     }}
                 }};
 
+                // 交回站点继续提供静态文件（站点中间件），返回的指令由网关执行，
+                // 函数拿不到文件内容，必须把指令作为 handler 的返回值
+                var next = function(options) {{
+                    options = typeof options === 'string' ? {{ path: options }} : (options || {{}});
+                    return {{ _isNext: true, path: options.path || null, headers: options.headers || {{}} }};
+                }};
+
                 // 环境变量对象
                 var envData = __REQUEST__.env || {{}};
                 var env = {{
                     get: function(key) {{ 
                         return envData[key]; 
+                    }},
+                    // 站点静态文件：传入路径时改写到该路径，传入 request 时提供原路径。
+                    // 与 ctx.next() 相同，返回的是指令而不是文件。
+                    // TODO: 这里不是出站 fetch；添加出站 fetch 时需要附加 traceparent（见 telemetry 模块）
+                    ASSETS: {{
+                        fetch: function(input) {{
+                            return next(typeof input === 'string' ? {{ path: input }} : {{}});
+                        }}
                     }}
                 }};
                 
                 // 上下文对象（传递给 handler 的第二个参数）
-                var ctx = {{ env: env }};
+                var ctx = {{ env: env, auth: __REQUEST__.auth || null, next: next }};
 
                 // 用户代码
                 {user_code}
@@ -316,6 +689,14 @@ impl NexoIsolate {
                             headers: result.headers
                         }});
                     }}
+                    // 处理 ctx.next() 指令
+                    if (result && result._isNext) {{
+                        return JSON.stringify({{
+                            __isNext: true,
+                            path: result.path,
+                            headers: result.headers
+                        }});
+                    }}
                     return JSON.stringify(result);
                 }} else if (typeof main === 'function') {{
                     var result = main(request);
@@ -345,21 +726,23 @@ impl NexoIsolate {
         let code = v8::String::new(scope, &wrapped_code)
             .ok_or_else(|| anyhow!("Failed to create code string"))?;
 
-        let script = v8::Script::compile(scope, code, None)
+        let script = tracing::info_span!("isolate.compile")
+            .in_scope(|| v8::Script::compile(scope, code, None))
             .ok_or_else(|| anyhow!("Failed to compile script"))?;
 
         // 检查超时
         if start.elapsed().as_millis() as u64 > timeout_ms {
-            return Err(anyhow!("Execution timeout: exceeded {}ms limit", timeout_ms));
+            return Err(anyhow!("{}: exceeded {}ms limit", TIMEOUT_ERROR, timeout_ms));
         }
 
         // 执行脚本
-        let result = script.run(scope)
+        let result = tracing::info_span!("isolate.run")
+            .in_scope(|| script.run(scope))
             .ok_or_else(|| anyhow!("Script execution failed"))?;
 
         // 检查超时
         if start.elapsed().as_millis() as u64 > timeout_ms {
-            return Err(anyhow!("Execution timeout: exceeded {}ms limit", timeout_ms));
+            return Err(anyhow!("{}: exceeded {}ms limit", TIMEOUT_ERROR, timeout_ms));
         }
 
         // 转换结果
@@ -417,6 +800,27 @@ mod tests {
         assert_eq!(output["method"], "POST");
     }
 
+    #[test]
+    fn test_next_directive() {
+        let isolate = NexoIsolate::new(IsolateConfig::default());
+        let code = r#"
+            function handler(request, ctx) {
+                if (request.url === '/private') return ctx.next({ path: '/login.html' });
+                return ctx.env.ASSETS.fetch(request);
+            }
+        "#;
+
+        let result = isolate.execute(code, serde_json::json!({ "url": "/private" })).unwrap();
+        let output = result.output.unwrap();
+        assert_eq!(output["__isNext"], true);
+        assert_eq!(output["path"], "/login.html");
+
+        let result = isolate.execute(code, serde_json::json!({ "url": "/" })).unwrap();
+        let output = result.output.unwrap();
+        assert_eq!(output["__isNext"], true);
+        assert!(output["path"].is_null());
+    }
+
     #[test]
     fn test_console_log() {
         let isolate = NexoIsolate::new(IsolateConfig::default());
@@ -429,6 +833,7 @@ mod tests {
 
         let result = isolate.execute(code, serde_json::json!({})).unwrap();
         assert!(result.success);
+        assert_eq!(result.logs, vec!["[LOG] Hello from isolate!".to_string()]);
     }
 
     #[test]
@@ -444,4 +849,35 @@ mod tests {
         assert!(!result.success);
         assert!(result.error.is_some());
     }
+
+    #[test]
+    fn test_check_reports_location() {
+        let isolate = NexoIsolate::new(IsolateConfig::default());
+        assert!(isolate.check("function handler() { return 1; }").is_ok());
+
+        let code = "function handler(request) {\n    return { invalid syntax here\n}";
+        let err = isolate.check(code).unwrap_err();
+        assert!(err.message.contains("SyntaxError"));
+        assert_eq!(err.line, Some(2));
+        assert!(err.column.is_some());
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let isolate = NexoIsolate::new(IsolateConfig {
+            max_heap_size_bytes: 16 * 1024 * 1024,
+            max_execution_time_ms: 10_000,
+            ..Default::default()
+        });
+        let code = r#"
+            function handler() {
+                var chunks = [];
+                while (true) { chunks.push(new Array(100000).fill("x")); }
+            }
+        "#;
+
+        let result = isolate.execute(code, serde_json::json!({})).unwrap();
+        assert!(!result.success);
+        assert_eq!(result.failure_kind, Some(FailureKind::OutOfMemory));
+    }
 }
@@ -0,0 +1,400 @@
+//! Invocation Logs - 函数调用记录存储
+//!
+//! 每次执行生成一条调用记录（请求 ID、函数版本、状态码、耗时、内存、
+//! 捕获的 console 输出和错误），按函数追加写入 `data/logs/{function_id}.jsonl`。
+//! 每个函数只保留最近的若干条且不超过保留时长，日志文件超过上限的两倍时重写压缩。
+//! 新记录同时广播给实时订阅者（SSE tail）。
+
+use crate::isolate::FailureKind;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{broadcast, RwLock};
+
+/// 单次查询最多返回的记录数
+const MAX_PAGE_SIZE: usize = 500;
+
+/// 调用记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvocationRecord {
+    /// 请求 ID
+    pub request_id: String,
+    pub function_id: String,
+    pub project_id: String,
+    /// 执行时的函数版本
+    pub version: u32,
+    pub timestamp: DateTime<Utc>,
+    pub method: String,
+    pub url: String,
+    /// 响应状态码
+    pub status: u16,
+    pub duration_ms: u64,
+    pub memory_used_bytes: usize,
+    /// 捕获的 console 输出
+    pub logs: Vec<String>,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub failure_kind: Option<FailureKind>,
+}
+
+/// 保留策略
+#[derive(Debug, Clone)]
+pub struct LogRetention {
+    /// 每个函数保留的最大记录数
+    pub max_records: usize,
+    /// 最长保留时间
+    pub max_age: Duration,
+}
+
+impl LogRetention {
+    /// 从环境变量读取：NEXO_LOG_MAX_RECORDS（默认 1000）、NEXO_LOG_RETENTION_HOURS（默认 24）
+    pub fn from_env() -> Self {
+        fn env(name: &str) -> Option<i64> {
+            std::env::var(name).ok().and_then(|s| s.parse().ok()).filter(|&n| n > 0)
+        }
+
+        Self {
+            max_records: env("NEXO_LOG_MAX_RECORDS").unwrap_or(1000) as usize,
+            max_age: Duration::hours(env("NEXO_LOG_RETENTION_HOURS").unwrap_or(24)),
+        }
+    }
+}
+
+/// 查询条件
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LogQuery {
+    /// 只返回该状态码
+    pub status: Option<u16>,
+    /// 只返回失败（状态码 >= 400）或成功的记录
+    pub errors: Option<bool>,
+    /// 只返回该版本
+    pub version: Option<u32>,
+    pub request_id: Option<String>,
+    /// 起始时间（含）
+    pub since: Option<DateTime<Utc>>,
+    /// 结束时间（不含）
+    pub until: Option<DateTime<Utc>>,
+    /// 在日志和错误信息中搜索
+    pub q: Option<String>,
+    /// 跳过的记录数
+    #[serde(default)]
+    pub offset: usize,
+    /// 返回的记录数（默认 50，最大 500）
+    pub limit: Option<usize>,
+}
+
+impl LogQuery {
+    fn matches(&self, record: &InvocationRecord) -> bool {
+        self.status.is_none_or(|s| record.status == s)
+            && self.errors.is_none_or(|e| (record.status >= 400) == e)
+            && self.version.is_none_or(|v| record.version == v)
+            && self.request_id.as_ref().is_none_or(|id| &record.request_id == id)
+            && self.since.is_none_or(|t| record.timestamp >= t)
+            && self.until.is_none_or(|t| record.timestamp < t)
+            && self.q.as_ref().is_none_or(|q| {
+                record.logs.iter().any(|l| l.contains(q.as_str()))
+                    || record.error.as_ref().is_some_and(|e| e.contains(q.as_str()))
+            })
+    }
+}
+
+/// 分页结果（按时间倒序）
+#[derive(Debug, Clone, Serialize)]
+pub struct LogPage {
+    pub records: Vec<InvocationRecord>,
+    /// 匹配的总数
+    pub total: usize,
+    /// 下一页的 offset，没有更多时为空
+    pub next_offset: Option<usize>,
+}
+
+/// 单个函数的日志
+#[derive(Default)]
+struct FunctionLog {
+    records: VecDeque<InvocationRecord>,
+    /// 日志文件中的行数
+    file_lines: usize,
+}
+
+/// 调用记录存储（克隆共享同一份数据）
+#[derive(Clone)]
+pub struct LogStore {
+    logs: Arc<RwLock<HashMap<String, FunctionLog>>>,
+    /// 日志目录
+    storage_dir: PathBuf,
+    retention: LogRetention,
+    /// 实时订阅
+    sender: broadcast::Sender<InvocationRecord>,
+}
+
+impl LogStore {
+    pub fn new() -> Self {
+        let storage_dir = std::env::current_dir()
+            .unwrap_or_else(|_| PathBuf::from("."))
+            .join("data")
+            .join("logs");
+
+        println!("[LogStore] 日志目录: {}", storage_dir.display());
+
+        Self::with_storage_path(storage_dir, LogRetention::from_env())
+    }
+
+    pub fn with_storage_path(storage_dir: PathBuf, retention: LogRetention) -> Self {
+        let logs = match Self::load_from_path(&storage_dir, &retention) {
+            Ok(logs) => {
+                println!("[LogStore] 加载了 {} 个函数的调用记录", logs.len());
+                logs
+            }
+            Err(e) => {
+                eprintln!("[LogStore] 加载失败: {}", e);
+                HashMap::new()
+            }
+        };
+
+        let (sender, _) = broadcast::channel(256);
+
+        Self {
+            logs: Arc::new(RwLock::new(logs)),
+            storage_dir,
+            retention,
+            sender,
+        }
+    }
+
+    /// 读取日志目录下的所有 JSONL 文件（跳过损坏的行）
+    fn load_from_path(
+        dir: &PathBuf,
+        retention: &LogRetention,
+    ) -> Result<HashMap<String, FunctionLog>, String> {
+        let mut logs = HashMap::new();
+        if !dir.exists() {
+            return Ok(logs);
+        }
+
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read log directory: {}", e))?;
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("jsonl") {
+                continue;
+            }
+            let Some(function_id) = path.file_stem().and_then(|s| s.to_str()).map(String::from) else {
+                continue;
+            };
+
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read log file: {}", e))?;
+            let lines = content.lines().count();
+            let mut log = FunctionLog {
+                records: content
+                    .lines()
+                    .filter_map(|line| serde_json::from_str(line).ok())
+                    .collect(),
+                file_lines: lines,
+            };
+            Self::prune(&mut log.records, retention, Utc::now());
+            logs.insert(function_id, log);
+        }
+
+        Ok(logs)
+    }
+
+    fn log_path(&self, function_id: &str) -> PathBuf {
+        self.storage_dir.join(format!("{}.jsonl", function_id))
+    }
+
+    /// 按条数和时长裁剪
+    fn prune(records: &mut VecDeque<InvocationRecord>, retention: &LogRetention, now: DateTime<Utc>) {
+        while records.len() > retention.max_records {
+            records.pop_front();
+        }
+        let cutoff = now - retention.max_age;
+        while records.front().is_some_and(|r| r.timestamp < cutoff) {
+            records.pop_front();
+        }
+    }
+
+    /// 写入一条调用记录
+    pub async fn record(&self, record: InvocationRecord) {
+        let mut logs = self.logs.write().await;
+        let log = logs.entry(record.function_id.clone()).or_default();
+
+        log.records.push_back(record.clone());
+        Self::prune(&mut log.records, &self.retention, Utc::now());
+
+        // 追加到文件；文件过长时按内存中的记录重写
+        let result = if log.file_lines >= self.retention.max_records * 2 {
+            self.rewrite(&record.function_id, &log.records).map(|_| {
+                log.file_lines = log.records.len();
+            })
+        } else {
+            self.append(&record).map(|_| {
+                log.file_lines += 1;
+            })
+        };
+        if let Err(e) = result {
+            eprintln!("[LogStore] 写入失败: {}", e);
+        }
+        drop(logs);
+
+        // 没有订阅者时发送失败，忽略即可
+        let _ = self.sender.send(record);
+    }
+
+    fn append(&self, record: &InvocationRecord) -> Result<(), String> {
+        std::fs::create_dir_all(&self.storage_dir)
+            .map_err(|e| format!("Failed to create log directory: {}", e))?;
+
+        let line = serde_json::to_string(record)
+            .map_err(|e| format!("Failed to serialize record: {}", e))?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path(&record.function_id))
+            .map_err(|e| format!("Failed to open log file: {}", e))?;
+        writeln!(file, "{}", line).map_err(|e| format!("Failed to write log file: {}", e))
+    }
+
+    fn rewrite(&self, function_id: &str, records: &VecDeque<InvocationRecord>) -> Result<(), String> {
+        std::fs::create_dir_all(&self.storage_dir)
+            .map_err(|e| format!("Failed to create log directory: {}", e))?;
+
+        let mut content = String::new();
+        for record in records {
+            let line = serde_json::to_string(record)
+                .map_err(|e| format!("Failed to serialize record: {}", e))?;
+            content.push_str(&line);
+            content.push('\n');
+        }
+        std::fs::write(self.log_path(function_id), content)
+            .map_err(|e| format!("Failed to write log file: {}", e))
+    }
+
+    /// 查询函数的调用记录（最新的在前）
+    pub async fn query(&self, function_id: &str, query: &LogQuery) -> LogPage {
+        let logs = self.logs.read().await;
+        let cutoff = Utc::now() - self.retention.max_age;
+
+        let matched: Vec<&InvocationRecord> = logs
+            .get(function_id)
+            .map(|log| {
+                log.records
+                    .iter()
+                    .rev()
+                    .filter(|r| r.timestamp >= cutoff && query.matches(r))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let limit = query.limit.unwrap_or(50).clamp(1, MAX_PAGE_SIZE);
+        let total = matched.len();
+        let records: Vec<InvocationRecord> = matched
+            .into_iter()
+            .skip(query.offset)
+            .take(limit)
+            .cloned()
+            .collect();
+        let end = query.offset + records.len();
+
+        LogPage {
+            records,
+            total,
+            next_offset: (end < total).then_some(end),
+        }
+    }
+
+    /// 订阅新的调用记录
+    pub fn subscribe(&self) -> broadcast::Receiver<InvocationRecord> {
+        self.sender.subscribe()
+    }
+
+    /// 删除函数的全部调用记录
+    pub async fn delete_function(&self, function_id: &str) {
+        self.logs.write().await.remove(function_id);
+        let path = self.log_path(function_id);
+        if path.exists() {
+            if let Err(e) = std::fs::remove_file(&path) {
+                eprintln!("[LogStore] 删除日志文件失败: {}", e);
+            }
+        }
+    }
+}
+
+impl Default for LogStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(function_id: &str, status: u16, log: &str) -> InvocationRecord {
+        InvocationRecord {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            function_id: function_id.to_string(),
+            project_id: "default".to_string(),
+            version: 1,
+            timestamp: Utc::now(),
+            method: "GET".to_string(),
+            url: "/fn/hello".to_string(),
+            status,
+            duration_ms: 1,
+            memory_used_bytes: 0,
+            logs: vec![log.to_string()],
+            error: None,
+            failure_kind: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_query_and_reload() {
+        let dir = std::env::temp_dir().join(format!("nexo-logs-{}", uuid::Uuid::new_v4()));
+        let retention = LogRetention {
+            max_records: 3,
+            max_age: Duration::hours(1),
+        };
+
+        let store = LogStore::with_storage_path(dir.clone(), retention.clone());
+        let mut tail = store.subscribe();
+        for i in 0..5 {
+            let status = if i == 4 { 500 } else { 200 };
+            store.record(record("fn-1", status, &format!("[LOG] call {}", i))).await;
+        }
+        assert_eq!(tail.recv().await.unwrap().logs, vec!["[LOG] call 0".to_string()]);
+
+        // 只保留最近 3 条，最新的在前
+        let page = store.query("fn-1", &LogQuery::default()).await;
+        assert_eq!(page.total, 3);
+        assert_eq!(page.records[0].logs[0], "[LOG] call 4");
+
+        let errors = store
+            .query("fn-1", &LogQuery { errors: Some(true), ..Default::default() })
+            .await;
+        assert_eq!(errors.total, 1);
+
+        let search = store
+            .query("fn-1", &LogQuery { q: Some("call 3".to_string()), ..Default::default() })
+            .await;
+        assert_eq!(search.total, 1);
+
+        let first = store
+            .query("fn-1", &LogQuery { limit: Some(2), ..Default::default() })
+            .await;
+        assert_eq!(first.next_offset, Some(2));
+
+        // 重新加载后保留相同的记录
+        let reloaded = LogStore::with_storage_path(dir.clone(), retention);
+        assert_eq!(reloaded.query("fn-1", &LogQuery::default()).await.total, 3);
+
+        reloaded.delete_function("fn-1").await;
+        assert_eq!(reloaded.query("fn-1", &LogQuery::default()).await.total, 0);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -4,18 +4,40 @@ mod api;
 mod function;
 mod pool;
 mod site;
+mod secret;
+mod project;
+mod auth;
+mod policy;
+mod ratelimit;
+mod worker;
+mod histogram;
+mod metrics;
+mod telemetry;
+mod logs;
+mod shutdown;
+mod health;
+mod blob;
+mod archive;
+mod compress;
+mod cache;
+mod rules;
+mod rewrite;
+mod edge;
 
 use anyhow::Result;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // 初始化日志
+    // 初始化日志和链路追踪
+    let tracer = telemetry::init_tracer()?;
+    let otel_enabled = tracer.is_some();
     tracing_subscriber::registry()
         .with(tracing_subscriber::fmt::layer())
         .with(tracing_subscriber::EnvFilter::new(
             std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
         ))
+        .with(tracer.map(|t| tracing_opentelemetry::layer().with_tracer(t)))
         .init();
 
     tracing::info!("🚀 Nexo Serverless Runtime v{}", env!("CARGO_PKG_VERSION"));
@@ -25,8 +47,13 @@ async fn main() -> Result<()> {
     isolate::init_v8();
     tracing::info!("✅ V8 engine initialized");
 
+    if otel_enabled {
+        tracing::info!("📡 OpenTelemetry OTLP export enabled");
+    }
+
     // 启动 API 服务器
-    api::start_server().await?;
+    let result = api::start_server().await;
 
-    Ok(())
+    telemetry::shutdown();
+    result
 }
@@ -0,0 +1,244 @@
+//! Metrics - Prometheus 指标导出
+//!
+//! 汇总 Isolate 池、网关响应和站点访问的计数，以 Prometheus 文本格式输出。
+
+use crate::histogram::{DurationBuckets, DURATION_BUCKETS_MS};
+use crate::pool::{FunctionStats, PoolStats};
+use std::collections::HashMap;
+use std::fmt::{Display, Write};
+
+/// 网关响应计数
+#[derive(Default)]
+pub struct GatewayMetrics {
+    /// (函数 ID, 状态码) -> 次数，未匹配到函数时函数 ID 为空
+    responses: parking_lot::Mutex<HashMap<(String, u16), u64>>,
+}
+
+impl GatewayMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一次网关响应
+    pub fn record_response(&self, function_id: Option<&str>, status: u16) {
+        *self
+            .responses
+            .lock()
+            .entry((function_id.unwrap_or_default().to_string(), status))
+            .or_insert(0) += 1;
+    }
+
+    /// 移除函数的计数
+    pub fn remove_function(&self, function_id: &str) {
+        self.responses.lock().retain(|(id, _), _| id != function_id);
+    }
+
+    /// (函数 ID, 状态码, 次数)
+    pub fn responses(&self) -> Vec<(String, u16, u64)> {
+        self.responses
+            .lock()
+            .iter()
+            .map(|((id, status), count)| (id.clone(), *status, *count))
+            .collect()
+    }
+}
+
+/// 单个函数的指标
+pub struct FunctionMetrics {
+    pub id: String,
+    pub name: String,
+    pub stats: FunctionStats,
+    pub duration: DurationBuckets,
+}
+
+/// 单个站点的指标
+pub struct SiteMetrics {
+    pub id: String,
+    pub name: String,
+    pub visits: u64,
+}
+
+/// 导出时的指标快照
+pub struct MetricsSnapshot {
+    pub pool: PoolStats,
+    pub functions: Vec<FunctionMetrics>,
+    /// (函数 ID, 状态码, 次数)
+    pub responses: Vec<(String, u16, u64)>,
+    pub sites: Vec<SiteMetrics>,
+}
+
+/// Prometheus 文本格式写入器
+struct Exposition {
+    out: String,
+}
+
+impl Exposition {
+    fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            self.out.push('{');
+            for (i, (key, val)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.out.push(',');
+                }
+                let _ = write!(self.out, "{}=\"{}\"", key, escape_label(val));
+            }
+            self.out.push('}');
+        }
+        let _ = writeln!(self.out, " {}", value);
+    }
+}
+
+/// 转义标签值中的反斜杠、引号和换行
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// 输出 Prometheus 文本格式
+pub fn render(snapshot: &MetricsSnapshot) -> String {
+    let mut e = Exposition { out: String::new() };
+    let pool = &snapshot.pool;
+
+    // 池级指标
+    e.header("nexo_pool_executions_total", "counter", "Total isolate executions.");
+    e.sample("nexo_pool_executions_total", &[], pool.total_executions);
+
+    e.header("nexo_pool_failures_total", "counter", "Failed isolate executions by kind.");
+    let other = pool
+        .failed_executions
+        .saturating_sub(pool.timeouts + pool.out_of_memory + pool.cancelled);
+    e.sample("nexo_pool_failures_total", &[("kind", "error")], other);
+    e.sample("nexo_pool_failures_total", &[("kind", "timeout")], pool.timeouts);
+    e.sample("nexo_pool_failures_total", &[("kind", "out_of_memory")], pool.out_of_memory);
+    e.sample("nexo_pool_failures_total", &[("kind", "cancelled")], pool.cancelled);
+
+    e.header("nexo_pool_rejected_total", "counter", "Executions shed by admission control.");
+    e.sample("nexo_pool_rejected_total", &[("reason", "queue_full")], pool.rejected_executions);
+    e.sample("nexo_pool_rejected_total", &[("reason", "queue_timeout")], pool.queue_timeouts);
+
+    e.header("nexo_pool_queue_depth", "gauge", "Executions waiting for an isolate.");
+    e.sample("nexo_pool_queue_depth", &[], pool.queue_depth);
+    e.header("nexo_pool_queue_capacity", "gauge", "Maximum number of queued executions.");
+    e.sample("nexo_pool_queue_capacity", &[], pool.max_queue);
+
+    e.header("nexo_pool_concurrent", "gauge", "Executions currently running.");
+    e.sample("nexo_pool_concurrent", &[], pool.current_concurrent);
+    e.header("nexo_pool_max_concurrent", "gauge", "Maximum concurrent executions.");
+    e.sample("nexo_pool_max_concurrent", &[], pool.max_concurrent);
+
+    // 函数级指标
+    e.header("nexo_function_executions_total", "counter", "Executions per function.");
+    for f in &snapshot.functions {
+        let labels = [("function_id", f.id.as_str()), ("function", f.name.as_str())];
+        e.sample("nexo_function_executions_total", &labels, f.stats.invocations);
+    }
+
+    e.header("nexo_function_failures_total", "counter", "Failed executions per function by kind.");
+    for f in &snapshot.functions {
+        let s = &f.stats;
+        let other = s.failed.saturating_sub(s.timeouts + s.out_of_memory + s.cancelled);
+        let kinds = [
+            ("error", other),
+            ("timeout", s.timeouts),
+            ("out_of_memory", s.out_of_memory),
+            ("cancelled", s.cancelled),
+        ];
+        for (kind, value) in kinds {
+            let labels = [("function_id", f.id.as_str()), ("function", f.name.as_str()), ("kind", kind)];
+            e.sample("nexo_function_failures_total", &labels, value);
+        }
+    }
+
+    e.header("nexo_function_duration_ms", "histogram", "Execution time per function in milliseconds.");
+    for f in &snapshot.functions {
+        let counts = f.duration.cumulative_counts();
+        for (bound, count) in DURATION_BUCKETS_MS.iter().zip(counts) {
+            let le = bound.to_string();
+            let labels = [("function_id", f.id.as_str()), ("function", f.name.as_str()), ("le", le.as_str())];
+            e.sample("nexo_function_duration_ms_bucket", &labels, count);
+        }
+        let labels = [("function_id", f.id.as_str()), ("function", f.name.as_str()), ("le", "+Inf")];
+        e.sample("nexo_function_duration_ms_bucket", &labels, f.duration.count());
+
+        let labels = [("function_id", f.id.as_str()), ("function", f.name.as_str())];
+        e.sample("nexo_function_duration_ms_sum", &labels, f.duration.sum());
+        e.sample("nexo_function_duration_ms_count", &labels, f.duration.count());
+    }
+
+    // 网关响应
+    let names: HashMap<&str, &str> = snapshot
+        .functions
+        .iter()
+        .map(|f| (f.id.as_str(), f.name.as_str()))
+        .collect();
+    e.header("nexo_gateway_responses_total", "counter", "Gateway responses by function and HTTP status.");
+    for (function_id, status, count) in &snapshot.responses {
+        let status = status.to_string();
+        let name = names.get(function_id.as_str()).copied().unwrap_or_default();
+        let labels = [("function_id", function_id.as_str()), ("function", name), ("status", status.as_str())];
+        e.sample("nexo_gateway_responses_total", &labels, count);
+    }
+
+    // 站点访问
+    e.header("nexo_site_hits_total", "counter", "Static site hits.");
+    for site in &snapshot.sites {
+        let labels = [("site_id", site.id.as_str()), ("site", site.name.as_str())];
+        e.sample("nexo_site_hits_total", &labels, site.visits);
+    }
+
+    e.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut duration = DurationBuckets::default();
+        duration.record(3);
+        duration.record(40);
+
+        let gateway = GatewayMetrics::new();
+        gateway.record_response(Some("fn-1"), 200);
+        gateway.record_response(Some("fn-1"), 200);
+        gateway.record_response(None, 404);
+
+        let snapshot = MetricsSnapshot {
+            pool: PoolStats {
+                total_executions: 2,
+                failed_executions: 1,
+                timeouts: 1,
+                ..Default::default()
+            },
+            functions: vec![FunctionMetrics {
+                id: "fn-1".to_string(),
+                name: "say \"hi\"".to_string(),
+                stats: FunctionStats {
+                    invocations: 2,
+                    ..Default::default()
+                },
+                duration,
+            }],
+            responses: gateway.responses(),
+            sites: vec![],
+        };
+
+        let text = render(&snapshot);
+        assert!(text.contains("# TYPE nexo_function_duration_ms histogram"));
+        assert!(text.contains("nexo_pool_failures_total{kind=\"timeout\"} 1"));
+        assert!(text.contains(r#"nexo_function_executions_total{function_id="fn-1",function="say \"hi\""} 2"#));
+        assert!(text.contains(r#"nexo_function_duration_ms_bucket{function_id="fn-1",function="say \"hi\"",le="5"} 1"#));
+        assert!(text.contains(r#"nexo_function_duration_ms_bucket{function_id="fn-1",function="say \"hi\"",le="+Inf"} 2"#));
+        assert!(text.contains(r#"status="200"} 2"#));
+        assert!(text.contains(r#"nexo_gateway_responses_total{function_id="",function="",status="404"} 1"#));
+    }
+}
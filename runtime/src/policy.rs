@@ -0,0 +1,413 @@
+//! Auth policies - 函数级网关认证策略
+//!
+//! 函数可以配置一个可选的认证策略，网关在启动 Isolate 之前校验：
+//! - 静态 API Key（只保存哈希）
+//! - JWT（HS256 使用函数密钥，RS256 使用公钥），claims 通过 `ctx.auth` 传给函数
+//! - Basic Auth（只保存加盐的 PBKDF2 密码哈希）
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Basic Auth 密码哈希的 PBKDF2 迭代次数
+const PBKDF2_ROUNDS: u32 = 100_000;
+/// 密码哈希格式前缀
+const PBKDF2_SCHEME: &str = "pbkdf2-sha256";
+
+fn default_api_key_header() -> String {
+    "x-api-key".to_string()
+}
+
+/// JWT 签名算法
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum JwtAlgorithm {
+    HS256,
+    RS256,
+}
+
+/// JWT 校验配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JwtPolicy {
+    pub algorithm: JwtAlgorithm,
+    /// HS256：保存签名密钥的函数密钥名称
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// RS256：PEM 格式的公钥
+    #[serde(default)]
+    pub public_key: Option<String>,
+    #[serde(default)]
+    pub issuer: Option<String>,
+    #[serde(default)]
+    pub audience: Option<String>,
+    /// 必须匹配的 claims，不匹配返回 403
+    #[serde(default)]
+    pub required_claims: HashMap<String, serde_json::Value>,
+}
+
+/// 函数认证策略
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthPolicy {
+    /// 静态 API Key
+    ApiKey {
+        /// 读取 Key 的请求头
+        #[serde(default = "default_api_key_header")]
+        header: String,
+        /// 明文 Key（只写，保存前转换为哈希）
+        #[serde(default, skip_serializing)]
+        keys: Vec<String>,
+        /// Key 的 SHA-256
+        #[serde(default)]
+        key_hashes: Vec<String>,
+    },
+    /// JWT Bearer Token
+    Jwt(JwtPolicy),
+    /// HTTP Basic Auth
+    Basic {
+        #[serde(default)]
+        realm: Option<String>,
+        /// 用户名 -> 明文密码（只写，保存前转换为哈希）
+        #[serde(default, skip_serializing)]
+        users: HashMap<String, String>,
+        /// 用户名 -> `pbkdf2-sha256$轮数$盐$哈希`
+        #[serde(default)]
+        user_hashes: HashMap<String, String>,
+    },
+}
+
+/// 认证失败
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthFailure {
+    /// 缺少或无效的凭据（401），附带 WWW-Authenticate 质询
+    Unauthorized { message: String, challenge: String },
+    /// 凭据有效但无权访问（403）
+    Forbidden(String),
+    /// 策略配置错误，如引用的密钥不存在（500）
+    Misconfigured(String),
+}
+
+fn sha256_hex(input: &str) -> String {
+    Sha256::digest(input.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// 比较两个哈希，避免提前返回泄露时序信息
+fn hashes_equal(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn pbkdf2_sha256(password: &str, salt: &[u8], rounds: u32) -> [u8; 32] {
+    let mut output = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, rounds, &mut output);
+    output
+}
+
+/// 使用随机盐计算密码哈希
+fn hash_password(password: &str) -> String {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let hash = pbkdf2_sha256(password, &salt, PBKDF2_ROUNDS);
+    format!("{}${}${}${}", PBKDF2_SCHEME, PBKDF2_ROUNDS, BASE64.encode(salt), BASE64.encode(hash))
+}
+
+/// 解析 `pbkdf2-sha256$轮数$盐$哈希`，其他格式返回 `None`
+fn parse_password_hash(stored: &str) -> Option<(u32, Vec<u8>, &str)> {
+    let parts: Vec<&str> = stored.split('$').collect();
+    let [PBKDF2_SCHEME, rounds, salt, hash] = parts[..] else {
+        return None;
+    };
+    let rounds = rounds.parse::<u32>().ok().filter(|&r| r > 0)?;
+    Some((rounds, BASE64.decode(salt).ok()?, hash))
+}
+
+/// 校验密码，不是 PBKDF2 格式的哈希一律拒绝
+fn verify_password(password: &str, stored: &str) -> bool {
+    let Some((rounds, salt, hash)) = parse_password_hash(stored) else {
+        return false;
+    };
+    hashes_equal(hash, &BASE64.encode(pbkdf2_sha256(password, &salt, rounds)))
+}
+
+impl AuthPolicy {
+    /// 把明文凭据转换为哈希，并校验配置
+    pub fn prepare(&mut self) -> Result<(), String> {
+        match self {
+            AuthPolicy::ApiKey { header, keys, key_hashes } => {
+                *header = header.to_ascii_lowercase();
+                key_hashes.extend(keys.drain(..).map(|k| sha256_hex(&k)));
+                if key_hashes.is_empty() {
+                    return Err("API key policy requires at least one key".to_string());
+                }
+            }
+            AuthPolicy::Jwt(jwt) => match jwt.algorithm {
+                JwtAlgorithm::HS256 if jwt.secret.is_none() => {
+                    return Err("HS256 policy requires 'secret' (name of a function secret)".to_string());
+                }
+                JwtAlgorithm::RS256 => {
+                    let pem = jwt.public_key.as_deref().ok_or("RS256 policy requires 'public_key'")?;
+                    DecodingKey::from_rsa_pem(pem.as_bytes())
+                        .map_err(|e| format!("Invalid RS256 public key: {}", e))?;
+                }
+                _ => {}
+            },
+            AuthPolicy::Basic { users, user_hashes, .. } => {
+                user_hashes.extend(
+                    users
+                        .drain()
+                        .map(|(user, password)| {
+                            let hash = hash_password(&password);
+                            (user, hash)
+                        }),
+                );
+                if user_hashes.is_empty() {
+                    return Err("Basic auth policy requires at least one user".to_string());
+                }
+                if let Some((user, _)) = user_hashes.iter().find(|(_, hash)| parse_password_hash(hash).is_none()) {
+                    return Err(format!("Password hash for '{}' must use the {} scheme", user, PBKDF2_SCHEME));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 校验请求
+    ///
+    /// `headers` 的键为小写；`secrets` 为函数解密后的密钥。
+    /// 成功时返回传给 `ctx.auth` 的信息。
+    pub fn evaluate(
+        &self,
+        headers: &HashMap<String, String>,
+        secrets: &HashMap<String, String>,
+    ) -> Result<serde_json::Value, AuthFailure> {
+        match self {
+            AuthPolicy::ApiKey { header, key_hashes, .. } => {
+                let key = headers.get(header).ok_or_else(|| AuthFailure::Unauthorized {
+                    message: format!("Missing API key in '{}' header", header),
+                    challenge: "ApiKey".to_string(),
+                })?;
+                let hash = sha256_hex(key.trim());
+                if key_hashes.iter().any(|h| hashes_equal(h, &hash)) {
+                    // 哈希前缀用于区分调用方，不暴露 Key 本身
+                    Ok(serde_json::json!({ "type": "api_key", "key_id": &hash[..16] }))
+                } else {
+                    Err(AuthFailure::Unauthorized {
+                        message: "Invalid API key".to_string(),
+                        challenge: "ApiKey".to_string(),
+                    })
+                }
+            }
+            AuthPolicy::Jwt(jwt) => Self::evaluate_jwt(jwt, headers, secrets),
+            AuthPolicy::Basic { realm, user_hashes, .. } => {
+                let challenge = format!(
+                    "Basic realm=\"{}\"",
+                    realm.as_deref().unwrap_or("nexo")
+                );
+                let unauthorized = |message: &str| AuthFailure::Unauthorized {
+                    message: message.to_string(),
+                    challenge: challenge.clone(),
+                };
+
+                let encoded = headers
+                    .get("authorization")
+                    .and_then(|v| v.strip_prefix("Basic "))
+                    .ok_or_else(|| unauthorized("Missing basic credentials"))?;
+                let decoded = BASE64
+                    .decode(encoded.trim())
+                    .ok()
+                    .and_then(|b| String::from_utf8(b).ok())
+                    .ok_or_else(|| unauthorized("Malformed basic credentials"))?;
+                let (user, password) = decoded
+                    .split_once(':')
+                    .ok_or_else(|| unauthorized("Malformed basic credentials"))?;
+
+                match user_hashes.get(user) {
+                    Some(stored) if verify_password(password, stored) => {
+                        Ok(serde_json::json!({ "type": "basic", "user": user }))
+                    }
+                    _ => Err(unauthorized("Invalid username or password")),
+                }
+            }
+        }
+    }
+
+    fn evaluate_jwt(
+        jwt: &JwtPolicy,
+        headers: &HashMap<String, String>,
+        secrets: &HashMap<String, String>,
+    ) -> Result<serde_json::Value, AuthFailure> {
+        let unauthorized = |message: String| AuthFailure::Unauthorized {
+            message,
+            challenge: "Bearer".to_string(),
+        };
+
+        let token = headers
+            .get("authorization")
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::trim)
+            .ok_or_else(|| unauthorized("Missing bearer token".to_string()))?;
+
+        let (algorithm, key) = match jwt.algorithm {
+            JwtAlgorithm::HS256 => {
+                let secret = jwt
+                    .secret
+                    .as_ref()
+                    .and_then(|name| secrets.get(name))
+                    .ok_or_else(|| AuthFailure::Misconfigured("JWT secret is not configured".to_string()))?;
+                (Algorithm::HS256, DecodingKey::from_secret(secret.as_bytes()))
+            }
+            JwtAlgorithm::RS256 => {
+                let key = jwt
+                    .public_key
+                    .as_deref()
+                    .and_then(|pem| DecodingKey::from_rsa_pem(pem.as_bytes()).ok())
+                    .ok_or_else(|| AuthFailure::Misconfigured("JWT public key is invalid".to_string()))?;
+                (Algorithm::RS256, key)
+            }
+        };
+
+        let mut validation = Validation::new(algorithm);
+        match &jwt.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        if let Some(issuer) = &jwt.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+
+        let claims = jsonwebtoken::decode::<serde_json::Value>(token, &key, &validation)
+            .map_err(|e| unauthorized(format!("Invalid token: {}", e)))?
+            .claims;
+
+        for (name, expected) in &jwt.required_claims {
+            if claims.get(name) != Some(expected) {
+                return Err(AuthFailure::Forbidden(format!("Claim '{}' does not match", name)));
+            }
+        }
+
+        Ok(serde_json::json!({ "type": "jwt", "claims": claims }))
+    }
+}
+
+/// 认证结果中的调用方身份（API Key ID、Basic 用户名或 JWT 的 `sub`）
+pub fn auth_identity(auth: &serde_json::Value) -> Option<String> {
+    let identity = match auth.get("type")?.as_str()? {
+        "api_key" => auth.get("key_id"),
+        "basic" => auth.get("user"),
+        "jwt" => auth.get("claims")?.get("sub"),
+        _ => None,
+    }?;
+    identity.as_str().map(|id| format!("{}:{}", auth["type"].as_str().unwrap_or_default(), id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_api_key_policy() {
+        let mut policy = AuthPolicy::ApiKey {
+            header: "X-API-Key".to_string(),
+            keys: vec!["k1".to_string()],
+            key_hashes: vec![],
+        };
+        policy.prepare().unwrap();
+
+        let stored = serde_json::to_string(&policy).unwrap();
+        assert!(!stored.contains("\"k1\""));
+
+        let none = HashMap::new();
+        let auth = policy.evaluate(&headers(&[("x-api-key", "k1")]), &none).unwrap();
+        assert_eq!(auth_identity(&auth), Some(format!("api_key:{}", &sha256_hex("k1")[..16])));
+        assert!(matches!(
+            policy.evaluate(&headers(&[("x-api-key", "k2")]), &none),
+            Err(AuthFailure::Unauthorized { .. })
+        ));
+        assert!(matches!(
+            policy.evaluate(&HashMap::new(), &none),
+            Err(AuthFailure::Unauthorized { .. })
+        ));
+    }
+
+    #[test]
+    fn test_basic_policy() {
+        let mut policy = AuthPolicy::Basic {
+            realm: None,
+            users: [("bob".to_string(), "hunter2".to_string())].into_iter().collect(),
+            user_hashes: HashMap::new(),
+        };
+        policy.prepare().unwrap();
+
+        let good = format!("Basic {}", BASE64.encode("bob:hunter2"));
+        let bad = format!("Basic {}", BASE64.encode("bob:wrong"));
+        let none = HashMap::new();
+
+        let auth = policy.evaluate(&headers(&[("authorization", &good)]), &none).unwrap();
+        assert_eq!(auth["user"], "bob");
+        assert_eq!(auth_identity(&auth).as_deref(), Some("basic:bob"));
+        assert!(policy.evaluate(&headers(&[("authorization", &bad)]), &none).is_err());
+
+        // 加盐：相同的密码每次得到不同的哈希
+        let AuthPolicy::Basic { user_hashes, .. } = &policy else { unreachable!() };
+        assert!(user_hashes["bob"].starts_with("pbkdf2-sha256$"));
+        assert_ne!(hash_password("hunter2"), hash_password("hunter2"));
+
+        // 只接受 PBKDF2 格式的哈希
+        let mut unsalted = AuthPolicy::Basic {
+            realm: None,
+            users: HashMap::new(),
+            user_hashes: [("bob".to_string(), sha256_hex("bob:hunter2"))].into_iter().collect(),
+        };
+        assert!(unsalted.evaluate(&headers(&[("authorization", &good)]), &none).is_err());
+        assert!(unsalted.prepare().unwrap_err().contains("pbkdf2-sha256"));
+    }
+
+    #[test]
+    fn test_hs256_policy() {
+        let policy = AuthPolicy::Jwt(JwtPolicy {
+            algorithm: JwtAlgorithm::HS256,
+            secret: Some("JWT_SECRET".to_string()),
+            public_key: None,
+            issuer: None,
+            audience: None,
+            required_claims: [("role".to_string(), serde_json::json!("admin"))].into_iter().collect(),
+        });
+        let secrets = headers(&[("JWT_SECRET", "shh")]);
+
+        let sign = |claims: serde_json::Value| {
+            jsonwebtoken::encode(
+                &jsonwebtoken::Header::new(Algorithm::HS256),
+                &claims,
+                &jsonwebtoken::EncodingKey::from_secret(b"shh"),
+            )
+            .unwrap()
+        };
+        let exp = chrono::Utc::now().timestamp() + 60;
+
+        let admin = format!("Bearer {}", sign(serde_json::json!({ "sub": "u1", "role": "admin", "exp": exp })));
+        let auth = policy.evaluate(&headers(&[("authorization", &admin)]), &secrets).unwrap();
+        assert_eq!(auth["claims"]["sub"], "u1");
+        assert_eq!(auth_identity(&auth).as_deref(), Some("jwt:u1"));
+
+        let user = format!("Bearer {}", sign(serde_json::json!({ "sub": "u2", "role": "user", "exp": exp })));
+        assert!(matches!(
+            policy.evaluate(&headers(&[("authorization", &user)]), &secrets),
+            Err(AuthFailure::Forbidden(_))
+        ));
+
+        // 引用的密钥不存在是配置错误，不是认证失败
+        assert!(matches!(
+            policy.evaluate(&headers(&[("authorization", &admin)]), &HashMap::new()),
+            Err(AuthFailure::Misconfigured(_))
+        ));
+    }
+}
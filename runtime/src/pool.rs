@@ -2,12 +2,22 @@
 //!
 //! 由于 V8 Isolate 创建非常快（< 5ms），我们采用按需创建的策略，
 //! 而不是维护预热池。使用 Semaphore 控制最大并发数。
+//!
+//! 没有空闲许可时请求进入有界等待队列：队列已满立即拒绝，排队超时也会拒绝，
+//! 调用方据此返回 503。管理类调用可以使用预留的许可和预留的排队位置，不会被公网流量堵住。
+//!
+//! 获得许可后，V8 工作在固定大小的工作线程池（见 `worker`）上执行。
 
-use crate::isolate::{NexoIsolate, IsolateConfig, ExecutionResult};
+use crate::histogram::{DurationBuckets, LatencyRecorder, LatencyStats};
+use crate::isolate::{CancelToken, NexoIsolate, IsolateConfig, ExecutionResult, FailureKind, ScriptError};
+use crate::project::ProjectQuotas;
+use crate::worker::{WorkerConfig, WorkerPool};
 use std::sync::Arc;
-use tokio::sync::{Semaphore, RwLock};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, RwLock};
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
 
 /// 池统计信息
 #[derive(Debug, Clone, Default, serde::Serialize)]
@@ -15,11 +25,112 @@ pub struct PoolStats {
     pub total_executions: u64,
     pub successful_executions: u64,
     pub failed_executions: u64,
+    /// 执行超时次数（计入 failed_executions）
+    pub timeouts: u64,
+    /// 超出堆内存上限次数（计入 failed_executions）
+    pub out_of_memory: u64,
+    /// 被取消次数（客户端断开，计入 failed_executions）
+    pub cancelled: u64,
     pub total_execution_time_ms: u64,
     pub avg_execution_time_ms: f64,
     pub current_concurrent: usize,
     pub max_concurrent: usize,
     pub total_memory_used_bytes: u64,
+    /// V8 工作线程数
+    pub v8_workers: usize,
+    /// 当前排队的请求数
+    pub queue_depth: usize,
+    /// 最大排队数
+    pub max_queue: usize,
+    /// 因队列已满被拒绝的请求数
+    pub rejected_executions: u64,
+    /// 排队超时的请求数
+    pub queue_timeouts: u64,
+    /// 经过排队才获得许可的请求数
+    pub queued_executions: u64,
+    pub total_queue_wait_ms: u64,
+    pub avg_queue_wait_ms: f64,
+    pub max_queue_wait_ms: u64,
+    /// 执行时间、排队等待和内存的分位数
+    pub latency: LatencyStats,
+}
+
+/// 调用优先级
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// 公网网关流量
+    Public,
+    /// 管理 API 发起的调用（部署检查、手动调用），可使用预留许可
+    Management,
+}
+
+/// 池配置
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// 最大并发数（包含预留给管理调用的许可）
+    pub max_concurrent: usize,
+    /// 最大排队数，超过时立即拒绝
+    pub max_queue: usize,
+    /// 最长排队时间
+    pub queue_timeout: Duration,
+    /// 预留给管理调用的许可数
+    pub reserved_management: usize,
+    /// 队列中预留给管理调用的位置数（公网请求最多排 `max_queue - reserved_management_queue` 个）
+    pub reserved_management_queue: usize,
+    /// V8 工作线程配置
+    pub workers: WorkerConfig,
+}
+
+impl PoolConfig {
+    pub fn new(max_concurrent: usize) -> Self {
+        let max_queue = max_concurrent * 10;
+        Self {
+            max_concurrent,
+            max_queue,
+            queue_timeout: Duration::from_secs(5),
+            reserved_management: 0,
+            reserved_management_queue: max_queue / 10,
+            workers: WorkerConfig::new(),
+        }
+    }
+
+    /// 从环境变量读取：NEXO_MAX_QUEUE、NEXO_QUEUE_TIMEOUT_MS、NEXO_RESERVED_MANAGEMENT、
+    /// NEXO_RESERVED_MANAGEMENT_QUEUE（默认为队列长度的 1/10）
+    pub fn from_env(max_concurrent: usize) -> Self {
+        fn env<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok().and_then(|s| s.parse().ok())
+        }
+
+        let defaults = Self::new(max_concurrent);
+        let max_queue = env("NEXO_MAX_QUEUE").unwrap_or(defaults.max_queue);
+        Self {
+            max_concurrent,
+            max_queue,
+            queue_timeout: env("NEXO_QUEUE_TIMEOUT_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.queue_timeout),
+            reserved_management: env("NEXO_RESERVED_MANAGEMENT").unwrap_or(defaults.reserved_management),
+            reserved_management_queue: env("NEXO_RESERVED_MANAGEMENT_QUEUE").unwrap_or(max_queue / 10),
+            workers: WorkerConfig::from_env(),
+        }
+    }
+}
+
+/// 排队计数守卫（排队的请求被取消时也能正确减少计数）
+struct QueueGuard<'a>(&'a AtomicUsize);
+
+impl Drop for QueueGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// 准入许可：持有期间占用项目配额和全局并发
+struct Admission {
+    _project_permits: Vec<OwnedSemaphorePermit>,
+    _permit: OwnedSemaphorePermit,
+    /// 排队等待时间
+    queue_wait_ms: u64,
 }
 
 /// 函数级别的统计
@@ -28,60 +139,422 @@ pub struct FunctionStats {
     pub invocations: u64,
     pub successful: u64,
     pub failed: u64,
+    pub timeouts: u64,
+    pub out_of_memory: u64,
+    pub cancelled: u64,
     pub total_time_ms: u64,
     pub avg_time_ms: f64,
     pub last_execution_ms: u64,
+    /// 执行时间、排队等待和内存的分位数
+    pub latency: LatencyStats,
+}
+
+/// 项目配额限制器
+struct ProjectLimiter {
+    /// 并发配额
+    concurrency: Option<Arc<Semaphore>>,
+    /// 内存配额（每个许可代表 1MB 堆上限）
+    memory: Option<Arc<Semaphore>>,
+    /// 并发配额总量
+    max_concurrent: usize,
+    /// 内存配额总量（MB）
+    max_memory_mb: u32,
+}
+
+/// 调整配额信号量的许可总量
+///
+/// 增加时直接补充许可；减少时先收回空闲的许可，其余的在执行中的许可释放后收回，
+/// 收回之前新的执行继续等待，已持有许可的执行不会让项目超出新配额。
+fn resize_quota(semaphore: &Arc<Semaphore>, from: usize, to: usize) {
+    if to >= from {
+        semaphore.add_permits(to - from);
+        return;
+    }
+
+    let shrink = from - to;
+    let pending = shrink - semaphore.forget_permits(shrink);
+    if pending == 0 {
+        return;
+    }
+    // 没有 tokio 运行时时不会有执行持有许可
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        let semaphore = Arc::clone(semaphore);
+        handle.spawn(async move {
+            if let Ok(permits) = semaphore.acquire_many_owned(pending as u32).await {
+                permits.forget();
+            }
+        });
+    }
+}
+
+impl ProjectLimiter {
+    /// 一次执行需要的内存许可数（MB），超过配额总量时返回错误
+    fn memory_permits(&self, heap_size_bytes: usize) -> Result<u32, String> {
+        let memory_mb = heap_size_bytes.div_ceil(1024 * 1024).max(1);
+        if memory_mb > self.max_memory_mb as usize {
+            return Err(format!(
+                "Memory limit {}MB exceeds project quota of {}MB",
+                memory_mb, self.max_memory_mb
+            ));
+        }
+        Ok(memory_mb as u32)
+    }
 }
 
 /// Isolate 池
 pub struct IsolatePool {
-    /// 最大并发数（用于初始化信号量）
-    #[allow(dead_code)]
-    max_concurrent: usize,
-    /// 并发控制信号量
+    /// 池配置
+    config: PoolConfig,
+    /// 并发控制信号量（所有调用共享）
     semaphore: Arc<Semaphore>,
+    /// 预留给管理调用的许可
+    reserved: Arc<Semaphore>,
+    /// 当前排队数
+    queued: AtomicUsize,
+    /// V8 工作线程
+    workers: WorkerPool,
     /// 当前并发计数
     current_concurrent: AtomicU64,
     /// 全局统计
     stats: Arc<RwLock<PoolStats>>,
     /// 函数级统计
     function_stats: Arc<RwLock<HashMap<String, FunctionStats>>>,
+    /// 项目配额
+    project_limiters: parking_lot::RwLock<HashMap<String, Arc<ProjectLimiter>>>,
+    /// 全局分布统计
+    latency: parking_lot::Mutex<LatencyRecorder>,
+    /// 函数级分布统计
+    function_latency: parking_lot::Mutex<HashMap<String, LatencyRecorder>>,
+    /// 函数级并发槽：函数 ID -> (上限, 信号量)
+    function_slots: parking_lot::Mutex<HashMap<String, (u32, Arc<Semaphore>)>>,
 }
 
 impl IsolatePool {
     /// 创建新的 Isolate 池
     pub fn new(max_concurrent: usize) -> Self {
+        Self::with_config(PoolConfig::new(max_concurrent))
+    }
+
+    /// 使用指定配置创建 Isolate 池
+    pub fn with_config(config: PoolConfig) -> Self {
         // 初始化 V8（只需一次）
         crate::isolate::init_v8();
 
+        let workers = WorkerPool::new(config.workers.clone());
+        let v8_workers = workers.threads();
+
+        // 至少保留一个公共许可
+        let reserved = config.reserved_management.min(config.max_concurrent.saturating_sub(1));
+
         Self {
-            max_concurrent,
-            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            semaphore: Arc::new(Semaphore::new(config.max_concurrent - reserved)),
+            reserved: Arc::new(Semaphore::new(reserved)),
+            queued: AtomicUsize::new(0),
+            workers,
             current_concurrent: AtomicU64::new(0),
             stats: Arc::new(RwLock::new(PoolStats {
-                max_concurrent,
+                max_concurrent: config.max_concurrent,
+                max_queue: config.max_queue,
+                v8_workers,
                 ..Default::default()
             })),
             function_stats: Arc::new(RwLock::new(HashMap::new())),
+            project_limiters: parking_lot::RwLock::new(HashMap::new()),
+            latency: parking_lot::Mutex::new(LatencyRecorder::default()),
+            function_latency: parking_lot::Mutex::new(HashMap::new()),
+            function_slots: parking_lot::Mutex::new(HashMap::new()),
+            config,
+        }
+    }
+
+    /// 尝试占用函数的并发槽（不等待），已满时返回 None
+    ///
+    /// 在获取全局许可之前调用，使单个热点函数无法占满全局并发。
+    pub fn try_acquire_function_slot(
+        &self,
+        function_id: &str,
+        max_concurrency: u32,
+    ) -> Option<OwnedSemaphorePermit> {
+        let semaphore = {
+            let mut slots = self.function_slots.lock();
+            let entry = slots
+                .entry(function_id.to_string())
+                .or_insert_with(|| (max_concurrency, Arc::new(Semaphore::new(max_concurrency as usize))));
+            // 上限变化时换用新的信号量，旧许可在旧信号量上释放
+            if entry.0 != max_concurrency {
+                *entry = (max_concurrency, Arc::new(Semaphore::new(max_concurrency as usize)));
+            }
+            Arc::clone(&entry.1)
+        };
+
+        semaphore.try_acquire_owned().ok()
+    }
+
+    /// 移除函数的并发槽和分布统计
+    pub fn remove_function(&self, function_id: &str) {
+        self.function_slots.lock().remove(function_id);
+        self.function_latency.lock().remove(function_id);
+    }
+
+    /// 设置项目配额
+    ///
+    /// 已有的配额在原信号量上调整总量，执行中的许可继续计入新配额。
+    pub fn set_project_quotas(&self, project_id: &str, quotas: &ProjectQuotas) {
+        let mut limiters = self.project_limiters.write();
+        if quotas.max_concurrent.is_none() && quotas.max_memory_mb.is_none() {
+            limiters.remove(project_id);
+            return;
+        }
+
+        let current = limiters.get(project_id);
+        let concurrency = quotas.max_concurrent.map(|n| match current.and_then(|l| l.concurrency.as_ref()) {
+            Some(semaphore) => {
+                resize_quota(semaphore, current.map_or(0, |l| l.max_concurrent), n);
+                Arc::clone(semaphore)
+            }
+            None => Arc::new(Semaphore::new(n)),
+        });
+        let memory = quotas.max_memory_mb.map(|mb| match current.and_then(|l| l.memory.as_ref()) {
+            Some(semaphore) => {
+                resize_quota(semaphore, current.map_or(0, |l| l.max_memory_mb as usize), mb as usize);
+                Arc::clone(semaphore)
+            }
+            None => Arc::new(Semaphore::new(mb as usize)),
+        });
+
+        let limiter = ProjectLimiter {
+            concurrency,
+            memory,
+            max_concurrent: quotas.max_concurrent.unwrap_or(0),
+            max_memory_mb: quotas.max_memory_mb.unwrap_or(0),
+        };
+        limiters.insert(project_id.to_string(), Arc::new(limiter));
+    }
+
+    /// 移除项目配额
+    pub fn remove_project_quotas(&self, project_id: &str) {
+        self.project_limiters.write().remove(project_id);
+    }
+
+    /// 获取项目配额许可（并发 + 内存），项目没有配额时返回空
+    async fn acquire_project_permits(
+        &self,
+        project_id: &str,
+        heap_size_bytes: usize,
+    ) -> Result<Vec<OwnedSemaphorePermit>, String> {
+        let limiter = self.project_limiters.read().get(project_id).cloned();
+        let Some(limiter) = limiter else {
+            return Ok(Vec::new());
+        };
+
+        let mut permits = Vec::new();
+
+        if let Some(memory) = &limiter.memory {
+            let permit = Arc::clone(memory)
+                .acquire_many_owned(limiter.memory_permits(heap_size_bytes)?)
+                .await
+                .map_err(|_| "Project quota closed".to_string())?;
+            permits.push(permit);
+        }
+
+        if let Some(concurrency) = &limiter.concurrency {
+            let permit = Arc::clone(concurrency)
+                .acquire_owned()
+                .await
+                .map_err(|_| "Project quota closed".to_string())?;
+            permits.push(permit);
+        }
+
+        Ok(permits)
+    }
+
+    /// 不等待地获取项目配额许可，配额已占满时返回 `Ok(None)`
+    fn try_acquire_project_permits(
+        &self,
+        project_id: &str,
+        heap_size_bytes: usize,
+    ) -> Result<Option<Vec<OwnedSemaphorePermit>>, String> {
+        let limiter = self.project_limiters.read().get(project_id).cloned();
+        let Some(limiter) = limiter else {
+            return Ok(Some(Vec::new()));
+        };
+
+        let mut permits = Vec::new();
+
+        if let Some(memory) = &limiter.memory {
+            match Arc::clone(memory).try_acquire_many_owned(limiter.memory_permits(heap_size_bytes)?) {
+                Ok(permit) => permits.push(permit),
+                Err(_) => return Ok(None),
+            }
+        }
+
+        if let Some(concurrency) = &limiter.concurrency {
+            match Arc::clone(concurrency).try_acquire_owned() {
+                Ok(permit) => permits.push(permit),
+                Err(_) => return Ok(None),
+            }
+        }
+
+        Ok(Some(permits))
+    }
+
+    /// 不等待地获取全局许可
+    fn try_acquire(&self, priority: Priority) -> Option<OwnedSemaphorePermit> {
+        Arc::clone(&self.semaphore).try_acquire_owned().ok().or_else(|| match priority {
+            Priority::Management => Arc::clone(&self.reserved).try_acquire_owned().ok(),
+            Priority::Public => None,
+        })
+    }
+
+    /// 等待全局许可
+    async fn acquire(&self, priority: Priority) -> Option<OwnedSemaphorePermit> {
+        let shared = Arc::clone(&self.semaphore).acquire_owned();
+        match priority {
+            Priority::Public => shared.await.ok(),
+            Priority::Management => {
+                let reserved = Arc::clone(&self.reserved).acquire_owned();
+                tokio::select! {
+                    permit = shared => permit.ok(),
+                    permit = reserved => permit.ok(),
+                }
+            }
+        }
+    }
+
+    /// 准入控制：获取项目配额和全局许可
+    ///
+    /// 没有空闲许可时进入等待队列。队列已满或排队超时返回
+    /// [`ExecutionResult::overloaded`]，项目配额配置错误返回普通失败。
+    async fn admit(&self, priority: Priority, config: &IsolateConfig) -> Result<Admission, ExecutionResult> {
+        // 快速路径：项目配额和全局许可都有空闲时不进入队列
+        let project_permits = self
+            .try_acquire_project_permits(&config.project_id, config.max_heap_size_bytes)
+            .map_err(ExecutionResult::failure)?;
+        if let Some(project_permits) = project_permits {
+            if let Some(permit) = self.try_acquire(priority) {
+                return Ok(Admission {
+                    _project_permits: project_permits,
+                    _permit: permit,
+                    queue_wait_ms: 0,
+                });
+            }
+        }
+
+        // 进入等待队列，公网请求不能占用预留给管理调用的位置
+        let capacity = match priority {
+            Priority::Public => self
+                .config
+                .max_queue
+                .saturating_sub(self.config.reserved_management_queue),
+            Priority::Management => self.config.max_queue,
+        };
+        let depth = self.queued.fetch_add(1, Ordering::SeqCst) + 1;
+        let _guard = QueueGuard(&self.queued);
+        if depth > capacity {
+            self.stats.write().await.rejected_executions += 1;
+            return Err(ExecutionResult::overloaded("Server is overloaded: execution queue is full"));
+        }
+
+        let start = Instant::now();
+        let wait = async {
+            // 先获取项目配额，再获取全局并发许可
+            let project_permits = self
+                .acquire_project_permits(&config.project_id, config.max_heap_size_bytes)
+                .await
+                .map_err(ExecutionResult::failure)?;
+            let permit = self
+                .acquire(priority)
+                .await
+                .ok_or_else(|| ExecutionResult::failure("Isolate pool closed"))?;
+            Ok(Admission {
+                _project_permits: project_permits,
+                _permit: permit,
+                queue_wait_ms: 0,
+            })
+        };
+
+        let mut admission = match tokio::time::timeout(self.config.queue_timeout, wait).await {
+            Ok(admission) => admission?,
+            Err(_) => {
+                self.stats.write().await.queue_timeouts += 1;
+                return Err(ExecutionResult::overloaded(format!(
+                    "Server is overloaded: no isolate available within {}ms",
+                    self.config.queue_timeout.as_millis()
+                )));
+            }
+        };
+
+        let waited_ms = start.elapsed().as_millis() as u64;
+        admission.queue_wait_ms = waited_ms;
+        {
+            let mut stats = self.stats.write().await;
+            stats.queued_executions += 1;
+            stats.total_queue_wait_ms += waited_ms;
+            stats.max_queue_wait_ms = stats.max_queue_wait_ms.max(waited_ms);
+            stats.avg_queue_wait_ms =
+                stats.total_queue_wait_ms as f64 / stats.queued_executions as f64;
         }
+
+        Ok(admission)
     }
 
     /// 在池中执行代码
+    pub async fn execute(
+        &self,
+        function_id: &str,
+        code: &str,
+        request_data: serde_json::Value,
+        config: Option<IsolateConfig>,
+        priority: Priority,
+    ) -> ExecutionResult {
+        self.execute_with_cancel(function_id, code, request_data, config, priority, &CancelToken::new())
+            .await
+    }
+
+    /// 在池中执行代码，`cancel` 被取消时停止排队或终止正在运行的脚本
     /// 
     /// 这个方法会：
-    /// 1. 获取并发许可
+    /// 1. 通过准入控制获取并发许可（可能排队或被拒绝）
     /// 2. 创建新的 Isolate
     /// 3. 执行代码
     /// 4. 释放许可并更新统计
-    pub async fn execute(
+    #[tracing::instrument(
+        name = "pool.execute",
+        skip_all,
+        fields(function_id = %function_id, queue_wait_ms, success)
+    )]
+    pub async fn execute_with_cancel(
         &self,
         function_id: &str,
         code: &str,
         request_data: serde_json::Value,
         config: Option<IsolateConfig>,
+        priority: Priority,
+        cancel: &CancelToken,
     ) -> ExecutionResult {
+        // 创建 Isolate 配置
+        let isolate_config = config.unwrap_or_else(|| {
+            IsolateConfig {
+                function_id: function_id.to_string(),
+                ..Default::default()
+            }
+        });
+
         // 获取并发许可
-        let _permit = self.semaphore.acquire().await.unwrap();
+        let admit = self
+            .admit(priority, &isolate_config)
+            .instrument(tracing::info_span!("pool.queue_wait"));
+        let admission = tokio::select! {
+            admitted = admit => match admitted {
+                Ok(admission) => admission,
+                Err(result) => return result,
+            },
+            // 排队期间被取消：放弃排队
+            _ = cancel.cancelled() => {
+                return cancel.cancelled_result().unwrap_or_else(|| ExecutionResult::failure("Cancelled"));
+            }
+        };
+        tracing::Span::current().record("queue_wait_ms", admission.queue_wait_ms);
         
         // 更新并发计数
         self.current_concurrent.fetch_add(1, Ordering::SeqCst);
@@ -92,49 +565,86 @@ impl IsolatePool {
             stats.current_concurrent = self.current_concurrent.load(Ordering::SeqCst) as usize;
         }
 
-        // 创建 Isolate 配置
-        let isolate_config = config.unwrap_or_else(|| {
-            IsolateConfig {
-                function_id: function_id.to_string(),
-                ..Default::default()
-            }
-        });
-
-        // 在独立线程中执行（V8 操作是同步的）
+        // 在 V8 工作线程中执行（V8 操作是同步的）
         let code = code.to_string();
         let function_id = function_id.to_string();
         
-        let result = tokio::task::spawn_blocking(move || {
-            let isolate = NexoIsolate::new(isolate_config);
-            isolate.execute(&code, request_data)
-        })
-        .await
-        .unwrap_or_else(|e| Ok(ExecutionResult {
-            success: false,
-            output: None,
-            error: Some(format!("Task panicked: {}", e)),
-            execution_time_ms: 0,
-            memory_used_bytes: 0,
-            logs: vec![],
-        }))
-        .unwrap_or_else(|e| ExecutionResult {
-            success: false,
-            output: None,
-            error: Some(e.to_string()),
-            execution_time_ms: 0,
-            memory_used_bytes: 0,
-            logs: vec![],
-        });
+        let span = tracing::info_span!("isolate.execute");
+        let cancel = cancel.clone();
+        let result = self
+            .workers
+            .run(move |worker| {
+                span.in_scope(|| worker.execute(isolate_config, &code, request_data, &cancel))
+            })
+            .await
+            .unwrap_or_else(ExecutionResult::failure);
+        tracing::Span::current().record("success", result.success);
 
         // 减少并发计数
         self.current_concurrent.fetch_sub(1, Ordering::SeqCst);
 
         // 更新统计
         self.update_stats(&function_id, &result).await;
+        self.record_latency(&function_id, &result, admission.queue_wait_ms);
 
         result
     }
 
+    /// 在池中编译检查代码（不执行、不计入执行统计）
+    pub async fn check(
+        &self,
+        code: &str,
+        config: Option<IsolateConfig>,
+    ) -> Result<(), ScriptError> {
+        let isolate_config = config.unwrap_or_default();
+        let _admission = self
+            .admit(Priority::Management, &isolate_config)
+            .await
+            .map_err(|result| ScriptError::new(result.error.unwrap_or_default()))?;
+
+        let code = code.to_string();
+
+        self.workers
+            .run(move |_| NexoIsolate::new(isolate_config).check(&code))
+            .await
+            .unwrap_or_else(|e| Err(ScriptError::new(e)))
+    }
+
+    /// 在 V8 工作线程上用新建的 Isolate 运行探测脚本，返回耗时
+    ///
+    /// 不经过准入控制，也不计入执行统计；超过 `deadline` 时终止脚本。
+    pub async fn probe(&self, deadline: Duration) -> Result<Duration, String> {
+        let start = Instant::now();
+        let cancel = CancelToken::new();
+        let token = cancel.clone();
+        let run = self.workers.run(move |_| {
+            let nexo = NexoIsolate::new(IsolateConfig {
+                function_id: "__probe__".to_string(),
+                max_execution_time_ms: deadline.as_millis() as u64,
+                max_heap_size_bytes: 16 * 1024 * 1024,
+                ..Default::default()
+            });
+            let mut isolate = nexo.create_isolate();
+            nexo.execute_in(&mut isolate, "function handler() { return 1 + 1; }", serde_json::json!({}), &token)
+                .unwrap_or_else(|e| ExecutionResult::failure(e.to_string()))
+        });
+
+        let result = match tokio::time::timeout(deadline, run).await {
+            Ok(result) => result?,
+            Err(_) => {
+                cancel.cancel(FailureKind::Timeout, "Probe timed out");
+                return Err(format!("V8 probe exceeded {}ms", deadline.as_millis()));
+            }
+        };
+
+        match result.output {
+            Some(serde_json::Value::Number(n)) if result.success && n.as_u64() == Some(2) => {
+                Ok(start.elapsed())
+            }
+            _ => Err(result.error.unwrap_or_else(|| "V8 probe returned an unexpected result".to_string())),
+        }
+    }
+
     /// 更新执行统计
     async fn update_stats(&self, function_id: &str, result: &ExecutionResult) {
         // 更新全局统计
@@ -150,6 +660,12 @@ impl IsolatePool {
             } else {
                 stats.failed_executions += 1;
             }
+            match result.failure_kind {
+                Some(FailureKind::Timeout) => stats.timeouts += 1,
+                Some(FailureKind::OutOfMemory) => stats.out_of_memory += 1,
+                Some(FailureKind::Cancelled) => stats.cancelled += 1,
+                _ => {}
+            }
 
             if stats.total_executions > 0 {
                 stats.avg_execution_time_ms =
@@ -173,6 +689,12 @@ impl IsolatePool {
             } else {
                 entry.failed += 1;
             }
+            match result.failure_kind {
+                Some(FailureKind::Timeout) => entry.timeouts += 1,
+                Some(FailureKind::OutOfMemory) => entry.out_of_memory += 1,
+                Some(FailureKind::Cancelled) => entry.cancelled += 1,
+                _ => {}
+            }
 
             if entry.invocations > 0 {
                 entry.avg_time_ms = entry.total_time_ms as f64 / entry.invocations as f64;
@@ -180,20 +702,58 @@ impl IsolatePool {
         }
     }
 
+    /// 记录执行时间、排队等待和内存分布
+    fn record_latency(&self, function_id: &str, result: &ExecutionResult, queue_wait_ms: u64) {
+        let record = |recorder: &mut LatencyRecorder| {
+            recorder.record_execution(result.execution_time_ms, result.memory_used_bytes as u64);
+            recorder.record_queue_wait(queue_wait_ms);
+        };
+
+        record(&mut self.latency.lock());
+        record(
+            self.function_latency
+                .lock()
+                .entry(function_id.to_string())
+                .or_default(),
+        );
+    }
+
+    /// 各函数自启动以来的执行时间直方图（用于导出指标）
+    pub fn execution_histograms(&self) -> HashMap<String, DurationBuckets> {
+        self.function_latency
+            .lock()
+            .iter()
+            .map(|(id, recorder)| (id.clone(), recorder.execution_histogram().clone()))
+            .collect()
+    }
+
     /// 获取全局统计
     pub async fn get_stats(&self) -> PoolStats {
-        self.stats.read().await.clone()
+        let mut stats = self.stats.read().await.clone();
+        stats.queue_depth = self.queued.load(Ordering::SeqCst);
+        stats.latency = self.latency.lock().snapshot();
+        stats
     }
 
     /// 获取函数统计
     pub async fn get_function_stats(&self, function_id: &str) -> Option<FunctionStats> {
-        self.function_stats.read().await.get(function_id).cloned()
+        let mut stats = self.function_stats.read().await.get(function_id).cloned()?;
+        if let Some(recorder) = self.function_latency.lock().get(function_id) {
+            stats.latency = recorder.snapshot();
+        }
+        Some(stats)
     }
 
     /// 获取所有函数统计
-    #[allow(dead_code)]
     pub async fn get_all_function_stats(&self) -> HashMap<String, FunctionStats> {
-        self.function_stats.read().await.clone()
+        let mut all = self.function_stats.read().await.clone();
+        let latency = self.function_latency.lock();
+        for (function_id, stats) in all.iter_mut() {
+            if let Some(recorder) = latency.get(function_id) {
+                stats.latency = recorder.snapshot();
+            }
+        }
+        all
     }
 
     /// 获取当前并发数
@@ -205,7 +765,7 @@ impl IsolatePool {
     /// 获取可用许可数
     #[allow(dead_code)]
     pub fn available_permits(&self) -> usize {
-        self.semaphore.available_permits()
+        self.semaphore.available_permits() + self.reserved.available_permits()
     }
 }
 
@@ -229,7 +789,7 @@ mod tests {
             }
         "#;
 
-        let result = pool.execute("test-fn", code, serde_json::json!({}), None).await;
+        let result = pool.execute("test-fn", code, serde_json::json!({}), None, Priority::Public).await;
         
         assert!(result.success);
         assert!(result.output.is_some());
@@ -247,12 +807,16 @@ mod tests {
 
         // 执行几次
         for _ in 0..3 {
-            pool.execute("test-fn", code, serde_json::json!({}), None).await;
+            pool.execute("test-fn", code, serde_json::json!({}), None, Priority::Public).await;
         }
 
         let stats = pool.get_stats().await;
         assert_eq!(stats.total_executions, 3);
         assert_eq!(stats.successful_executions, 3);
+        assert_eq!(stats.latency.execution_time_ms.last_1m.count, 3);
+
+        let function_stats = pool.get_function_stats("test-fn").await.unwrap();
+        assert_eq!(function_stats.latency.queue_wait_ms.last_1h.count, 3);
     }
 
     #[tokio::test]
@@ -277,6 +841,7 @@ mod tests {
                     &code,
                     serde_json::json!({ "id": i }),
                     None,
+                    Priority::Public,
                 ).await
             }));
         }
@@ -289,4 +854,155 @@ mod tests {
         let stats = pool.get_stats().await;
         assert_eq!(stats.total_executions, 5);
     }
+
+    #[tokio::test]
+    async fn test_admission_queue() {
+        let pool = IsolatePool::with_config(PoolConfig {
+            max_concurrent: 2,
+            max_queue: 1,
+            queue_timeout: Duration::from_millis(20),
+            reserved_management: 1,
+            reserved_management_queue: 0,
+            workers: WorkerConfig { threads: 1, pin_cpus: false },
+        });
+        let config = IsolateConfig::default();
+
+        // 占满公共许可
+        let _busy = pool.admit(Priority::Public, &config).await.unwrap();
+
+        // 排队超时
+        let timed_out = pool.admit(Priority::Public, &config).await.err().unwrap();
+        assert!(timed_out.is_overloaded());
+
+        // 管理调用使用预留许可
+        let _management = pool.admit(Priority::Management, &config).await.unwrap();
+
+        // 队列已满时立即拒绝
+        let pool = Arc::new(pool);
+        let waiting = {
+            let pool = Arc::clone(&pool);
+            tokio::spawn(async move {
+                pool.admit(Priority::Public, &IsolateConfig::default()).await.is_ok()
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let rejected = pool.admit(Priority::Public, &config).await.err().unwrap();
+        assert!(rejected.is_overloaded());
+        assert!(!waiting.await.unwrap());
+
+        let stats = pool.get_stats().await;
+        assert_eq!(stats.queue_timeouts, 2);
+        assert_eq!(stats.rejected_executions, 1);
+        assert_eq!(stats.queue_depth, 0);
+    }
+
+    #[tokio::test]
+    async fn test_reserved_management_queue() {
+        let pool = Arc::new(IsolatePool::with_config(PoolConfig {
+            max_concurrent: 1,
+            max_queue: 2,
+            queue_timeout: Duration::from_millis(50),
+            reserved_management: 0,
+            reserved_management_queue: 1,
+            workers: WorkerConfig { threads: 1, pin_cpus: false },
+        }));
+        let config = IsolateConfig::default();
+
+        let busy = pool.admit(Priority::Public, &config).await.unwrap();
+        let waiting = {
+            let pool = Arc::clone(&pool);
+            tokio::spawn(async move { pool.admit(Priority::Public, &IsolateConfig::default()).await.is_ok() })
+        };
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        // 公网请求不能使用预留位置，管理调用仍然可以排队
+        assert!(pool.admit(Priority::Public, &config).await.err().unwrap().is_overloaded());
+        let management = {
+            let pool = Arc::clone(&pool);
+            tokio::spawn(async move { pool.admit(Priority::Management, &IsolateConfig::default()).await.is_ok() })
+        };
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert_eq!(pool.get_stats().await.queue_depth, 2);
+
+        drop(busy);
+        assert!(waiting.await.unwrap());
+        assert!(management.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_project_quota_fast_path() {
+        let pool = IsolatePool::new(10);
+        pool.set_project_quotas("small", &ProjectQuotas {
+            max_concurrent: Some(2),
+            max_memory_mb: None,
+        });
+        let config = IsolateConfig {
+            project_id: "small".to_string(),
+            ..Default::default()
+        };
+
+        // 配额有空闲时不计入排队
+        let _first = pool.admit(Priority::Public, &config).await.unwrap();
+        let _second = pool.admit(Priority::Public, &config).await.unwrap();
+        assert_eq!(pool.get_stats().await.queued_executions, 0);
+    }
+
+    #[tokio::test]
+    async fn test_lower_project_quota_counts_running() {
+        let pool = IsolatePool::new(10);
+        let quotas = |n| ProjectQuotas { max_concurrent: Some(n), max_memory_mb: None };
+        pool.set_project_quotas("small", &quotas(2));
+
+        let first = pool.try_acquire_project_permits("small", 0).unwrap().unwrap();
+        let second = pool.try_acquire_project_permits("small", 0).unwrap().unwrap();
+
+        // 两个执行仍在运行时降到 1：释放一个之后仍然占满
+        pool.set_project_quotas("small", &quotas(1));
+        drop(first);
+        tokio::task::yield_now().await;
+        assert!(pool.try_acquire_project_permits("small", 0).unwrap().is_none());
+
+        drop(second);
+        tokio::task::yield_now().await;
+        let third = pool.try_acquire_project_permits("small", 0).unwrap();
+        assert!(third.is_some());
+        assert!(pool.try_acquire_project_permits("small", 0).unwrap().is_none());
+
+        // 提高配额立即生效
+        pool.set_project_quotas("small", &quotas(3));
+        assert!(pool.try_acquire_project_permits("small", 0).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_function_slots() {
+        let pool = IsolatePool::new(10);
+
+        let first = pool.try_acquire_function_slot("hot-fn", 1);
+        assert!(first.is_some());
+        assert!(pool.try_acquire_function_slot("hot-fn", 1).is_none());
+        assert!(pool.try_acquire_function_slot("other-fn", 1).is_some());
+
+        drop(first);
+        assert!(pool.try_acquire_function_slot("hot-fn", 1).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_project_memory_quota() {
+        let pool = IsolatePool::new(10);
+        pool.set_project_quotas("small", &ProjectQuotas {
+            max_concurrent: Some(1),
+            max_memory_mb: Some(64),
+        });
+
+        let config = IsolateConfig {
+            project_id: "small".to_string(),
+            max_heap_size_bytes: 128 * 1024 * 1024,
+            ..Default::default()
+        };
+
+        let code = "function handler() { return 1; }";
+        let result = pool.execute("test-fn", code, serde_json::json!({}), Some(config), Priority::Public).await;
+        assert!(!result.success);
+        assert!(result.error.unwrap().contains("project quota"));
+    }
 }
@@ -0,0 +1,431 @@
+//! Projects - 项目（租户）管理
+//!
+//! 每个项目拥有自己的函数、站点、密钥和路由，可以配置路由前缀或自定义域名，
+//! 以及在 Isolate 池中强制执行的并发和内存配额。
+//! 未指定项目的请求归属默认项目 `default`。
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// 默认项目 ID
+pub const DEFAULT_PROJECT: &str = "default";
+
+/// 项目配额
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProjectQuotas {
+    /// 最大并发执行数
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+    /// 同时运行的 Isolate 堆上限之和（MB）
+    #[serde(default)]
+    pub max_memory_mb: Option<u32>,
+}
+
+/// 项目
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Project {
+    /// 项目 ID（小写字母、数字和 '-'）
+    pub id: String,
+    /// 项目名称
+    pub name: String,
+    /// 网关路由前缀，例如 `/acme` 对应 `/fn/acme/*` 和 `/site/acme/*`
+    #[serde(default)]
+    pub route_prefix: Option<String>,
+    /// 绑定的域名（按 Host 头匹配）
+    #[serde(default)]
+    pub domains: Vec<String>,
+    /// 配额
+    #[serde(default)]
+    pub quotas: ProjectQuotas,
+    /// 创建时间
+    pub created_at: DateTime<Utc>,
+    /// 更新时间
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Project {
+    fn default_project() -> Self {
+        let now = Utc::now();
+        Self {
+            id: DEFAULT_PROJECT.to_string(),
+            name: "Default".to_string(),
+            route_prefix: None,
+            domains: Vec::new(),
+            quotas: ProjectQuotas::default(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// 网关上的公开路径（加上路由前缀）
+    pub fn public_path(&self, route: &str) -> String {
+        format!("{}{}", self.route_prefix.as_deref().unwrap_or(""), route)
+    }
+
+    /// 检查函数的内存限制是否超出项目配额
+    pub fn check_memory_limit(&self, max_memory_mb: u32) -> Result<(), String> {
+        match self.quotas.max_memory_mb {
+            Some(quota) if max_memory_mb > quota => Err(format!(
+                "Function memory limit {}MB exceeds project quota of {}MB",
+                max_memory_mb, quota
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 创建项目请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateProjectRequest {
+    pub id: String,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub route_prefix: Option<String>,
+    #[serde(default)]
+    pub domains: Vec<String>,
+    #[serde(default)]
+    pub quotas: ProjectQuotas,
+}
+
+/// 更新项目请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateProjectRequest {
+    pub name: Option<String>,
+    pub route_prefix: Option<String>,
+    pub domains: Option<Vec<String>>,
+    pub quotas: Option<ProjectQuotas>,
+}
+
+/// 持久化数据
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct PersistedProjects {
+    projects: HashMap<String, Project>,
+}
+
+/// 项目存储
+#[derive(Clone)]
+pub struct ProjectStore {
+    projects: Arc<RwLock<HashMap<String, Project>>>,
+    storage_path: PathBuf,
+}
+
+impl ProjectStore {
+    pub fn new() -> Self {
+        let storage_path = std::env::current_dir()
+            .unwrap_or_else(|_| PathBuf::from("."))
+            .join("data")
+            .join("projects.json");
+
+        println!("[ProjectStore] 数据存储路径: {}", storage_path.display());
+
+        Self::with_storage_path(storage_path)
+    }
+
+    pub fn with_storage_path(storage_path: PathBuf) -> Self {
+        let mut projects = match Self::load_from_path(&storage_path) {
+            Ok(data) => {
+                println!("[ProjectStore] 已加载 {} 个项目", data.projects.len());
+                data.projects
+            }
+            Err(_) => HashMap::new(),
+        };
+
+        // 默认项目始终存在
+        projects
+            .entry(DEFAULT_PROJECT.to_string())
+            .or_insert_with(Project::default_project);
+
+        Self {
+            projects: Arc::new(RwLock::new(projects)),
+            storage_path,
+        }
+    }
+
+    /// 从文件加载数据（静态方法）
+    fn load_from_path(path: &PathBuf) -> Result<PersistedProjects, String> {
+        if !path.exists() {
+            return Ok(PersistedProjects::default());
+        }
+
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read storage file: {}", e))?;
+
+        serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse storage file: {}", e))
+    }
+
+    async fn save(&self) -> Result<(), String> {
+        let data = PersistedProjects {
+            projects: self.projects.read().await.clone(),
+        };
+
+        if let Some(parent) = self.storage_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create storage directory: {}", e))?;
+        }
+
+        let content = serde_json::to_string_pretty(&data)
+            .map_err(|e| format!("Failed to serialize data: {}", e))?;
+
+        std::fs::write(&self.storage_path, content)
+            .map_err(|e| format!("Failed to write storage file: {}", e))?;
+
+        Ok(())
+    }
+
+    /// 校验项目 ID
+    fn validate_id(id: &str) -> Result<(), String> {
+        if id.is_empty() || id.len() > 63 {
+            return Err("Project id must be 1-63 characters".to_string());
+        }
+        if !id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+            return Err("Project id may only contain lowercase letters, digits and '-'".to_string());
+        }
+        Ok(())
+    }
+
+    /// 规范化路由前缀：`acme`、`/acme/` -> `/acme`
+    fn normalize_prefix(prefix: Option<String>) -> Result<Option<String>, String> {
+        let Some(prefix) = prefix else {
+            return Ok(None);
+        };
+        let trimmed = prefix.trim_matches('/');
+        if trimmed.is_empty() {
+            return Ok(None);
+        }
+        if trimmed.contains('/') {
+            return Err("Route prefix must be a single path segment".to_string());
+        }
+        Ok(Some(format!("/{}", trimmed)))
+    }
+
+    /// 规范化域名（小写、去掉端口）
+    fn normalize_domains(domains: Vec<String>) -> Vec<String> {
+        domains
+            .into_iter()
+            .map(|d| Self::host_name(&d).to_string())
+            .filter(|d| !d.is_empty())
+            .collect()
+    }
+
+    /// 从 Host 头中去掉端口并转为小写
+    pub fn host_name(host: &str) -> String {
+        host.trim()
+            .rsplit_once(':')
+            .filter(|(_, port)| port.chars().all(|c| c.is_ascii_digit()))
+            .map(|(name, _)| name)
+            .unwrap_or(host.trim())
+            .to_ascii_lowercase()
+    }
+
+    /// 检查前缀和域名是否与其他项目冲突
+    fn check_conflicts(
+        projects: &HashMap<String, Project>,
+        id: &str,
+        route_prefix: &Option<String>,
+        domains: &[String],
+    ) -> Result<(), String> {
+        for other in projects.values().filter(|p| p.id != id) {
+            if route_prefix.is_some() && other.route_prefix == *route_prefix {
+                return Err(format!(
+                    "Route prefix '{}' is already in use",
+                    route_prefix.as_deref().unwrap_or("")
+                ));
+            }
+            if let Some(domain) = domains.iter().find(|d| other.domains.contains(d)) {
+                return Err(format!("Domain '{}' is already in use", domain));
+            }
+        }
+        Ok(())
+    }
+
+    /// 创建项目
+    pub async fn create(&self, req: CreateProjectRequest) -> Result<Project, String> {
+        Self::validate_id(&req.id)?;
+        let route_prefix = Self::normalize_prefix(req.route_prefix)?;
+        let domains = Self::normalize_domains(req.domains);
+
+        let mut projects = self.projects.write().await;
+        if projects.contains_key(&req.id) {
+            return Err(format!("Project '{}' already exists", req.id));
+        }
+        Self::check_conflicts(&projects, &req.id, &route_prefix, &domains)?;
+
+        let now = Utc::now();
+        let project = Project {
+            id: req.id.clone(),
+            name: req.name.unwrap_or_else(|| req.id.clone()),
+            route_prefix,
+            domains,
+            quotas: req.quotas,
+            created_at: now,
+            updated_at: now,
+        };
+
+        projects.insert(req.id, project.clone());
+
+        drop(projects);
+        if let Err(e) = self.save().await {
+            eprintln!("[ProjectStore] 保存失败: {}", e);
+        }
+
+        Ok(project)
+    }
+
+    /// 获取项目
+    pub async fn get(&self, id: &str) -> Option<Project> {
+        self.projects.read().await.get(id).cloned()
+    }
+
+    /// 列出所有项目
+    pub async fn list(&self) -> Vec<Project> {
+        self.projects.read().await.values().cloned().collect()
+    }
+
+    /// 更新项目
+    pub async fn update(&self, id: &str, req: UpdateProjectRequest) -> Result<Project, String> {
+        let mut projects = self.projects.write().await;
+        let current = projects.get(id).ok_or("Project not found")?;
+
+        let route_prefix = match req.route_prefix {
+            Some(prefix) => Self::normalize_prefix(Some(prefix))?,
+            None => current.route_prefix.clone(),
+        };
+        let domains = match req.domains {
+            Some(domains) => Self::normalize_domains(domains),
+            None => current.domains.clone(),
+        };
+        Self::check_conflicts(&projects, id, &route_prefix, &domains)?;
+
+        let project = projects.get_mut(id).ok_or("Project not found")?;
+        project.route_prefix = route_prefix;
+        project.domains = domains;
+        if let Some(name) = req.name {
+            project.name = name;
+        }
+        if let Some(quotas) = req.quotas {
+            project.quotas = quotas;
+        }
+        project.updated_at = Utc::now();
+
+        let result = project.clone();
+
+        drop(projects);
+        if let Err(e) = self.save().await {
+            eprintln!("[ProjectStore] 保存失败: {}", e);
+        }
+
+        Ok(result)
+    }
+
+    /// 删除项目（调用方需确保项目下已没有函数和站点）
+    pub async fn delete(&self, id: &str) -> Result<(), String> {
+        if id == DEFAULT_PROJECT {
+            return Err("The default project cannot be deleted".to_string());
+        }
+
+        if self.projects.write().await.remove(id).is_none() {
+            return Err("Project not found".to_string());
+        }
+
+        if let Err(e) = self.save().await {
+            eprintln!("[ProjectStore] 保存失败: {}", e);
+        }
+
+        Ok(())
+    }
+
+    /// 根据 Host 和网关路径解析项目，返回项目和去掉前缀后的路径
+    ///
+    /// 优先按域名匹配；其次按路由前缀匹配；否则归属默认项目。
+    pub async fn resolve(&self, host: Option<&str>, path: &str) -> Option<(Project, String)> {
+        let projects = self.projects.read().await;
+
+        if let Some(host) = host.map(Self::host_name) {
+            if let Some(project) = projects.values().find(|p| p.domains.contains(&host)) {
+                return Some((project.clone(), path.to_string()));
+            }
+        }
+
+        for project in projects.values() {
+            let Some(prefix) = project.route_prefix.as_deref() else {
+                continue;
+            };
+            if path == prefix {
+                return Some((project.clone(), "/".to_string()));
+            }
+            if let Some(rest) = path.strip_prefix(prefix) {
+                if rest.starts_with('/') {
+                    return Some((project.clone(), rest.to_string()));
+                }
+            }
+        }
+
+        projects
+            .get(DEFAULT_PROJECT)
+            .map(|p| (p.clone(), path.to_string()))
+    }
+}
+
+impl Default for ProjectStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 旧数据没有 project_id 字段时归属默认项目
+pub fn default_project_id() -> String {
+    DEFAULT_PROJECT.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> ProjectStore {
+        let path = std::env::temp_dir().join(format!("nexo-projects-{}.json", uuid::Uuid::new_v4()));
+        ProjectStore::with_storage_path(path)
+    }
+
+    fn project_request(id: &str, prefix: Option<&str>, domains: &[&str]) -> CreateProjectRequest {
+        CreateProjectRequest {
+            id: id.to_string(),
+            name: None,
+            route_prefix: prefix.map(String::from),
+            domains: domains.iter().map(|d| d.to_string()).collect(),
+            quotas: ProjectQuotas::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_project() {
+        let store = temp_store();
+        store.create(project_request("acme", Some("acme/"), &["Acme.example.com"])).await.unwrap();
+
+        let (project, path) = store.resolve(None, "/acme/hello").await.unwrap();
+        assert_eq!(project.id, "acme");
+        assert_eq!(path, "/hello");
+
+        let (project, path) = store.resolve(Some("acme.example.com:3000"), "/hello").await.unwrap();
+        assert_eq!(project.id, "acme");
+        assert_eq!(path, "/hello");
+
+        let (project, path) = store.resolve(None, "/acmex/hello").await.unwrap();
+        assert_eq!(project.id, DEFAULT_PROJECT);
+        assert_eq!(path, "/acmex/hello");
+    }
+
+    #[tokio::test]
+    async fn test_project_conflicts() {
+        let store = temp_store();
+        store.create(project_request("a", Some("/shared"), &[])).await.unwrap();
+
+        assert!(store.create(project_request("b", Some("/shared"), &[])).await.is_err());
+        assert!(store.create(project_request("Bad_Id", None, &[])).await.is_err());
+        assert!(store.delete(DEFAULT_PROJECT).await.is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! Rate limiting - 网关的令牌桶限流
+//!
+//! 每个函数可以配置按客户端（IP 或认证后的调用方身份）计算的令牌桶，
+//! 在获取 Isolate 许可之前判定，超限时返回 429 和 Retry-After。
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// 桶数量上限，达到后淘汰最久未访问的桶
+const MAX_BUCKETS: usize = 10_000;
+/// 达到上限时一次淘汰的桶数，之后的新客户端不必每次都扫描
+const EVICT_BATCH: usize = MAX_BUCKETS / 10;
+/// 清理已回满的桶的间隔
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 限流时区分客户端的方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    /// 客户端 IP
+    #[default]
+    Ip,
+    /// 网关验证过的调用方身份（API Key、Basic 用户或 JWT sub），未认证时退回 IP
+    ApiKey,
+}
+
+/// 函数限流配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimit {
+    /// 每秒补充的令牌数
+    pub requests_per_second: f64,
+    /// 桶容量（允许的突发请求数）
+    pub burst: u32,
+    /// 客户端标识方式
+    #[serde(default)]
+    pub key: RateLimitKey,
+}
+
+impl RateLimit {
+    /// 校验配置
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.requests_per_second.is_finite() || self.requests_per_second <= 0.0 {
+            return Err("requests_per_second must be a positive number".to_string());
+        }
+        if self.burst == 0 {
+            return Err("burst must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// 令牌桶（记录所属函数的限流参数，清理时按各自的参数判断）
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+    requests_per_second: f64,
+    burst: f64,
+}
+
+impl TokenBucket {
+    fn new(limit: &RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            last_refill: now,
+            requests_per_second: limit.requests_per_second,
+            burst: limit.burst as f64,
+        }
+    }
+
+    /// 补充到 `now` 时的令牌数
+    fn tokens_at(&self, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        (self.tokens + elapsed * self.requests_per_second).min(self.burst)
+    }
+
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        // 函数的限流配置可能已更新
+        self.requests_per_second = limit.requests_per_second;
+        self.burst = limit.burst as f64;
+        self.tokens = self.tokens_at(now);
+        self.last_refill = now;
+    }
+}
+
+/// 限流器
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: parking_lot::Mutex<Buckets>,
+}
+
+#[derive(Default)]
+struct Buckets {
+    /// (函数 ID, 客户端) -> 令牌桶
+    map: HashMap<(String, String), TokenBucket>,
+    /// 上次清理的时间
+    last_prune: Option<Instant>,
+}
+
+impl Buckets {
+    /// 新客户端加入前：按间隔清理已回满的桶，达到上限时批量淘汰最久未访问的桶
+    fn make_room(&mut self, now: Instant) {
+        if self.last_prune.is_none_or(|last| now.duration_since(last) >= PRUNE_INTERVAL) {
+            RateLimiter::prune(&mut self.map, now);
+            self.last_prune = Some(now);
+        }
+        if self.map.len() >= MAX_BUCKETS {
+            RateLimiter::evict_oldest(&mut self.map, EVICT_BATCH);
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 消耗一个令牌；超限时返回需要等待的时间
+    pub fn check(&self, function_id: &str, client: &str, limit: &RateLimit) -> Result<(), Duration> {
+        self.check_at(function_id, client, limit, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        function_id: &str,
+        client: &str,
+        limit: &RateLimit,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock();
+
+        let key = (function_id.to_string(), client.to_string());
+        if !buckets.map.contains_key(&key) {
+            buckets.make_room(now);
+        }
+
+        let bucket = buckets.map.entry(key).or_insert_with(|| TokenBucket::new(limit, now));
+        bucket.refill(limit, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / limit.requests_per_second;
+            Err(Duration::from_secs_f64(wait))
+        }
+    }
+
+    /// 清理已回满的桶（回满的桶与不存在等价）
+    fn prune(buckets: &mut HashMap<(String, String), TokenBucket>, now: Instant) {
+        buckets.retain(|_, bucket| bucket.tokens_at(now) < bucket.burst);
+    }
+
+    /// 淘汰最久未访问的 `count` 个桶
+    fn evict_oldest(buckets: &mut HashMap<(String, String), TokenBucket>, count: usize) {
+        let mut accessed: Vec<Instant> = buckets.values().map(|b| b.last_refill).collect();
+        if count == 0 || accessed.is_empty() {
+            return;
+        }
+        let index = count.min(accessed.len()) - 1;
+        let (_, &mut cutoff, _) = accessed.select_nth_unstable(index);
+        buckets.retain(|_, bucket| bucket.last_refill > cutoff);
+    }
+
+    /// 移除函数的全部桶
+    pub fn remove_function(&self, function_id: &str) {
+        self.buckets.lock().map.retain(|(id, _), _| id != function_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new();
+        let limit = RateLimit {
+            requests_per_second: 2.0,
+            burst: 2,
+            key: RateLimitKey::Ip,
+        };
+        let now = Instant::now();
+
+        assert!(limiter.check_at("fn", "1.1.1.1", &limit, now).is_ok());
+        assert!(limiter.check_at("fn", "1.1.1.1", &limit, now).is_ok());
+
+        let wait = limiter.check_at("fn", "1.1.1.1", &limit, now).unwrap_err();
+        assert!(wait <= Duration::from_millis(500));
+
+        // 其他客户端不受影响
+        assert!(limiter.check_at("fn", "2.2.2.2", &limit, now).is_ok());
+
+        // 补充令牌后恢复
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.check_at("fn", "1.1.1.1", &limit, later).is_ok());
+    }
+
+    #[test]
+    fn test_prune_uses_each_bucket_limit() {
+        let strict = RateLimit { requests_per_second: 0.1, burst: 1, key: RateLimitKey::Ip };
+        let loose = RateLimit { requests_per_second: 1000.0, burst: 1000, key: RateLimitKey::Ip };
+        let now = Instant::now();
+
+        let mut buckets = HashMap::new();
+        let mut drained = TokenBucket::new(&strict, now);
+        drained.tokens = 0.0;
+        buckets.insert(("strict".to_string(), "a".to_string()), drained);
+        buckets.insert(("loose".to_string(), "b".to_string()), TokenBucket::new(&loose, now));
+
+        // 宽松函数的参数不会让严格函数的空桶被当作已回满
+        RateLimiter::prune(&mut buckets, now + Duration::from_secs(1));
+        assert!(buckets.contains_key(&("strict".to_string(), "a".to_string())));
+        assert!(!buckets.contains_key(&("loose".to_string(), "b".to_string())));
+    }
+
+    #[test]
+    fn test_bucket_cap_evicts_oldest() {
+        let limiter = RateLimiter::new();
+        let limit = RateLimit { requests_per_second: 0.001, burst: 1, key: RateLimitKey::Ip };
+        let start = Instant::now();
+
+        // 每个客户端都耗尽了令牌，清理回满的桶不起作用
+        for i in 0..=MAX_BUCKETS {
+            let now = start + Duration::from_millis(i as u64);
+            assert!(limiter.check_at("fn", &format!("client-{}", i), &limit, now).is_ok());
+        }
+
+        let buckets = limiter.buckets.lock();
+        assert!(buckets.map.len() <= MAX_BUCKETS);
+        assert!(!buckets.map.contains_key(&("fn".to_string(), "client-0".to_string())));
+        assert!(buckets.map.contains_key(&("fn".to_string(), format!("client-{}", MAX_BUCKETS))));
+    }
+}
@@ -0,0 +1,251 @@
+//! Asset rewriting - 部署时改写 HTML/CSS 中的绝对资源路径
+//!
+//! 构建工具默认按站点部署在域名根路径生成 `/assets/index.js` 这样的引用。
+//! 站点挂载在 `/site/{route}` 下时，这些引用会落到站点之外；开启改写后，
+//! 部署时把它们改为 `{base}/assets/index.js`。
+
+/// HTML 中值为 URL 的属性
+const URL_ATTRIBUTES: [&str; 4] = ["src", "href", "action", "poster"];
+
+/// 按 MIME 类型改写内容中的绝对路径，内容没有变化或不需要改写时返回 `None`
+pub fn rewrite_asset_urls(content: &[u8], mime_type: &str, base: &str) -> Option<Vec<u8>> {
+    let html = if mime_type.starts_with("text/html") {
+        true
+    } else if mime_type.starts_with("text/css") {
+        false
+    } else {
+        return None;
+    };
+
+    let base = base.trim_end_matches('/');
+    if base.is_empty() {
+        return None;
+    }
+
+    let text = std::str::from_utf8(content).ok()?;
+    let rewritten = if html { rewrite_html(text, base) } else { rewrite_css(text, base) };
+    (rewritten != text).then(|| rewritten.into_bytes())
+}
+
+/// 只改写标签内的 URL 属性和 `style` 属性；文本、注释和 `<script>` 内容原样保留，
+/// `<style>` 内容按 CSS 改写
+fn rewrite_html(text: &str, base: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        let (head, tail) = rest.split_at(start);
+        output.push_str(head);
+
+        if tail.starts_with("<!--") {
+            let end = tail.find("-->").map_or(tail.len(), |i| i + 3);
+            output.push_str(&tail[..end]);
+            rest = &tail[end..];
+            continue;
+        }
+
+        // 不是开始标签（结束标签、`<!DOCTYPE>`）时原样保留，文本中的 `<` 只跳过它本身
+        let opening = tail[1..].starts_with(|c: char| c.is_ascii_alphabetic());
+        if !opening && !tail[1..].starts_with(['/', '!', '?']) {
+            output.push('<');
+            rest = &tail[1..];
+            continue;
+        }
+
+        let end = tag_end(tail);
+        let (tag, after) = tail.split_at(end);
+        rest = after;
+        if !opening {
+            output.push_str(tag);
+            continue;
+        }
+        output.push_str(&rewrite_tag(tag, base));
+
+        let name = tag[1..]
+            .split(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if name == "script" || name == "style" {
+            let close = rest.to_ascii_lowercase().find(&format!("</{}", name)).unwrap_or(rest.len());
+            let (body, after) = rest.split_at(close);
+            if name == "style" {
+                output.push_str(&rewrite_css(body, base));
+            } else {
+                output.push_str(body);
+            }
+            rest = after;
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// 标签结束位置（`>` 之后），忽略引号内的 `>`
+fn tag_end(tag: &str) -> usize {
+    let mut quote = None;
+    for (i, b) in tag.bytes().enumerate().skip(1) {
+        match (quote, b) {
+            (Some(q), _) if b == q => quote = None,
+            (Some(_), _) => {}
+            (None, b'"' | b'\'') => quote = Some(b),
+            (None, b'>') => return i + 1,
+            _ => {}
+        }
+    }
+    tag.len()
+}
+
+/// 改写开始标签中的属性值，属性名不区分大小写
+fn rewrite_tag(tag: &str, base: &str) -> String {
+    let bytes = tag.as_bytes();
+    let is_name = |b: u8| !b.is_ascii_whitespace() && !matches!(b, b'=' | b'>' | b'/' | b'"' | b'\'');
+    let skip_space = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        i
+    };
+
+    let mut output = String::with_capacity(tag.len());
+    let mut copied = 0;
+    // 跳过标签名
+    let mut i = 1;
+    while i < bytes.len() && is_name(bytes[i]) {
+        i += 1;
+    }
+
+    while i < bytes.len() {
+        if !is_name(bytes[i]) {
+            i += 1;
+            continue;
+        }
+        let name_start = i;
+        while i < bytes.len() && is_name(bytes[i]) {
+            i += 1;
+        }
+        let name = tag[name_start..i].to_ascii_lowercase();
+
+        let eq = skip_space(i);
+        if bytes.get(eq) != Some(&b'=') {
+            continue;
+        }
+        let value_start = skip_space(eq + 1);
+        let (value_start, value_end, next) = match bytes.get(value_start) {
+            Some(&quote @ (b'"' | b'\'')) => {
+                let end = tag[value_start + 1..]
+                    .find(quote as char)
+                    .map_or(tag.len(), |e| value_start + 1 + e);
+                (value_start + 1, end, (end + 1).min(tag.len()))
+            }
+            _ => {
+                let end = tag[value_start..]
+                    .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                    .map_or(tag.len(), |e| value_start + e);
+                (value_start, end, end)
+            }
+        };
+
+        let value = &tag[value_start..value_end];
+        let rewritten = if URL_ATTRIBUTES.contains(&name.as_str()) {
+            needs_base(value, base).then(|| format!("{}{}", base, value))
+        } else if name == "style" {
+            Some(rewrite_css(value, base)).filter(|v| v != value)
+        } else {
+            None
+        };
+        if let Some(rewritten) = rewritten {
+            output.push_str(&tag[copied..value_start]);
+            output.push_str(&rewritten);
+            copied = value_end;
+        }
+        i = next;
+    }
+
+    output.push_str(&tag[copied..]);
+    output
+}
+
+/// 在每个 `url(`（及可选的引号）之后，把以 `/` 开头的路径加上 `base`
+fn rewrite_css(text: &str, base: &str) -> String {
+    let lower = text.to_ascii_lowercase();
+    let mut output = String::with_capacity(text.len());
+    let mut copied = 0;
+
+    while let Some(index) = lower[copied..].find("url(") {
+        let url_start = copied + index + "url(".len();
+        let quote = if text[url_start..].starts_with(['"', '\'']) { 1 } else { 0 };
+        output.push_str(&text[copied..url_start + quote]);
+        copied = url_start + quote;
+
+        if needs_base(&text[copied..], base) {
+            output.push_str(base);
+        }
+    }
+
+    output.push_str(&text[copied..]);
+    output
+}
+
+/// 站点内的绝对路径（排除 `//host/...` 和已经带前缀的路径）
+fn needs_base(url: &str, base: &str) -> bool {
+    if !url.starts_with('/') || url.starts_with("//") {
+        return false;
+    }
+    match url.strip_prefix(base) {
+        Some(after) => !(after.is_empty() || after.starts_with(['/', '"', '\'', ')', '?', '#'])),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_html_and_css() {
+        let html = br#"<link href="/assets/app.css"><script src='/assets/app.js'></script>
+<a href="https://example.com/x">x</a><img src="//cdn.example.com/a.png"><a href="/">home</a>
+<div style="background: url(/img/bg.png)"></div><a href="about.html">about</a>"#;
+        let out = rewrite_asset_urls(html, "text/html; charset=utf-8", "/site/app/").unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(r#"href="/site/app/assets/app.css""#));
+        assert!(out.contains("src='/site/app/assets/app.js'"));
+        assert!(out.contains(r#"href="https://example.com/x""#));
+        assert!(out.contains(r#"src="//cdn.example.com/a.png""#));
+        assert!(out.contains(r#"href="/site/app/""#));
+        assert!(out.contains("url(/site/app/img/bg.png)"));
+        assert!(out.contains(r#"href="about.html""#));
+
+        // 已改写过的内容不再重复加前缀
+        assert!(rewrite_asset_urls(out.as_bytes(), "text/html", "/site/app").is_none());
+
+        let css = b"@font-face { src: url('/fonts/a.woff2') } .b { background: url(\"/img/b.png\") }";
+        let out = String::from_utf8(rewrite_asset_urls(css, "text/css", "/site/app").unwrap()).unwrap();
+        assert!(out.contains("url('/site/app/fonts/a.woff2')"));
+        assert!(out.contains("url(\"/site/app/img/b.png\")"));
+
+        assert!(rewrite_asset_urls(b"import '/a.js'", "application/javascript", "/site/app").is_none());
+        assert!(rewrite_asset_urls(html, "text/html", "").is_none());
+    }
+
+    #[test]
+    fn test_rewrite_only_attributes() {
+        let html = r#"<IMG SRC="/a.png" Alt="see src=/x"><a data-x=1 HREF = /b.html>go to href=/c</a>
+<!-- <img src="/old.png"> -->
+<script type="module" src="/app.js">if (!user) location.href="/login"; const u = url("/api");</script>
+<SCRIPT>window.src='/d'</SCRIPT><style>.a { background: URL('/e.png') }</style>
+<p>Set href="/f" or url(/g) in text</p><input value="a < b" formaction="/h">"#;
+        let out = rewrite_asset_urls(html.as_bytes(), "text/html", "/site/app").unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(r#"<IMG SRC="/site/app/a.png" Alt="see src=/x">"#));
+        assert!(out.contains("<a data-x=1 HREF = /site/app/b.html>go to href=/c</a>"));
+        assert!(out.contains(r#"<!-- <img src="/old.png"> -->"#));
+        assert!(out.contains(r#"<script type="module" src="/site/app/app.js">if (!user) location.href="/login"; const u = url("/api");</script>"#));
+        assert!(out.contains("<SCRIPT>window.src='/d'</SCRIPT>"));
+        assert!(out.contains("URL('/site/app/e.png')"));
+        assert!(out.contains(r#"<p>Set href="/f" or url(/g) in text</p>"#));
+        assert!(out.contains(r#"formaction="/h""#));
+    }
+}
@@ -0,0 +1,377 @@
+//! Site rules - 站点的重定向、重写和自定义响应头
+//!
+//! 规则来自站点根目录下的 `_redirects` / `_headers` 文件（Netlify 格式），
+//! 或部署请求中的 JSON 配置，在部署时解析校验并随部署保存。
+//!
+//! `_redirects` 每行一条：`/from /to [status][!]`
+//! - `:name` 匹配一个路径段，结尾的 `*` 匹配剩余路径，在目标中用 `:name` / `:splat` 引用
+//! - 3xx 为重定向，200 为重写（目标为 `/fn/...` 时转发给函数），404 返回指定页面
+//! - 默认只在请求的文件不存在时生效，加 `!` 时总是生效
+//!
+//! `_headers` 为路径行加缩进的 `Name: value` 行。
+
+use axum::http::{HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 重定向规则文件名
+pub const REDIRECTS_FILE: &str = "_redirects";
+/// 响应头规则文件名
+pub const HEADERS_FILE: &str = "_headers";
+
+/// 允许的状态码
+const ALLOWED_STATUS: [u16; 7] = [200, 301, 302, 303, 307, 308, 404];
+
+/// 站点规则
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SiteRules {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redirects: Vec<RedirectRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<HeaderRule>,
+}
+
+/// 重定向 / 重写规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedirectRule {
+    pub from: String,
+    pub to: String,
+    #[serde(default = "default_status")]
+    pub status: u16,
+    /// 即使请求的文件存在也生效
+    #[serde(default)]
+    pub force: bool,
+}
+
+fn default_status() -> u16 {
+    301
+}
+
+/// 响应头规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderRule {
+    /// 路径模式，支持 `:name` 和 `*`
+    pub path: String,
+    pub headers: HashMap<String, String>,
+}
+
+/// 匹配到的重定向规则
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedirectMatch {
+    /// 替换占位符后的目标
+    pub to: String,
+    pub status: u16,
+    pub force: bool,
+}
+
+impl RedirectMatch {
+    /// 是否为重定向（而不是重写）
+    pub fn is_redirect(&self) -> bool {
+        (300..400).contains(&self.status)
+    }
+}
+
+impl SiteRules {
+    pub fn is_empty(&self) -> bool {
+        self.redirects.is_empty() && self.headers.is_empty()
+    }
+
+    /// 合并规则，`self` 中的规则优先
+    pub fn merge(mut self, other: SiteRules) -> Self {
+        self.redirects.extend(other.redirects);
+        self.headers.extend(other.headers);
+        self
+    }
+
+    /// 校验规则
+    pub fn validate(&self) -> Result<(), String> {
+        for rule in &self.redirects {
+            validate_redirect(rule)?;
+        }
+        for rule in &self.headers {
+            validate_pattern(&rule.path)?;
+            for (name, value) in &rule.headers {
+                validate_header(name, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 第一条匹配请求路径的重定向规则
+    pub fn redirect(&self, path: &str) -> Option<RedirectMatch> {
+        self.redirects.iter().find_map(|rule| {
+            let params = match_path(&rule.from, path)?;
+            Some(RedirectMatch {
+                to: substitute(&rule.to, &params),
+                status: rule.status,
+                force: rule.force,
+            })
+        })
+    }
+
+    /// 匹配请求路径的所有自定义响应头
+    pub fn headers_for(&self, path: &str) -> Vec<(&str, &str)> {
+        self.headers
+            .iter()
+            .filter(|rule| match_path(&rule.path, path).is_some())
+            .flat_map(|rule| rule.headers.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+            .collect()
+    }
+}
+
+/// 解析 `_redirects`
+pub fn parse_redirects(text: &str) -> Result<Vec<RedirectRule>, String> {
+    let mut rules = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |e: String| format!("{} line {}: {}", REDIRECTS_FILE, index + 1, e);
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let (from, to, status) = match parts.as_slice() {
+            [from, to] => (*from, *to, None),
+            [from, to, status] => (*from, *to, Some(*status)),
+            _ => return Err(error("expected '/from /to [status]'".to_string())),
+        };
+
+        let (status, force) = match status {
+            None => (default_status(), false),
+            Some(status) => {
+                let (code, force) = match status.strip_suffix('!') {
+                    Some(code) => (code, true),
+                    None => (status, false),
+                };
+                let code = code
+                    .parse()
+                    .map_err(|_| error(format!("invalid status '{}'", status)))?;
+                (code, force)
+            }
+        };
+
+        let rule = RedirectRule {
+            from: from.to_string(),
+            to: to.to_string(),
+            status,
+            force,
+        };
+        validate_redirect(&rule).map_err(error)?;
+        rules.push(rule);
+    }
+
+    Ok(rules)
+}
+
+/// 解析 `_headers`
+pub fn parse_headers(text: &str) -> Result<Vec<HeaderRule>, String> {
+    let mut rules: Vec<HeaderRule> = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let error = |e: String| format!("{} line {}: {}", HEADERS_FILE, index + 1, e);
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        // 不缩进的行是路径
+        if !line.starts_with([' ', '\t']) {
+            validate_pattern(trimmed).map_err(error)?;
+            rules.push(HeaderRule {
+                path: trimmed.to_string(),
+                headers: HashMap::new(),
+            });
+            continue;
+        }
+
+        let rule = rules
+            .last_mut()
+            .ok_or_else(|| error("header without a path".to_string()))?;
+        let (name, value) = trimmed
+            .split_once(':')
+            .ok_or_else(|| error("expected 'Name: value'".to_string()))?;
+        let (name, value) = (name.trim(), value.trim());
+        validate_header(name, value).map_err(error)?;
+
+        // 同名的头合并为逗号分隔的列表
+        rule.headers
+            .entry(name.to_string())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+
+    Ok(rules)
+}
+
+fn validate_redirect(rule: &RedirectRule) -> Result<(), String> {
+    validate_pattern(&rule.from)?;
+    if !ALLOWED_STATUS.contains(&rule.status) {
+        return Err(format!("unsupported status {}", rule.status));
+    }
+    let external = rule.to.starts_with("http://") || rule.to.starts_with("https://");
+    if !external && !rule.to.starts_with('/') {
+        return Err(format!("target '{}' must start with '/' or be an absolute URL", rule.to));
+    }
+    // 不支持代理到外部地址，只能重定向过去
+    if external && !(300..400).contains(&rule.status) {
+        return Err(format!("status {} cannot target an external URL", rule.status));
+    }
+    Ok(())
+}
+
+/// 校验路径模式
+pub fn validate_pattern(pattern: &str) -> Result<(), String> {
+    if !pattern.starts_with('/') {
+        return Err(format!("path '{}' must start with '/'", pattern));
+    }
+    if pattern.contains('*') && !pattern.ends_with('*') {
+        return Err(format!("'*' must be at the end of '{}'", pattern));
+    }
+    Ok(())
+}
+
+/// 校验响应头名称和值
+pub fn validate_header(name: &str, value: &str) -> Result<(), String> {
+    HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("invalid header name '{}'", name))?;
+    HeaderValue::from_str(value).map_err(|_| format!("invalid value for header '{}'", name))?;
+    Ok(())
+}
+
+/// 匹配路径模式，返回占位符的值
+pub fn match_path(pattern: &str, path: &str) -> Option<HashMap<String, String>> {
+    let Some(prefix) = pattern.strip_suffix('*') else {
+        return match_segments(pattern, path);
+    };
+
+    // 结尾的 `*` 匹配前缀之后的剩余路径（可以为空）
+    let prefix = prefix.trim_end_matches('/');
+    let depth = prefix.split('/').count();
+    let mut segments = path.splitn(depth + 1, '/');
+    let head: Vec<&str> = segments.by_ref().take(depth).collect();
+    let rest = segments.next().unwrap_or("");
+
+    let mut params = match_segments(prefix, &head.join("/"))?;
+    params.insert("splat".to_string(), rest.to_string());
+    Some(params)
+}
+
+/// 逐段匹配（忽略结尾的 `/`）
+fn match_segments(pattern: &str, path: &str) -> Option<HashMap<String, String>> {
+    let pattern: Vec<&str> = pattern.trim_end_matches('/').split('/').collect();
+    let path: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    if pattern.len() != path.len() {
+        return None;
+    }
+
+    let mut params = HashMap::new();
+    for (expected, actual) in pattern.iter().zip(&path) {
+        match expected.strip_prefix(':') {
+            Some(name) if !actual.is_empty() => {
+                params.insert(name.to_string(), actual.to_string());
+            }
+            Some(_) => return None,
+            None if expected == actual => {}
+            None => return None,
+        }
+    }
+    Some(params)
+}
+
+/// 把目标中的 `:name` 替换为匹配到的值
+fn substitute(to: &str, params: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(to.len());
+    let mut rest = to;
+
+    while let Some(index) = rest.find(':') {
+        result.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        let len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        match params.get(&after[..len]) {
+            Some(value) if len > 0 => result.push_str(value),
+            _ => {
+                result.push(':');
+                result.push_str(&after[..len]);
+            }
+        }
+        rest = &after[len..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redirects() {
+        let rules = SiteRules {
+            redirects: parse_redirects(
+                "# comment\n\
+                 /old-path   /new-path\n\
+                 /blog/:year/:slug  /posts/:year-:slug  302\n\
+                 /api/*  /fn/api/:splat  200!\n\
+                 /docs/*  https://docs.example.com/:splat  308\n\
+                 /*  /index.html  200\n",
+            )
+            .unwrap(),
+            headers: Vec::new(),
+        };
+
+        let m = rules.redirect("/old-path/").unwrap();
+        assert_eq!((m.to.as_str(), m.status, m.force), ("/new-path", 301, false));
+        assert!(m.is_redirect());
+
+        let m = rules.redirect("/blog/2024/hello").unwrap();
+        assert_eq!((m.to.as_str(), m.status), ("/posts/2024-hello", 302));
+
+        let m = rules.redirect("/api/users/42").unwrap();
+        assert_eq!((m.to.as_str(), m.status, m.force), ("/fn/api/users/42", 200, true));
+        assert!(!m.is_redirect());
+
+        let m = rules.redirect("/docs/guide/intro").unwrap();
+        assert_eq!(m.to, "https://docs.example.com/guide/intro");
+
+        // 兜底的 SPA 重写
+        assert_eq!(rules.redirect("/settings/profile").unwrap().to, "/index.html");
+    }
+
+    #[test]
+    fn test_invalid_redirects() {
+        assert!(parse_redirects("/a").unwrap_err().contains("line 1"));
+        assert!(parse_redirects("\n/a /b 418").unwrap_err().contains("line 2"));
+        assert!(parse_redirects("/a https://example.com 200").unwrap_err().contains("external"));
+        assert!(parse_redirects("a /b").is_err());
+        assert!(parse_redirects("/a/*/b /c").is_err());
+    }
+
+    #[test]
+    fn test_headers() {
+        let rules = SiteRules {
+            redirects: Vec::new(),
+            headers: parse_headers(
+                "/*\n  X-Frame-Options: DENY\n  Link: </a.css>; rel=preload\n  Link: </b.js>; rel=preload\n\
+                 /assets/*\n\tAccess-Control-Allow-Origin: *\n",
+            )
+            .unwrap(),
+        };
+
+        let headers = rules.headers_for("/index.html");
+        assert_eq!(headers.len(), 2);
+        assert!(headers.contains(&("Link", "</a.css>; rel=preload, </b.js>; rel=preload")));
+
+        let headers = rules.headers_for("/assets/app.js");
+        assert!(headers.contains(&("Access-Control-Allow-Origin", "*")));
+        assert!(headers.contains(&("X-Frame-Options", "DENY")));
+
+        assert!(parse_headers("  X-A: b").unwrap_err().contains("without a path"));
+        assert!(parse_headers("/*\n  Bad Header: x").is_err());
+    }
+}
@@ -2,12 +2,41 @@
 //!
 //! 负责协调函数存储、Isolate 池和请求处理。
 
-use crate::function::{Function, FunctionStore, FunctionStatus};
-use crate::isolate::IsolateConfig;
-use crate::pool::{IsolatePool, PoolStats};
+use crate::function::{
+    CreateFunctionRequest, Function, FunctionLimits, FunctionStatus, FunctionStore,
+    UpdateFunctionRequest,
+};
+use crate::isolate::{CancelToken, ExecutionResult, FailureKind, IsolateConfig, ScriptError};
+use crate::logs::{InvocationRecord, LogStore};
+use crate::policy::{self, AuthFailure};
+use crate::pool::{IsolatePool, PoolConfig, PoolStats, Priority};
+use crate::ratelimit::{RateLimit, RateLimitKey, RateLimiter};
+use crate::rules;
+use crate::secret::{self, SecretStore};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// 默认网关超时（毫秒）
+const DEFAULT_GATEWAY_TIMEOUT_MS: u64 = 30_000;
+
+/// 不从函数响应转发的响应头：逐跳头、由网关重新计算的长度和网关自己的调用信息
+const DROPPED_HEADERS: [&str; 10] = [
+    "connection",
+    "keep-alive",
+    "transfer-encoding",
+    "te",
+    "trailer",
+    "upgrade",
+    "content-length",
+    "x-execution-time-ms",
+    "x-function-id",
+    "x-request-id",
+];
 
 /// 函数请求
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +49,12 @@ pub struct FunctionRequest {
     pub query_params: HashMap<String, String>,
     #[serde(default)]
     pub env: HashMap<String, String>,
+    /// 网关认证结果（API Key 或认证策略通过后填入，作为 `ctx.auth` 传给函数）
+    #[serde(default)]
+    pub auth: Option<serde_json::Value>,
+    /// 客户端 IP
+    #[serde(default)]
+    pub client_ip: Option<String>,
 }
 
 /// 函数响应
@@ -32,22 +67,272 @@ pub struct FunctionResponse {
     pub memory_used_bytes: usize,
     pub function_id: String,
     pub logs: Vec<String>,
+    /// 请求 ID（对应调用记录）
+    #[serde(default)]
+    pub request_id: String,
+}
+
+impl FunctionResponse {
+    /// 需要转发给客户端的响应头（`Location`、`Set-Cookie`、CORS 等），
+    /// 跳过逐跳头、`Proxy-*` 和无效的响应头，按名称排序
+    pub fn forwarded_headers(&self) -> Vec<(String, String)> {
+        let mut forwarded: Vec<(String, String)> = self
+            .headers
+            .iter()
+            .filter(|(name, value)| {
+                let lower = name.to_ascii_lowercase();
+                !DROPPED_HEADERS.contains(&lower.as_str())
+                    && !lower.starts_with("proxy-")
+                    && rules::validate_header(name, value).is_ok()
+            })
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        forwarded.sort();
+        forwarded
+    }
+}
+
+/// 网关拒绝请求的原因（均在启动 Isolate 之前判定）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GatewayError {
+    /// 路由没有对应的函数
+    NotFound(String),
+    /// 函数不接受该 HTTP 方法
+    MethodNotAllowed(String),
+    /// 函数未处于 Active 状态
+    Unavailable(String),
+    /// 缺少或无效的凭据
+    Unauthorized { message: String, challenge: String },
+    /// 凭据有效但无权访问
+    Forbidden(String),
+    /// 超出限流或函数并发上限
+    TooManyRequests { message: String, retry_after_secs: u64 },
+    /// 函数的网关配置有误（如认证策略引用的密钥不存在）
+    Misconfigured(String),
+}
+
+impl GatewayError {
+    /// 对应的 HTTP 状态码
+    pub fn status(&self) -> u16 {
+        match self {
+            GatewayError::NotFound(_) => 404,
+            GatewayError::MethodNotAllowed(_) => 405,
+            GatewayError::Unavailable(_) => 503,
+            GatewayError::Unauthorized { .. } => 401,
+            GatewayError::Forbidden(_) => 403,
+            GatewayError::TooManyRequests { .. } => 429,
+            GatewayError::Misconfigured(_) => 500,
+        }
+    }
+
+    /// 错误信息
+    pub fn message(&self) -> &str {
+        match self {
+            GatewayError::NotFound(m)
+            | GatewayError::MethodNotAllowed(m)
+            | GatewayError::Unavailable(m)
+            | GatewayError::Forbidden(m)
+            | GatewayError::Misconfigured(m) => m,
+            GatewayError::Unauthorized { message, .. }
+            | GatewayError::TooManyRequests { message, .. } => message,
+        }
+    }
+}
+
+impl From<AuthFailure> for GatewayError {
+    fn from(failure: AuthFailure) -> Self {
+        match failure {
+            AuthFailure::Unauthorized { message, challenge } => {
+                GatewayError::Unauthorized { message, challenge }
+            }
+            AuthFailure::Forbidden(message) => GatewayError::Forbidden(message),
+            AuthFailure::Misconfigured(message) => GatewayError::Misconfigured(message),
+        }
+    }
 }
 
 /// Nexo 运行时
 pub struct NexoRuntime {
     /// 函数存储
     pub functions: FunctionStore,
+    /// 密钥存储
+    pub secrets: SecretStore,
     /// Isolate 池
     pool: Arc<IsolatePool>,
+    /// 调用记录
+    pub logs: LogStore,
+    /// 网关限流器
+    rate_limiter: RateLimiter,
+    /// 网关超时：超过后终止执行并返回 504
+    gateway_timeout: Duration,
 }
 
 impl NexoRuntime {
     /// 创建新的运行时
     pub fn new(max_concurrent_isolates: usize) -> Self {
+        Self::with_pool_config(PoolConfig::new(max_concurrent_isolates))
+    }
+
+    /// 使用指定的池配置创建运行时
+    pub fn with_pool_config(pool_config: PoolConfig) -> Self {
         Self {
             functions: FunctionStore::new(),
-            pool: Arc::new(IsolatePool::new(max_concurrent_isolates)),
+            secrets: SecretStore::new(),
+            logs: LogStore::new(),
+            pool: Arc::new(IsolatePool::with_config(pool_config)),
+            rate_limiter: RateLimiter::new(),
+            gateway_timeout: Self::gateway_timeout_from_env(),
+        }
+    }
+
+    /// 从环境变量 NEXO_GATEWAY_TIMEOUT_MS 读取网关超时（默认 30 秒）
+    fn gateway_timeout_from_env() -> Duration {
+        let ms = std::env::var("NEXO_GATEWAY_TIMEOUT_MS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|&ms: &u64| ms > 0)
+            .unwrap_or(DEFAULT_GATEWAY_TIMEOUT_MS);
+        Duration::from_millis(ms)
+    }
+
+    /// 部署新函数
+    ///
+    /// 函数先以 Deploying 状态登记，编译（及可选的冒烟测试）通过后置为 Active；
+    /// 失败时置为 Error 并在函数上记录错误位置，返回的函数带有 `deploy_error`，
+    /// 之后用修正后的代码更新即可。密钥写入失败时撤销登记。
+    pub async fn deploy_function(
+        &self,
+        project_id: &str,
+        mut req: CreateFunctionRequest,
+    ) -> Result<Function, String> {
+        if !req.secrets.is_empty() && !self.secrets.is_enabled() {
+            return Err("Secrets are disabled: NEXO_MASTER_KEY is not set".to_string());
+        }
+
+        let smoke_test = req.smoke_test.clone();
+        let secrets = std::mem::take(&mut req.secrets);
+        let function = self.functions.create(project_id, req).await?;
+
+        for (name, value) in &secrets {
+            if let Err(e) = self.secrets.set(&function.id, name, value).await {
+                self.rollback_deploy(&function.id).await;
+                return Err(e);
+            }
+        }
+
+        let error = self
+            .verify_code(
+                &function.project_id,
+                &function.id,
+                &function.code,
+                &function.limits,
+                &function.env,
+                smoke_test,
+            )
+            .await
+            .err();
+
+        self.functions.finish_deploy(&function.id, error).await
+    }
+
+    /// 撤销密钥写入失败的函数
+    async fn rollback_deploy(&self, id: &str) {
+        if let Err(e) = self.delete_function(id).await {
+            tracing::warn!("Failed to roll back function {}: {}", id, e);
+        }
+    }
+
+    /// 更新函数
+    ///
+    /// 新代码必须先通过检查；检查失败时拒绝更新，旧代码继续提供服务。
+    pub async fn update_function(
+        &self,
+        id: &str,
+        req: UpdateFunctionRequest,
+    ) -> Result<Function, String> {
+        let current = self.functions.get(id).await.ok_or("Function not found")?;
+
+        if let Some(code) = &req.code {
+            let limits = req.limits.as_ref().unwrap_or(&current.limits);
+            let env = req.env.as_ref().unwrap_or(&current.env);
+
+            if let Err(e) = self
+                .verify_code(&current.project_id, id, code, limits, env, req.smoke_test.clone())
+                .await
+            {
+                self.functions.record_deploy_error(id, e.clone()).await;
+                return Err(format!("Deployment failed: {}", e));
+            }
+        }
+
+        self.functions.update(id, req).await
+    }
+
+    /// 删除函数及其密钥
+    pub async fn delete_function(&self, id: &str) -> Result<(), String> {
+        self.functions.delete(id).await?;
+        self.secrets.delete_scope(id).await;
+        self.logs.delete_function(id).await;
+        self.rate_limiter.remove_function(id);
+        self.pool.remove_function(id);
+        Ok(())
+    }
+
+    /// 合并普通环境变量和解密后的密钥（同名时密钥优先）
+    async fn resolve_env(
+        &self,
+        function_id: &str,
+        env: &HashMap<String, String>,
+    ) -> (HashMap<String, String>, Vec<String>) {
+        let secrets = self.secrets.reveal(function_id).await;
+        let secret_values: Vec<String> = secrets.values().cloned().collect();
+
+        let mut merged = env.clone();
+        merged.extend(secrets);
+        (merged, secret_values)
+    }
+
+    /// 在 Isolate 中编译代码，并按需执行一次冒烟测试
+    async fn verify_code(
+        &self,
+        project_id: &str,
+        function_id: &str,
+        code: &str,
+        limits: &FunctionLimits,
+        env: &HashMap<String, String>,
+        smoke_test: Option<serde_json::Value>,
+    ) -> Result<(), ScriptError> {
+        let config = Self::isolate_config(project_id, function_id, limits);
+
+        self.pool.check(code, Some(config.clone())).await?;
+
+        if let Some(mut request_data) = smoke_test {
+            let (env, _) = self.resolve_env(function_id, env).await;
+            if let Some(obj) = request_data.as_object_mut() {
+                obj.insert("env".to_string(), serde_json::to_value(env).unwrap_or_default());
+            }
+
+            let result = self.pool
+                .execute(function_id, code, request_data, Some(config), Priority::Management)
+                .await;
+            if !result.success {
+                return Err(ScriptError::new(format!(
+                    "Smoke test failed: {}",
+                    result.error.unwrap_or_else(|| "Unknown error".to_string())
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 根据函数资源限制构建 Isolate 配置
+    fn isolate_config(project_id: &str, function_id: &str, limits: &FunctionLimits) -> IsolateConfig {
+        IsolateConfig {
+            max_execution_time_ms: limits.max_execution_time_ms,
+            max_heap_size_bytes: (limits.max_memory_mb as usize) * 1024 * 1024,
+            function_id: function_id.to_string(),
+            project_id: project_id.to_string(),
         }
     }
 
@@ -56,64 +341,148 @@ impl NexoRuntime {
         &self,
         function: &Function,
         request: FunctionRequest,
+        priority: Priority,
     ) -> FunctionResponse {
         // 记录调用
         self.functions.record_invocation(&function.id).await;
 
         // 构建 Isolate 配置
-        let config = IsolateConfig {
-            max_execution_time_ms: function.limits.max_execution_time_ms,
-            max_heap_size_bytes: (function.limits.max_memory_mb as usize) * 1024 * 1024,
-            function_id: function.id.clone(),
-        };
+        let config = Self::isolate_config(&function.project_id, &function.id, &function.limits);
 
-        // 构建请求数据（包含环境变量）
+        // 构建请求数据（包含环境变量和密钥）
+        let (env, secret_values) = self.resolve_env(&function.id, &function.env).await;
         let mut request_with_env = request.clone();
-        request_with_env.env = function.env.clone();
+        request_with_env.env = env;
         let request_data = serde_json::to_value(&request_with_env).unwrap_or_default();
 
-        // 在 Isolate 池中执行
-        let result = self.pool.execute(
-            &function.id,
-            &function.code,
+        // 执行放在独立任务中：调用方的 future 被丢弃（客户端断开）时，
+        // 守卫取消执行并终止脚本，任务仍会把这次调用记为已取消
+        let cancel = CancelToken::new();
+        let guard = cancel.drop_guard();
+        let invocation = Invocation {
+            function: function.clone(),
+            request,
             request_data,
-            Some(config),
-        ).await;
+            config,
+            priority,
+            secret_values,
+        };
+        let mut task = tokio::spawn(
+            invocation
+                .run(Arc::clone(&self.pool), self.logs.clone(), cancel.clone())
+                .in_current_span(),
+        );
 
-        // 转换为响应
-        self.result_to_response(&function.id, result)
+        let joined = tokio::select! {
+            joined = &mut task => joined,
+            _ = tokio::time::sleep(self.gateway_timeout) => {
+                cancel.cancel(
+                    FailureKind::Timeout,
+                    format!("Gateway timeout: exceeded {}ms", self.gateway_timeout.as_millis()),
+                );
+                task.await
+            }
+        };
+        guard.disarm();
+
+        joined.unwrap_or_else(|e| {
+            let mut response = Self::result_to_response(
+                &function.id,
+                ExecutionResult::failure(format!("Execution task failed: {}", e)),
+            );
+            response.request_id = Uuid::new_v4().to_string();
+            response
+        })
     }
 
-    /// 通过路由执行函数
+    /// 通过路由执行项目内的函数
     pub async fn execute_by_route(
         &self,
+        project_id: &str,
         route: &str,
         method: &str,
-        request: FunctionRequest,
-    ) -> Result<FunctionResponse, String> {
+        mut request: FunctionRequest,
+    ) -> Result<FunctionResponse, GatewayError> {
         // 查找函数
-        let function = self.functions.get_by_route(route).await
-            .ok_or_else(|| format!("No function found for route: {}", route))?;
+        let function = self.functions.get_by_route(project_id, route).await
+            .ok_or_else(|| GatewayError::NotFound(format!("No function found for route: {}", route)))?;
 
         // 检查 HTTP 方法
         if !function.methods.iter().any(|m| m.eq_ignore_ascii_case(method)) {
-            return Err(format!("Method {} not allowed for this function", method));
+            return Err(GatewayError::MethodNotAllowed(format!(
+                "Method {} not allowed for this function",
+                method
+            )));
         }
 
         // 检查函数状态
         if function.status != FunctionStatus::Active {
-            return Err("Function is not active".to_string());
+            return Err(GatewayError::Unavailable("Function is not active".to_string()));
+        }
+
+        // 按 IP 限流在认证之前判定
+        let rate_limit = function.rate_limit.as_ref();
+        if let Some(limit) = rate_limit.filter(|l| l.key == RateLimitKey::Ip) {
+            self.check_rate_limit(&function.id, &request, limit)?;
+        }
+
+        // 校验认证策略（不启动 Isolate）；密码哈希较慢，放在阻塞线程中计算
+        if let Some(policy) = function.auth.clone() {
+            let secrets = self.secrets.reveal(&function.id).await;
+            let headers = request.headers.clone();
+            let evaluated = tokio::task::spawn_blocking(move || policy.evaluate(&headers, &secrets))
+                .await
+                .map_err(|e| GatewayError::Misconfigured(format!("Auth check failed: {}", e)))?;
+            request.auth = Some(evaluated?);
+        }
+
+        // 按调用方身份限流需要认证之后的身份
+        if let Some(limit) = rate_limit.filter(|l| l.key == RateLimitKey::ApiKey) {
+            self.check_rate_limit(&function.id, &request, limit)?;
         }
 
-        Ok(self.execute_function(&function, request).await)
+        // 占用函数并发槽（在获取全局许可之前）
+        let _slot = match function.limits.max_concurrency {
+            Some(max) => Some(self.pool.try_acquire_function_slot(&function.id, max).ok_or_else(|| {
+                GatewayError::TooManyRequests {
+                    message: "Function concurrency limit reached".to_string(),
+                    retry_after_secs: 1,
+                }
+            })?),
+            None => None,
+        };
+
+        Ok(self.execute_function(&function, request, Priority::Public).await)
     }
 
-    /// 将执行结果转换为 HTTP 响应
-    fn result_to_response(
+    /// 消耗客户端的一个令牌
+    fn check_rate_limit(
         &self,
         function_id: &str,
-        result: crate::isolate::ExecutionResult,
-    ) -> FunctionResponse {
+        request: &FunctionRequest,
+        limit: &RateLimit,
+    ) -> Result<(), GatewayError> {
+        let client = Self::client_key(request, limit.key);
+        self.rate_limiter.check(function_id, &client, limit).map_err(|wait| {
+            GatewayError::TooManyRequests {
+                message: "Rate limit exceeded".to_string(),
+                retry_after_secs: wait.as_secs_f64().ceil().max(1.0) as u64,
+            }
+        })
+    }
+
+    /// 限流使用的客户端标识：只使用网关验证过的身份，未认证的请求按 IP 计算
+    fn client_key(request: &FunctionRequest, key: RateLimitKey) -> String {
+        if key == RateLimitKey::ApiKey {
+            if let Some(identity) = request.auth.as_ref().and_then(policy::auth_identity) {
+                return format!("id:{}", identity);
+            }
+        }
+        format!("ip:{}", request.client_ip.as_deref().unwrap_or("unknown"))
+    }
+
+    /// 将执行结果转换为 HTTP 响应
+    fn result_to_response(function_id: &str, result: ExecutionResult) -> FunctionResponse {
         if result.success {
             // 检查是否是 Response 对象
             if let Some(ref output) = result.output {
@@ -149,6 +518,7 @@ impl NexoRuntime {
                         memory_used_bytes: result.memory_used_bytes,
                         function_id: function_id.to_string(),
                         logs: result.logs,
+                        request_id: String::new(),
                     };
                 }
             }
@@ -164,10 +534,36 @@ impl NexoRuntime {
                 memory_used_bytes: result.memory_used_bytes,
                 function_id: function_id.to_string(),
                 logs: result.logs,
+                request_id: String::new(),
+            }
+        } else if result.is_overloaded() {
+            // 过载拒绝：503，提示客户端稍后重试
+            FunctionResponse {
+                status: 503,
+                headers: [
+                    ("Content-Type".to_string(), "application/json".to_string()),
+                    ("Retry-After".to_string(), "1".to_string()),
+                ]
+                .into_iter()
+                .collect(),
+                body: Some(serde_json::json!({
+                    "error": result.error.unwrap_or_else(|| "Server is overloaded".to_string())
+                })),
+                execution_time_ms: 0,
+                memory_used_bytes: 0,
+                function_id: function_id.to_string(),
+                logs: result.logs,
+                request_id: String::new(),
             }
         } else {
+            // 网关超时 504；客户端已断开时 499（不会被客户端收到，只用于记录）
+            let status = match result.failure_kind {
+                Some(FailureKind::Timeout) => 504,
+                Some(FailureKind::Cancelled) => 499,
+                _ => 500,
+            };
             FunctionResponse {
-                status: 500,
+                status,
                 headers: [("Content-Type".to_string(), "application/json".to_string())]
                     .into_iter()
                     .collect(),
@@ -178,6 +574,7 @@ impl NexoRuntime {
                 memory_used_bytes: result.memory_used_bytes,
                 function_id: function_id.to_string(),
                 logs: result.logs,
+                request_id: String::new(),
             }
         }
     }
@@ -198,3 +595,155 @@ impl Default for NexoRuntime {
         Self::new(100)
     }
 }
+
+/// 一次待执行的调用
+struct Invocation {
+    function: Function,
+    request: FunctionRequest,
+    request_data: serde_json::Value,
+    config: IsolateConfig,
+    priority: Priority,
+    /// 需要从输出中脱敏的密钥值
+    secret_values: Vec<String>,
+}
+
+impl Invocation {
+    /// 在池中执行并写入调用记录
+    async fn run(self, pool: Arc<IsolatePool>, logs: LogStore, cancel: CancelToken) -> FunctionResponse {
+        let Invocation {
+            function,
+            request,
+            request_data,
+            config,
+            priority,
+            secret_values,
+        } = self;
+
+        // 在 Isolate 池中执行
+        let mut result = pool
+            .execute_with_cancel(
+                &function.id,
+                &function.code,
+                request_data,
+                Some(config),
+                priority,
+                &cancel,
+            )
+            .await;
+
+        // 从日志和错误信息中脱敏密钥
+        if !secret_values.is_empty() {
+            let values: Vec<&str> = secret_values.iter().map(String::as_str).collect();
+            result.logs = result.logs.iter().map(|l| secret::redact(l, &values)).collect();
+            result.error = result.error.map(|e| secret::redact(&e, &values));
+        }
+
+        let error = result.error.clone();
+        let failure_kind = result.failure_kind;
+
+        // 转换为响应
+        let mut response = NexoRuntime::result_to_response(&function.id, result);
+        response.request_id = Uuid::new_v4().to_string();
+
+        // 写入调用记录
+        logs.record(InvocationRecord {
+            request_id: response.request_id.clone(),
+            function_id: function.id.clone(),
+            project_id: function.project_id.clone(),
+            version: function.version,
+            timestamp: Utc::now(),
+            method: request.method,
+            url: request.url,
+            status: response.status,
+            duration_ms: response.execution_time_ms,
+            memory_used_bytes: response.memory_used_bytes,
+            logs: response.logs.clone(),
+            error,
+            failure_kind,
+        })
+        .await;
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::DEFAULT_PROJECT;
+
+    #[test]
+    fn test_forwarded_headers() {
+        let response = |pairs: &[(&str, &str)]| FunctionResponse {
+            status: 200,
+            headers: pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            body: None,
+            execution_time_ms: 0,
+            memory_used_bytes: 0,
+            function_id: "fn-test".to_string(),
+            logs: Vec::new(),
+            request_id: String::new(),
+        };
+
+        // 重定向、认证质询、Cookie 和 CORS 响应头原样转发
+        let redirect = response(&[("Location", "/login"), ("Set-Cookie", "session=; Max-Age=0")]);
+        assert_eq!(
+            redirect.forwarded_headers(),
+            vec![
+                ("Location".to_string(), "/login".to_string()),
+                ("Set-Cookie".to_string(), "session=; Max-Age=0".to_string()),
+            ]
+        );
+        let challenge = response(&[("WWW-Authenticate", "Basic realm=\"admin\""), ("Content-Type", "text/plain")]);
+        assert_eq!(challenge.forwarded_headers().len(), 2);
+        let cors = response(&[
+            ("Access-Control-Allow-Origin", "https://app.example.com"),
+            ("Access-Control-Allow-Credentials", "true"),
+            ("Vary", "Origin"),
+        ]);
+        assert_eq!(cors.forwarded_headers().len(), 3);
+
+        let dropped = response(&[
+            ("Connection", "close"),
+            ("Transfer-Encoding", "chunked"),
+            ("content-length", "10"),
+            ("Proxy-Authenticate", "Basic"),
+            ("X-Function-Id", "spoofed"),
+            ("X-Request-Id", "spoofed"),
+            ("bad name", "x"),
+            ("X-Bad-Value", "a\nb"),
+        ]);
+        assert!(dropped.forwarded_headers().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_deploy_records_syntax_error() {
+        let runtime = NexoRuntime::new(2);
+        let req = CreateFunctionRequest {
+            name: "broken".to_string(),
+            code: "function handler(request) {\n  return {;\n}".to_string(),
+            route: format!("/broken-{}", uuid::Uuid::new_v4()),
+            methods: vec!["GET".to_string()],
+            env: HashMap::new(),
+            require_api_key: false,
+            auth: None,
+            rate_limit: None,
+            limits: None,
+            secrets: HashMap::new(),
+            smoke_test: None,
+        };
+
+        let function = runtime.deploy_function(DEFAULT_PROJECT, req).await.unwrap();
+        assert_eq!(function.status, FunctionStatus::Error);
+        let error = function.deploy_error.clone().unwrap();
+        assert!(error.message.contains("SyntaxError"));
+        assert_eq!(error.line, Some(2));
+
+        // 错误保存在函数上，而不是只在响应里
+        let stored = runtime.functions.get(&function.id).await.unwrap();
+        assert_eq!(stored.status, FunctionStatus::Error);
+        assert_eq!(stored.deploy_error, Some(error));
+
+        runtime.delete_function(&function.id).await.unwrap();
+    }
+}
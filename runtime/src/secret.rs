@@ -0,0 +1,334 @@
+//! Secrets - 函数密钥存储
+//!
+//! 密钥与普通环境变量分开保存，使用 AES-256-GCM 加密后落盘，
+//! 主密钥来自环境变量 `NEXO_MASTER_KEY`。API 只写不读，密钥值永远不会被返回，
+//! 执行时与普通环境变量一起注入 `ctx.env`，并从捕获的日志中脱敏。
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// 日志中密钥值的替换文本
+pub const REDACTED: &str = "[REDACTED]";
+
+/// 加密后的密钥
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EncryptedSecret {
+    /// 随机数（base64）
+    nonce: String,
+    /// 密文（base64）
+    ciphertext: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+/// 密钥元信息（不含值）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecretInfo {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// 设置密钥请求
+#[derive(Debug, Clone, Deserialize)]
+pub struct SetSecretRequest {
+    pub value: String,
+}
+
+/// 持久化数据
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct PersistedSecrets {
+    /// scope（函数 ID）-> 名称 -> 密钥
+    secrets: HashMap<String, HashMap<String, EncryptedSecret>>,
+}
+
+/// 密钥存储
+#[derive(Clone)]
+pub struct SecretStore {
+    secrets: Arc<RwLock<HashMap<String, HashMap<String, EncryptedSecret>>>>,
+    cipher: Option<Arc<Aes256Gcm>>,
+    storage_path: PathBuf,
+}
+
+impl SecretStore {
+    pub fn new() -> Self {
+        let storage_path = std::env::current_dir()
+            .unwrap_or_else(|_| PathBuf::from("."))
+            .join("data")
+            .join("secrets.json");
+
+        println!("[SecretStore] 数据存储路径: {}", storage_path.display());
+
+        let master_key = std::env::var("NEXO_MASTER_KEY").ok().filter(|k| !k.is_empty());
+        if master_key.is_none() {
+            println!("[SecretStore] 未设置 NEXO_MASTER_KEY，密钥功能不可用");
+        }
+
+        Self::with_storage_path(storage_path, master_key.as_deref())
+    }
+
+    pub fn with_storage_path(storage_path: PathBuf, master_key: Option<&str>) -> Self {
+        let secrets = match Self::load_from_path(&storage_path) {
+            Ok(data) => data.secrets,
+            Err(e) => {
+                eprintln!("[SecretStore] 加载失败: {}", e);
+                HashMap::new()
+            }
+        };
+
+        // 主密钥可以是任意长度的口令，统一派生为 256 位密钥
+        let cipher = master_key.map(|key| {
+            let key = Sha256::digest(key.as_bytes());
+            Arc::new(Aes256Gcm::new(&key))
+        });
+
+        Self {
+            secrets: Arc::new(RwLock::new(secrets)),
+            cipher,
+            storage_path,
+        }
+    }
+
+    /// 从文件加载数据（静态方法）
+    fn load_from_path(path: &PathBuf) -> Result<PersistedSecrets, String> {
+        if !path.exists() {
+            return Ok(PersistedSecrets::default());
+        }
+
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read storage file: {}", e))?;
+
+        serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse storage file: {}", e))
+    }
+
+    async fn save(&self) -> Result<(), String> {
+        let data = PersistedSecrets {
+            secrets: self.secrets.read().await.clone(),
+        };
+
+        if let Some(parent) = self.storage_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create storage directory: {}", e))?;
+        }
+
+        let content = serde_json::to_string_pretty(&data)
+            .map_err(|e| format!("Failed to serialize data: {}", e))?;
+
+        std::fs::write(&self.storage_path, content)
+            .map_err(|e| format!("Failed to write storage file: {}", e))?;
+
+        Ok(())
+    }
+
+    /// 是否配置了主密钥
+    pub fn is_enabled(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    fn cipher(&self) -> Result<&Aes256Gcm, String> {
+        self.cipher
+            .as_deref()
+            .ok_or_else(|| "Secrets are disabled: NEXO_MASTER_KEY is not set".to_string())
+    }
+
+    /// 关联数据，把密文绑定到 scope 和名称上，防止密文被挪用
+    fn aad(scope: &str, name: &str) -> Vec<u8> {
+        format!("{}/{}", scope, name).into_bytes()
+    }
+
+    /// 校验密钥名称（与环境变量命名规则一致）
+    fn validate_name(name: &str) -> Result<(), String> {
+        if name.is_empty() {
+            return Err("Secret name cannot be empty".to_string());
+        }
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err("Secret name may only contain letters, digits and '_'".to_string());
+        }
+        Ok(())
+    }
+
+    /// 设置密钥（新建或覆盖）
+    pub async fn set(&self, scope: &str, name: &str, value: &str) -> Result<SecretInfo, String> {
+        Self::validate_name(name)?;
+        let cipher = self.cipher()?;
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = Self::aad(scope, name);
+        let ciphertext = cipher
+            .encrypt(&nonce, Payload { msg: value.as_bytes(), aad: &aad })
+            .map_err(|_| "Failed to encrypt secret".to_string())?;
+
+        let now = Utc::now();
+        let mut secrets = self.secrets.write().await;
+        let entries = secrets.entry(scope.to_string()).or_default();
+        let created_at = entries.get(name).map(|s| s.created_at).unwrap_or(now);
+
+        entries.insert(
+            name.to_string(),
+            EncryptedSecret {
+                nonce: BASE64.encode(nonce),
+                ciphertext: BASE64.encode(ciphertext),
+                created_at,
+                updated_at: now,
+            },
+        );
+
+        drop(secrets);
+        self.save().await?;
+
+        Ok(SecretInfo {
+            name: name.to_string(),
+            created_at,
+            updated_at: now,
+        })
+    }
+
+    /// 列出密钥（只返回名称和时间）
+    pub async fn list(&self, scope: &str) -> Vec<SecretInfo> {
+        let secrets = self.secrets.read().await;
+        let mut infos: Vec<SecretInfo> = secrets
+            .get(scope)
+            .map(|entries| {
+                entries
+                    .iter()
+                    .map(|(name, s)| SecretInfo {
+                        name: name.clone(),
+                        created_at: s.created_at,
+                        updated_at: s.updated_at,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        infos
+    }
+
+    /// 删除密钥
+    pub async fn delete(&self, scope: &str, name: &str) -> Result<(), String> {
+        let mut secrets = self.secrets.write().await;
+        let removed = secrets
+            .get_mut(scope)
+            .and_then(|entries| entries.remove(name))
+            .is_some();
+
+        if !removed {
+            return Err("Secret not found".to_string());
+        }
+        if secrets.get(scope).is_some_and(|entries| entries.is_empty()) {
+            secrets.remove(scope);
+        }
+
+        drop(secrets);
+        self.save().await
+    }
+
+    /// 删除某个 scope 下的全部密钥
+    pub async fn delete_scope(&self, scope: &str) {
+        if self.secrets.write().await.remove(scope).is_some() {
+            if let Err(e) = self.save().await {
+                eprintln!("[SecretStore] 保存失败: {}", e);
+            }
+        }
+    }
+
+    /// 解密某个 scope 下的全部密钥，仅供注入 Isolate 使用
+    pub async fn reveal(&self, scope: &str) -> HashMap<String, String> {
+        let Some(cipher) = self.cipher.as_deref() else {
+            return HashMap::new();
+        };
+
+        let secrets = self.secrets.read().await;
+        let Some(entries) = secrets.get(scope) else {
+            return HashMap::new();
+        };
+
+        entries
+            .iter()
+            .filter_map(|(name, secret)| {
+                let value = Self::decrypt(cipher, scope, name, secret);
+                if value.is_none() {
+                    eprintln!("[SecretStore] 无法解密密钥: {}/{}", scope, name);
+                }
+                value.map(|v| (name.clone(), v))
+            })
+            .collect()
+    }
+
+    fn decrypt(cipher: &Aes256Gcm, scope: &str, name: &str, secret: &EncryptedSecret) -> Option<String> {
+        let nonce = BASE64.decode(&secret.nonce).ok()?;
+        let ciphertext = BASE64.decode(&secret.ciphertext).ok()?;
+        if nonce.len() != 12 {
+            return None;
+        }
+
+        let aad = Self::aad(scope, name);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &aad })
+            .ok()?;
+
+        String::from_utf8(plaintext).ok()
+    }
+}
+
+impl Default for SecretStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 把文本中出现的密钥值替换为 [REDACTED]
+pub fn redact(text: &str, values: &[&str]) -> String {
+    values
+        .iter()
+        .filter(|v| !v.is_empty())
+        .fold(text.to_string(), |acc, v| acc.replace(v, REDACTED))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(master_key: Option<&str>) -> SecretStore {
+        let path = std::env::temp_dir().join(format!("nexo-secrets-{}.json", uuid::Uuid::new_v4()));
+        SecretStore::with_storage_path(path, master_key)
+    }
+
+    #[tokio::test]
+    async fn test_secret_roundtrip() {
+        let store = temp_store(Some("test-master-key"));
+        store.set("fn-1", "API_TOKEN", "s3cr3t").await.unwrap();
+
+        let revealed = store.reveal("fn-1").await;
+        assert_eq!(revealed.get("API_TOKEN").map(String::as_str), Some("s3cr3t"));
+
+        // 落盘内容不包含明文
+        let content = std::fs::read_to_string(&store.storage_path).unwrap();
+        assert!(!content.contains("s3cr3t"));
+
+        // 换一个主密钥无法解密
+        let other = SecretStore::with_storage_path(store.storage_path.clone(), Some("other-key"));
+        assert!(other.reveal("fn-1").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_secrets_disabled_without_master_key() {
+        let store = temp_store(None);
+        assert!(store.set("fn-1", "API_TOKEN", "s3cr3t").await.is_err());
+    }
+
+    #[test]
+    fn test_redact() {
+        let redacted = redact("token=s3cr3t user=bob", &["s3cr3t", ""]);
+        assert_eq!(redacted, "token=[REDACTED] user=bob");
+    }
+}
@@ -0,0 +1,128 @@
+//! Shutdown - 优雅停机
+//!
+//! 收到 SIGTERM / SIGINT 后进入排空状态：就绪检查立即返回未就绪，但继续正常处理请求，
+//! 等负载均衡摘除实例（NEXO_SHUTDOWN_DELAY_SECS，默认 5 秒）后才停止接受新连接。
+//! 之后已在执行的请求最多等待排空超时（NEXO_DRAIN_TIMEOUT_SECS，默认 30 秒），
+//! 仍未完成的请求被丢弃，其 Isolate 执行随之被取消。
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// 默认排空超时（秒）
+const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 30;
+/// 默认停止接受连接前的等待时间（秒）
+const DEFAULT_SHUTDOWN_DELAY_SECS: u64 = 5;
+
+/// 停机状态
+#[derive(Default)]
+pub struct Shutdown {
+    draining: AtomicBool,
+    notify: Notify,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 是否正在排空
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// 进入排空状态
+    pub fn begin_drain(&self) {
+        if !self.draining.swap(true, Ordering::SeqCst) {
+            self.notify.notify_waiters();
+        }
+    }
+
+    /// 等待进入排空状态
+    pub async fn draining(&self) {
+        let notified = self.notify.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+        if self.is_draining() {
+            return;
+        }
+        notified.await;
+    }
+
+    /// 从环境变量 NEXO_DRAIN_TIMEOUT_SECS 读取排空超时
+    pub fn drain_timeout_from_env() -> Duration {
+        Self::secs_from_env("NEXO_DRAIN_TIMEOUT_SECS", DEFAULT_DRAIN_TIMEOUT_SECS)
+    }
+
+    /// 从环境变量 NEXO_SHUTDOWN_DELAY_SECS 读取就绪检查失败后继续服务的时间
+    pub fn shutdown_delay_from_env() -> Duration {
+        Self::secs_from_env("NEXO_SHUTDOWN_DELAY_SECS", DEFAULT_SHUTDOWN_DELAY_SECS)
+    }
+
+    fn secs_from_env(name: &str, default: u64) -> Duration {
+        let secs = std::env::var(name)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(default);
+        Duration::from_secs(secs)
+    }
+}
+
+/// 等待 SIGTERM 或 SIGINT（Ctrl+C）
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_begin_drain_wakes_waiters() {
+        let shutdown = Arc::new(Shutdown::new());
+        let waiter = tokio::spawn({
+            let shutdown = Arc::clone(&shutdown);
+            async move { shutdown.draining().await }
+        });
+
+        tokio::task::yield_now().await;
+        assert!(!shutdown.is_draining());
+        shutdown.begin_drain();
+
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(shutdown.is_draining());
+
+        // 已在排空时立即返回
+        shutdown.draining().await;
+    }
+}
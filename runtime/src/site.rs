@@ -1,22 +1,68 @@
 //! Static site storage - 静态站点存储和管理
+//!
+//! 站点元数据保存在 `sites.json`，文件内容以原始字节存放在内容寻址的
+//! [`BlobStore`] 中，站点文件只记录内容哈希。可压缩的文件在部署时预先生成
+//! gzip / brotli 版本，同样存放在 BlobStore 中。`_redirects` / `_headers`
+//! 在部署时解析为 [`SiteRules`]。
+//!
+//! 站点挂载在 `/site{route}` 下（路由为 `/` 时挂载在 `/site/`），
+//! 也可以绑定域名，直接在该域名的根路径下访问。
 
+use base64::Engine;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use uuid::Uuid;
 
+use crate::archive::{self, ArchiveEntry};
+use crate::blob::BlobStore;
+use crate::compress::{self, Encoding};
+use crate::edge::SiteMiddleware;
+use crate::project::{default_project_id, Project, ProjectStore};
+use crate::rewrite;
+use crate::rules::{self, SiteRules};
+
 /// 静态站点文件
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SiteFile {
     /// 文件路径
     pub path: String,
-    /// 文件内容
-    pub content: String,
+    /// 内容哈希（SHA-256）
+    #[serde(default)]
+    pub hash: String,
+    /// 文件大小（字节）
+    #[serde(default)]
+    pub size: u64,
     /// MIME 类型
     pub mime_type: String,
+    /// 预先压缩的版本
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<CompressedVariant>,
+    /// 旧版本内联在 sites.json 中的内容，加载时迁移到 BlobStore
+    #[serde(default, rename = "content", skip_serializing)]
+    legacy_content: Option<String>,
+}
+
+/// 文件的压缩版本
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressedVariant {
+    pub encoding: Encoding,
+    /// 压缩后内容的哈希
+    pub hash: String,
+    /// 压缩后的大小（字节）
+    pub size: u64,
+}
+
+impl SiteFile {
+    /// 按 `Accept-Encoding` 选择压缩版本
+    pub fn negotiate(&self, accept_encoding: &str) -> Option<&CompressedVariant> {
+        let available: Vec<Encoding> = self.variants.iter().map(|v| v.encoding).collect();
+        let encoding = compress::negotiate(accept_encoding, &available)?;
+        self.variants.iter().find(|v| v.encoding == encoding)
+    }
 }
 
 /// 静态站点
@@ -24,6 +70,9 @@ pub struct SiteFile {
 pub struct Site {
     /// 站点 ID
     pub id: String,
+    /// 所属项目
+    #[serde(default = "default_project_id")]
+    pub project_id: String,
     /// 站点名称
     pub name: String,
     /// 访问路由
@@ -38,6 +87,95 @@ pub struct Site {
     pub updated_at: DateTime<Utc>,
     /// 访问次数
     pub visits: u64,
+    /// 当前生效的部署
+    #[serde(default)]
+    pub active_deployment: String,
+    /// 部署历史（最新的在后）
+    #[serde(default)]
+    pub deployments: Vec<Deployment>,
+    /// 当前部署的重定向和响应头规则
+    #[serde(default, skip_serializing_if = "SiteRules::is_empty")]
+    pub rules: SiteRules,
+    /// 当前部署找不到文件时的处理方式
+    #[serde(default)]
+    pub mode: SiteMode,
+    /// 绑定的域名，在这些域名的根路径下直接访问站点
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub domains: Vec<String>,
+    /// 在静态文件之前执行的中间件函数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub middleware: Option<SiteMiddleware>,
+}
+
+/// 找不到文件时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SiteMode {
+    /// 单页应用：不带扩展名的路径回退到 `index.html`，缺失的资源返回 404
+    #[default]
+    #[serde(rename = "spa")]
+    Spa,
+    /// 纯静态站点：找不到的路径都返回 404
+    #[serde(rename = "static")]
+    Static,
+    /// 找不到的路径返回站点的 `404.html`（状态码 404）
+    #[serde(rename = "404.html")]
+    NotFoundPage,
+}
+
+/// 站点自带的错误页
+pub const NOT_FOUND_PAGE: &str = "404.html";
+
+impl SiteMode {
+    /// 未指定时按文件推断：带 `404.html` 的站点使用它作为错误页，否则按单页应用处理
+    fn resolve(mode: Option<SiteMode>, files: &[SiteFile]) -> Result<SiteMode, String> {
+        let has_page = files.iter().any(|f| f.path == NOT_FOUND_PAGE);
+        match mode {
+            Some(SiteMode::NotFoundPage) if !has_page => {
+                Err(format!("Mode '404.html' requires a {} file", NOT_FOUND_PAGE))
+            }
+            Some(mode) => Ok(mode),
+            None if has_page => Ok(SiteMode::NotFoundPage),
+            None => Ok(SiteMode::Spa),
+        }
+    }
+}
+
+/// 站点的一次部署
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deployment {
+    /// 部署 ID（同时用于预览 URL）
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    /// 这次部署的文件
+    pub files: Vec<SiteFile>,
+    /// 这次部署的规则
+    #[serde(default, skip_serializing_if = "SiteRules::is_empty")]
+    pub rules: SiteRules,
+    #[serde(default)]
+    pub mode: SiteMode,
+}
+
+impl Deployment {
+    fn new(files: Vec<SiteFile>, rules: SiteRules, mode: SiteMode) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            created_at: Utc::now(),
+            files,
+            rules,
+            mode,
+        }
+    }
+}
+
+/// 部署摘要（列表中不返回文件）
+#[derive(Debug, Clone, Serialize)]
+pub struct DeploymentInfo {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub files_count: usize,
+    pub size: u64,
+    /// 是否为当前生效的部署
+    pub active: bool,
 }
 
 /// 创建站点请求
@@ -52,23 +190,169 @@ pub struct CreateSiteRequest {
     /// 项目类型
     #[serde(default = "default_project_type")]
     pub project_type: String,
+    /// 重定向和响应头规则（优先于 `_redirects` / `_headers` 文件）
+    #[serde(default)]
+    pub rules: SiteRules,
+    /// 找不到文件时的处理方式（默认按文件推断）
+    #[serde(default)]
+    pub mode: Option<SiteMode>,
+    /// 部署时把 HTML/CSS 中的绝对资源路径改写到站点的挂载路径下
+    #[serde(default)]
+    pub rewrite_assets: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SiteFileRequest {
     pub path: String,
     pub content: String,
+    /// 内容编码，二进制文件使用 base64
+    #[serde(default)]
+    pub encoding: FileEncoding,
 }
 
-fn default_project_type() -> String {
+/// 文件内容编码
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileEncoding {
+    #[default]
+    Utf8,
+    Base64,
+}
+
+impl SiteFileRequest {
+    /// 解码为原始字节
+    pub fn decode(&self) -> Result<Vec<u8>, String> {
+        match self.encoding {
+            FileEncoding::Utf8 => Ok(self.content.clone().into_bytes()),
+            FileEncoding::Base64 => base64::engine::general_purpose::STANDARD
+                .decode(self.content.trim())
+                .map_err(|e| format!("Invalid base64 content for '{}': {}", self.path, e)),
+        }
+    }
+}
+
+impl CreateSiteRequest {
+    /// 解码文件内容
+    pub fn into_upload(self) -> Result<SiteUpload, String> {
+        let files = self
+            .files
+            .iter()
+            .map(|f| {
+                Ok(ArchiveEntry {
+                    path: f.path.clone(),
+                    content: f.decode()?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(SiteUpload {
+            name: self.name,
+            route: self.route,
+            project_type: self.project_type,
+            files,
+            rules: self.rules,
+            mode: self.mode,
+            asset_prefix: self.rewrite_assets.then(|| SITE_PREFIX.to_string()),
+        })
+    }
+}
+
+/// 已解码的站点上传（来自 JSON 或归档包）
+#[derive(Debug, Clone)]
+pub struct SiteUpload {
+    pub name: Option<String>,
+    pub route: Option<String>,
+    pub project_type: String,
+    pub files: Vec<ArchiveEntry>,
+    pub rules: SiteRules,
+    pub mode: Option<SiteMode>,
+    /// 开启资源改写时站点路由之前的 URL 前缀（如 `/site` 或 `/site/{项目前缀}`）
+    pub asset_prefix: Option<String>,
+}
+
+impl SiteUpload {
+    /// 站点挂载在 `route` 下时资源改写的目标路径
+    fn asset_base(&self, route: &str) -> Option<String> {
+        let prefix = self.asset_prefix.as_deref()?;
+        Some(match route {
+            "/" => prefix.to_string(),
+            route => format!("{}{}", prefix, route),
+        })
+    }
+}
+
+/// 站点网关的路径前缀
+pub const SITE_PREFIX: &str = "/site";
+
+pub fn default_project_type() -> String {
     "html".to_string()
 }
 
+/// 增量部署清单
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SiteManifest {
+    pub name: Option<String>,
+    pub route: Option<String>,
+    #[serde(default = "default_project_type")]
+    pub project_type: String,
+    /// 文件路径 -> 内容的 SHA-256（十六进制）
+    pub files: HashMap<String, String>,
+    /// 部署到已有站点（为空时创建新站点）
+    #[serde(default)]
+    pub site_id: Option<String>,
+    #[serde(default)]
+    pub mode: Option<SiteMode>,
+}
+
+/// 增量部署计划
+#[derive(Debug, Clone, Serialize)]
+pub struct DeployPlan {
+    pub deploy_id: String,
+    /// 需要上传的内容哈希（项目的站点还没有引用过的内容）
+    pub missing: Vec<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// 已登记的文件：(内容哈希, MIME 类型) -> 文件，相同的内容复用已生成的压缩版本
+type KnownFiles = HashMap<(String, String), SiteFile>;
+
+/// 等待上传完成的部署
+#[derive(Debug, Clone)]
+struct PendingDeploy {
+    id: String,
+    project_id: String,
+    name: Option<String>,
+    route: Option<String>,
+    project_type: String,
+    /// 文件路径 -> 内容哈希
+    files: HashMap<String, String>,
+    site_id: Option<String>,
+    mode: Option<SiteMode>,
+    /// 本次部署已上传的内容哈希
+    uploaded: HashSet<String>,
+    expires_at: DateTime<Utc>,
+}
+
+/// 每个站点默认保留的部署数
+const DEFAULT_MAX_DEPLOYMENTS: usize = 10;
+
+/// 从环境变量 NEXO_SITE_MAX_DEPLOYMENTS 读取每个站点保留的部署数
+fn max_deployments() -> usize {
+    std::env::var("NEXO_SITE_MAX_DEPLOYMENTS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_MAX_DEPLOYMENTS)
+}
+
+/// 未完成的增量部署保留时长（秒）
+const PENDING_DEPLOY_TTL_SECS: i64 = 3600;
+
 /// 持久化数据
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 struct PersistedSites {
     sites: HashMap<String, Site>,
-    routes: HashMap<String, String>, // route -> site_id
+    routes: HashMap<String, String>, // project_id:route -> site_id
 }
 
 /// 站点存储
@@ -77,6 +361,13 @@ pub struct SiteStore {
     sites: Arc<RwLock<HashMap<String, Site>>>,
     routes: Arc<RwLock<HashMap<String, String>>>,
     storage_path: PathBuf,
+    /// 文件内容
+    blobs: BlobStore,
+    /// 未完成的增量部署
+    pending: Arc<RwLock<HashMap<String, PendingDeploy>>>,
+    /// 写入内容到登记站点之间持有读锁，垃圾回收持有写锁，
+    /// 避免刚写入、尚未被站点引用的内容被回收
+    staging: Arc<RwLock<()>>,
 }
 
 impl SiteStore {
@@ -92,20 +383,82 @@ impl SiteStore {
     }
     
     pub fn with_storage_path(storage_path: PathBuf) -> Self {
+        let blobs = BlobStore::with_storage_path(
+            storage_path
+                .parent()
+                .map(|p| p.join("blobs"))
+                .unwrap_or_else(|| PathBuf::from("blobs")),
+        );
+
         // 先同步加载数据
-        let (sites_data, routes_data) = match Self::load_from_path(&storage_path) {
+        let mut sites_data = match Self::load_from_path(&storage_path) {
             Ok(data) => {
                 println!("[SiteStore] 已加载 {} 个站点", data.sites.len());
-                (data.sites, data.routes)
+                data.sites
             }
-            Err(_) => (HashMap::new(), HashMap::new()),
+            Err(_) => HashMap::new(),
         };
+        let migrated = Self::migrate_inline_files(&mut sites_data, &blobs)
+            + Self::migrate_deployments(&mut sites_data);
+
+        // 从站点重建路由表（兼容没有项目前缀的旧数据）
+        let routes_data = sites_data
+            .values()
+            .map(|s| (Self::route_key(&s.project_id, &s.route), s.id.clone()))
+            .collect();
         
-        Self {
+        let store = Self {
             sites: Arc::new(RwLock::new(sites_data)),
             routes: Arc::new(RwLock::new(routes_data)),
             storage_path,
+            blobs,
+            pending: Arc::new(RwLock::new(HashMap::new())),
+            staging: Arc::new(RwLock::new(())),
+        };
+
+        if migrated > 0 {
+            println!("[SiteStore] 已迁移 {} 项旧数据", migrated);
+            if let Err(e) = store.save_blocking() {
+                eprintln!("[SiteStore] 保存失败: {}", e);
+            }
+        }
+
+        store
+    }
+
+    /// 为没有部署历史的旧站点生成初始部署，返回迁移的站点数
+    fn migrate_deployments(sites: &mut HashMap<String, Site>) -> usize {
+        let mut migrated = 0;
+        for site in sites.values_mut().filter(|s| s.deployments.is_empty()) {
+            let mut deployment = Deployment::new(site.files.clone(), site.rules.clone(), site.mode);
+            deployment.created_at = site.updated_at;
+            site.active_deployment = deployment.id.clone();
+            site.deployments.push(deployment);
+            migrated += 1;
         }
+        migrated
+    }
+
+    /// 把旧数据中内联的文件内容写入 BlobStore，返回迁移的文件数
+    fn migrate_inline_files(sites: &mut HashMap<String, Site>, blobs: &BlobStore) -> usize {
+        let mut migrated = 0;
+        for file in sites.values_mut().flat_map(|s| s.files.iter_mut()) {
+            let Some(content) = file.legacy_content.take() else {
+                continue;
+            };
+            match blobs.put(content.as_bytes()) {
+                Ok(hash) => {
+                    file.hash = hash;
+                    file.size = content.len() as u64;
+                    migrated += 1;
+                }
+                Err(e) => {
+                    eprintln!("[SiteStore] 迁移 {} 失败: {}", file.path, e);
+                    file.legacy_content = Some(content);
+                }
+            }
+        }
+        migrated
     }
     
     /// 从文件加载数据（静态方法）
@@ -130,12 +483,25 @@ impl SiteStore {
             routes: routes.clone(),
         };
         
+        self.write(&data)
+    }
+
+    /// 启动时（还没有其他持有者）同步保存
+    fn save_blocking(&self) -> Result<(), String> {
+        let data = PersistedSites {
+            sites: self.sites.try_read().map_err(|e| e.to_string())?.clone(),
+            routes: self.routes.try_read().map_err(|e| e.to_string())?.clone(),
+        };
+        self.write(&data)
+    }
+
+    fn write(&self, data: &PersistedSites) -> Result<(), String> {
         if let Some(parent) = self.storage_path.parent() {
             std::fs::create_dir_all(parent)
                 .map_err(|e| format!("Failed to create storage directory: {}", e))?;
         }
         
-        let content = serde_json::to_string_pretty(&data)
+        let content = serde_json::to_string_pretty(data)
             .map_err(|e| format!("Failed to serialize data: {}", e))?;
         
         std::fs::write(&self.storage_path, content)
@@ -167,43 +533,227 @@ impl SiteStore {
         }.to_string()
     }
     
+    /// 把文件内容（及其压缩版本）写入 BlobStore
+    fn store_file(&self, path: &str, content: &[u8], known: &KnownFiles) -> Result<SiteFile, String> {
+        let mime_type = Self::get_mime_type(path);
+        let path = path.trim_start_matches('/').to_string();
+        let hash = self.blobs.put(content)?;
+        if let Some(file) = known.get(&(hash.clone(), mime_type.clone())) {
+            return Ok(SiteFile { path, ..file.clone() });
+        }
+
+        let variants = compress::compress_variants(&mime_type, content)
+            .into_iter()
+            .map(|(encoding, compressed)| {
+                Ok(CompressedVariant {
+                    encoding,
+                    hash: self.blobs.put(&compressed)?,
+                    size: compressed.len() as u64,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(SiteFile {
+            path,
+            hash,
+            size: content.len() as u64,
+            mime_type,
+            variants,
+            legacy_content: None,
+        })
+    }
+
+    /// 文件内容在磁盘上的路径
+    pub fn blob_path(&self, file: &SiteFile) -> PathBuf {
+        self.blobs.path(&file.hash)
+    }
+
+    /// 压缩版本在磁盘上的路径
+    pub fn variant_path(&self, variant: &CompressedVariant) -> PathBuf {
+        self.blobs.path(&variant.hash)
+    }
+
+    /// 删除不再被任何站点引用的文件内容（调用方不能持有 `staging` 锁）
+    async fn collect_garbage(&self) {
+        let _staging = self.staging.write().await;
+        let mut live: HashSet<String> = self
+            .sites
+            .read()
+            .await
+            .values()
+            .flat_map(|s| {
+                s.files
+                    .iter()
+                    .chain(s.deployments.iter().flat_map(|d| d.files.iter()))
+                    .flat_map(|f| {
+                        std::iter::once(f.hash.clone())
+                            .chain(f.variants.iter().map(|v| v.hash.clone()))
+                    })
+            })
+            .collect();
+        // 未完成的增量部署已上传的内容也要保留
+        live.extend(self.pending.read().await.values().flat_map(|d| d.files.values().cloned()));
+        let removed = self.blobs.gc(&live);
+        if removed > 0 {
+            println!("[SiteStore] 清理了 {} 个未引用的文件", removed);
+        }
+    }
+
+    /// 路由表的键：路由在项目内唯一
+    fn route_key(project_id: &str, route: &str) -> String {
+        format!("{}:{}", project_id, route)
+    }
+
+    /// 规范化站点路由：以 `/` 开头、不以 `/` 结尾，`/` 表示挂载在根路径
+    fn normalize_route(route: &str) -> Result<String, String> {
+        let route = route.trim();
+        if !route.starts_with('/') {
+            return Err(format!("Route '{}' must start with '/'", route));
+        }
+        let route = route.trim_end_matches('/');
+        if route.split('/').any(|segment| segment == "." || segment == "..") {
+            return Err(format!("Invalid route '{}'", route));
+        }
+        Ok(if route.is_empty() { "/".to_string() } else { route.to_string() })
+    }
+
+    /// 未指定路由时的默认路由
+    fn default_route() -> String {
+        format!("/{}", Utc::now().timestamp_millis())
+    }
+
+    /// 把上传的文件写入 BlobStore，需要时改写 HTML/CSS 中的绝对资源路径
+    fn stage_files(
+        &self,
+        files: &[ArchiveEntry],
+        asset_base: Option<&str>,
+        known: &KnownFiles,
+    ) -> Result<Vec<SiteFile>, String> {
+        files
+            .iter()
+            .map(|f| {
+                let rewritten = asset_base.and_then(|base| {
+                    rewrite::rewrite_asset_urls(&f.content, &Self::get_mime_type(&f.path), base)
+                });
+                self.store_file(&f.path, rewritten.as_deref().unwrap_or(&f.content), known)
+            })
+            .collect()
+    }
+
+    /// 在阻塞线程上暂存上传的文件（压缩可能耗时很久，不能占用异步工作线程）
+    async fn stage_upload(&self, files: Vec<ArchiveEntry>, asset_base: Option<String>) -> Result<Vec<SiteFile>, String> {
+        let known = self.known_files().await;
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || store.stage_files(&files, asset_base.as_deref(), &known))
+            .await
+            .map_err(|e| format!("Staging failed: {}", e))?
+    }
+
+    /// 已登记的文件，按 (内容哈希, MIME 类型) 索引
+    async fn known_files(&self) -> KnownFiles {
+        self.sites
+            .read()
+            .await
+            .values()
+            .flat_map(|s| s.files.iter().chain(s.deployments.iter().flat_map(|d| d.files.iter())))
+            .map(|f| ((f.hash.clone(), f.mime_type.clone()), f.clone()))
+            .collect()
+    }
+
     /// 创建站点
-    pub async fn create(&self, req: CreateSiteRequest) -> Result<Site, String> {
+    pub async fn create(&self, project_id: &str, mut req: SiteUpload) -> Result<Site, String> {
+        let route = match &req.route {
+            Some(route) => Self::normalize_route(route)?,
+            None => Self::default_route(),
+        };
+        let _staging = self.staging.read().await;
+        let asset_base = req.asset_base(&route);
+        let files = self.stage_upload(std::mem::take(&mut req.files), asset_base).await?;
+        let deployment = self.new_deployment(files, req.rules, req.mode)?;
+
+        self.insert_site(project_id, req.name, Some(route), req.project_type, deployment)
+            .await
+    }
+
+    /// 新建部署：解析站点根目录下的 `_redirects` / `_headers`，与请求中的规则合并
+    fn new_deployment(
+        &self,
+        files: Vec<SiteFile>,
+        config: SiteRules,
+        mode: Option<SiteMode>,
+    ) -> Result<Deployment, String> {
+        config.validate()?;
+        let mode = SiteMode::resolve(mode, &files)?;
+
+        let read = |name: &str| -> Result<Option<String>, String> {
+            let Some(file) = files.iter().find(|f| f.path == name) else {
+                return Ok(None);
+            };
+            let content = std::fs::read(self.blob_path(file))
+                .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+            String::from_utf8(content)
+                .map(Some)
+                .map_err(|_| format!("{} is not valid UTF-8", name))
+        };
+
+        let mut file_rules = SiteRules::default();
+        if let Some(text) = read(rules::REDIRECTS_FILE)? {
+            file_rules.redirects = rules::parse_redirects(&text)?;
+        }
+        if let Some(text) = read(rules::HEADERS_FILE)? {
+            file_rules.headers = rules::parse_headers(&text)?;
+        }
+
+        Ok(Deployment::new(files, config.merge(file_rules), mode))
+    }
+
+    /// 登记新站点（文件内容已在 BlobStore 中）
+    async fn insert_site(
+        &self,
+        project_id: &str,
+        name: Option<String>,
+        route: Option<String>,
+        project_type: String,
+        deployment: Deployment,
+    ) -> Result<Site, String> {
         let mut sites = self.sites.write().await;
         let mut routes = self.routes.write().await;
         
         let id = Uuid::new_v4().to_string();
         let timestamp = Utc::now().timestamp_millis();
-        let route = req.route.unwrap_or_else(|| format!("/{}", timestamp));
-        let name = req.name.unwrap_or_else(|| format!("site-{}", timestamp));
+        let route = match route {
+            Some(route) => Self::normalize_route(&route)?,
+            None => Self::default_route(),
+        };
+        let name = name.unwrap_or_else(|| format!("site-{}", timestamp));
         
         // 检查路由是否已存在
-        if routes.contains_key(&route) {
+        let route_key = Self::route_key(project_id, &route);
+        if routes.contains_key(&route_key) {
             return Err(format!("Route '{}' is already in use", route));
         }
         
         let now = Utc::now();
         
-        let files: Vec<SiteFile> = req.files.into_iter().map(|f| {
-            SiteFile {
-                mime_type: Self::get_mime_type(&f.path),
-                path: f.path,
-                content: f.content,
-            }
-        }).collect();
-        
         let site = Site {
             id: id.clone(),
+            project_id: project_id.to_string(),
             name,
             route: route.clone(),
-            files,
-            project_type: req.project_type,
+            files: deployment.files.clone(),
+            rules: deployment.rules.clone(),
+            mode: deployment.mode,
+            domains: Vec::new(),
+            middleware: None,
+            project_type,
             created_at: now,
             updated_at: now,
             visits: 0,
+            active_deployment: deployment.id.clone(),
+            deployments: vec![deployment],
         };
         
-        routes.insert(route, id.clone());
+        routes.insert(route_key, id.clone());
         sites.insert(id, site.clone());
         
         drop(sites);
@@ -214,35 +764,448 @@ impl SiteStore {
         
         Ok(site)
     }
+
+    /// 项目的站点（含历史部署）引用的内容哈希
+    ///
+    /// 增量部署只能复用这些内容，不向项目透露其他项目是否存有某个内容。
+    async fn project_hashes(&self, project_id: &str) -> HashSet<String> {
+        self.sites
+            .read()
+            .await
+            .values()
+            .filter(|s| s.project_id == project_id)
+            .flat_map(|s| s.files.iter().chain(s.deployments.iter().flat_map(|d| d.files.iter())))
+            .map(|f| f.hash.clone())
+            .collect()
+    }
+
+    /// 开始增量部署：登记清单，返回项目需要上传的内容哈希
+    pub async fn begin_deploy(&self, project_id: &str, manifest: SiteManifest) -> Result<DeployPlan, String> {
+        if manifest.files.is_empty() {
+            return Err("Manifest contains no files".to_string());
+        }
+
+        if let Some(site_id) = &manifest.site_id {
+            self.get(site_id)
+                .await
+                .filter(|s| s.project_id == project_id)
+                .ok_or("Site not found")?;
+        }
+
+        let mut files = HashMap::new();
+        for (path, hash) in manifest.files {
+            let hash = hash.to_lowercase();
+            if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(format!("Invalid SHA-256 for '{}': {}", path, hash));
+            }
+            files.insert(archive::sanitize_path(path.trim_start_matches('/'))?, hash);
+        }
+
+        let known = self.project_hashes(project_id).await;
+        let mut missing: Vec<String> = files
+            .values()
+            .filter(|hash| !known.contains(*hash) || !self.blobs.path(hash).exists())
+            .cloned()
+            .collect();
+        missing.sort();
+        missing.dedup();
+
+        let now = Utc::now();
+        let deploy = PendingDeploy {
+            id: Uuid::new_v4().to_string(),
+            project_id: project_id.to_string(),
+            name: manifest.name,
+            route: manifest.route,
+            project_type: manifest.project_type,
+            files,
+            site_id: manifest.site_id,
+            mode: manifest.mode,
+            uploaded: HashSet::new(),
+            expires_at: now + Duration::seconds(PENDING_DEPLOY_TTL_SECS),
+        };
+        let plan = DeployPlan {
+            deploy_id: deploy.id.clone(),
+            missing,
+            expires_at: deploy.expires_at,
+        };
+
+        let mut pending = self.pending.write().await;
+        pending.retain(|_, d| d.expires_at > now);
+        pending.insert(deploy.id.clone(), deploy);
+
+        Ok(plan)
+    }
+
+    /// 上传增量部署缺少的内容，内容的哈希必须与清单一致
+    pub async fn upload_blob(
+        &self,
+        project_id: &str,
+        deploy_id: &str,
+        hash: &str,
+        content: &[u8],
+    ) -> Result<(), String> {
+        let hash = hash.to_lowercase();
+        {
+            let pending = self.pending.read().await;
+            let deploy = pending
+                .get(deploy_id)
+                .filter(|d| d.project_id == project_id && d.expires_at > Utc::now())
+                .ok_or("Deploy not found or expired")?;
+            if !deploy.files.values().any(|h| *h == hash) {
+                return Err(format!("Hash {} is not part of this deploy", hash));
+            }
+        }
+
+        let actual = BlobStore::hash(content);
+        if actual != hash {
+            return Err(format!("Content hash mismatch: expected {}, got {}", hash, actual));
+        }
+        let _staging = self.staging.read().await;
+        self.blobs.put(content)?;
+
+        // 部署可能已经完成或过期
+        let mut pending = self.pending.write().await;
+        let deploy = pending.get_mut(deploy_id).ok_or("Deploy not found or expired")?;
+        deploy.uploaded.insert(hash);
+        Ok(())
+    }
+
+    /// 完成增量部署：所有内容都已上传后一次性登记站点（或替换已有站点的文件）
+    ///
+    /// 开始时就从待完成列表中取出部署，并发的完成请求只有一个能成功；失败时放回，
+    /// 补传内容后可以重试。
+    pub async fn finalize_deploy(&self, project_id: &str, deploy_id: &str) -> Result<Site, String> {
+        let deploy = {
+            let mut pending = self.pending.write().await;
+            pending
+                .get(deploy_id)
+                .filter(|d| d.project_id == project_id && d.expires_at > Utc::now())
+                .ok_or("Deploy not found or expired")?;
+            pending.remove(deploy_id).ok_or("Deploy not found or expired")?
+        };
+
+        let staging = self.staging.read().await;
+        let result = self.register_deploy(project_id, &deploy).await;
+        drop(staging);
+
+        match result {
+            Ok(site) => {
+                self.collect_garbage().await;
+                Ok(site)
+            }
+            Err(e) => {
+                self.pending.write().await.insert(deploy.id.clone(), deploy);
+                Err(e)
+            }
+        }
+    }
+
+    /// 登记增量部署的文件，调用方持有暂存锁
+    async fn register_deploy(&self, project_id: &str, deploy: &PendingDeploy) -> Result<Site, String> {
+        // 只能引用项目已有的内容或本次上传的内容
+        let known = self.project_hashes(project_id).await;
+        let mut missing: Vec<&str> = deploy
+            .files
+            .values()
+            .filter(|hash| {
+                !(known.contains(*hash) || deploy.uploaded.contains(*hash)) || !self.blobs.path(hash).exists()
+            })
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            missing.sort();
+            missing.dedup();
+            return Err(format!("Missing uploads: {}", missing.join(", ")));
+        }
+
+        // 已登记过的内容直接复用压缩版本，只读回新上传的内容生成压缩版本
+        let known = self.known_files().await;
+        let store = self.clone();
+        let entries: Vec<(String, String)> = deploy.files.clone().into_iter().collect();
+        let mut files = tokio::task::spawn_blocking(move || {
+            entries
+                .iter()
+                .map(|(path, hash)| {
+                    let mime_type = Self::get_mime_type(path);
+                    if let Some(file) = known.get(&(hash.clone(), mime_type)) {
+                        return Ok(SiteFile { path: path.clone(), ..file.clone() });
+                    }
+                    let content = std::fs::read(store.blobs.path(hash))
+                        .map_err(|e| format!("Failed to read blob {}: {}", hash, e))?;
+                    store.store_file(path, &content, &known)
+                })
+                .collect::<Result<Vec<_>, String>>()
+        })
+        .await
+        .map_err(|e| format!("Staging failed: {}", e))??;
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let deployment = self.new_deployment(files, SiteRules::default(), deploy.mode)?;
+        let (name, route) = (deploy.name.clone(), deploy.route.clone());
+        match &deploy.site_id {
+            Some(site_id) => {
+                let project_type = Some(deploy.project_type.clone());
+                self.deploy(project_id, site_id, name, route, project_type, deployment).await
+            }
+            None => {
+                self.insert_site(project_id, name, route, deploy.project_type.clone(), deployment)
+                    .await
+            }
+        }
+    }
     
+    /// 更新站点：新建一次部署并替换当前的文件，路由不变（除非指定了新路由）
+    pub async fn update(&self, project_id: &str, site_id: &str, mut req: SiteUpload) -> Result<Site, String> {
+        let route = match &req.route {
+            Some(route) => Self::normalize_route(route)?,
+            None => {
+                self.get(site_id)
+                    .await
+                    .filter(|s| s.project_id == project_id)
+                    .ok_or("Site not found")?
+                    .route
+            }
+        };
+        let staging = self.staging.read().await;
+        let asset_base = req.asset_base(&route);
+        let files = self.stage_upload(std::mem::take(&mut req.files), asset_base).await?;
+        let deployment = self.new_deployment(files, req.rules, req.mode)?;
+
+        let site = self
+            .deploy(project_id, site_id, req.name, req.route, Some(req.project_type), deployment)
+            .await?;
+        drop(staging);
+        self.collect_garbage().await;
+        Ok(site)
+    }
+
+    /// 把文件集合作为新部署切换上线（调用方持有 `staging` 读锁，之后负责回收旧内容）
+    async fn deploy(
+        &self,
+        project_id: &str,
+        site_id: &str,
+        name: Option<String>,
+        route: Option<String>,
+        project_type: Option<String>,
+        deployment: Deployment,
+    ) -> Result<Site, String> {
+        let mut sites = self.sites.write().await;
+        let mut routes = self.routes.write().await;
+
+        let site = sites
+            .get_mut(site_id)
+            .filter(|s| s.project_id == project_id)
+            .ok_or("Site not found")?;
+
+        // 更换路由
+        let route = route.map(|r| Self::normalize_route(&r)).transpose()?;
+        if let Some(route) = route.filter(|r| *r != site.route) {
+            let route_key = Self::route_key(project_id, &route);
+            if routes.contains_key(&route_key) {
+                return Err(format!("Route '{}' is already in use", route));
+            }
+            routes.remove(&Self::route_key(project_id, &site.route));
+            routes.insert(route_key, site.id.clone());
+            site.route = route;
+        }
+        if let Some(name) = name {
+            site.name = name;
+        }
+        if let Some(project_type) = project_type {
+            site.project_type = project_type;
+        }
+
+        site.files = deployment.files.clone();
+        site.rules = deployment.rules.clone();
+        site.mode = deployment.mode;
+        site.active_deployment = deployment.id.clone();
+        site.deployments.push(deployment);
+        site.updated_at = Utc::now();
+        Self::prune_deployments(site, max_deployments());
+
+        let site = site.clone();
+        drop(sites);
+        drop(routes);
+        if let Err(e) = self.save().await {
+            eprintln!("[SiteStore] 保存失败: {}", e);
+        }
+
+        Ok(site)
+    }
+
+    /// 只保留最近的部署（当前生效的部署总是保留）
+    fn prune_deployments(site: &mut Site, keep: usize) {
+        while site.deployments.len() > keep {
+            let Some(index) = site
+                .deployments
+                .iter()
+                .position(|d| d.id != site.active_deployment)
+            else {
+                break;
+            };
+            site.deployments.remove(index);
+        }
+    }
+
+    /// 回滚到历史部署
+    pub async fn rollback(&self, project_id: &str, site_id: &str, deployment_id: &str) -> Result<Site, String> {
+        let mut sites = self.sites.write().await;
+        let site = sites
+            .get_mut(site_id)
+            .filter(|s| s.project_id == project_id)
+            .ok_or("Site not found")?;
+
+        let deployment = site
+            .deployments
+            .iter()
+            .find(|d| d.id == deployment_id)
+            .ok_or("Deployment not found")?;
+        site.files = deployment.files.clone();
+        site.rules = deployment.rules.clone();
+        site.mode = deployment.mode;
+        site.active_deployment = deployment.id.clone();
+        site.updated_at = Utc::now();
+
+        let site = site.clone();
+        drop(sites);
+        if let Err(e) = self.save().await {
+            eprintln!("[SiteStore] 保存失败: {}", e);
+        }
+
+        Ok(site)
+    }
+
+    /// 列出站点的部署（最新的在前）
+    pub fn deployments(site: &Site) -> Vec<DeploymentInfo> {
+        site.deployments
+            .iter()
+            .rev()
+            .map(|d| DeploymentInfo {
+                id: d.id.clone(),
+                created_at: d.created_at,
+                files_count: d.files.len(),
+                size: d.files.iter().map(|f| f.size).sum(),
+                active: d.id == site.active_deployment,
+            })
+            .collect()
+    }
+
     /// 获取站点
     pub async fn get(&self, id: &str) -> Option<Site> {
         self.sites.read().await.get(id).cloned()
     }
     
-    /// 通过路由获取站点
-    pub async fn get_by_route(&self, route: &str) -> Option<Site> {
-        let routes = self.routes.read().await;
-        
-        // 精确匹配
-        if let Some(id) = routes.get(route) {
-            return self.sites.read().await.get(id).cloned();
+    /// 按请求路径查找站点：路由最长的前缀匹配，都不匹配时使用挂载在 `/` 的站点；
+    /// 返回站点和站点内的文件路径
+    pub async fn resolve(&self, project_id: &str, path: &str) -> Option<(Site, String)> {
+        let sites = self.sites.read().await;
+        sites
+            .values()
+            .filter(|s| s.project_id == project_id)
+            .filter_map(|site| {
+                let rest = match site.route.as_str() {
+                    "/" => path,
+                    route => match path.strip_prefix(route) {
+                        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+                        _ => return None,
+                    },
+                };
+                Some((site, rest))
+            })
+            .max_by_key(|(site, _)| if site.route == "/" { 0 } else { site.route.len() })
+            .map(|(site, rest)| (site.clone(), rest.trim_start_matches('/').to_string()))
+    }
+
+    /// 按域名查找站点
+    pub async fn get_by_domain(&self, host: &str) -> Option<Site> {
+        let host = ProjectStore::host_name(host);
+        self.sites
+            .read()
+            .await
+            .values()
+            .find(|s| s.domains.contains(&host))
+            .cloned()
+    }
+
+    /// 设置站点绑定的域名，域名必须已绑定到站点所属项目，且不能被其他站点使用
+    pub async fn set_domains(&self, project: &Project, site_id: &str, domains: Vec<String>) -> Result<Site, String> {
+        let mut domains: Vec<String> = domains
+            .iter()
+            .map(|d| ProjectStore::host_name(d))
+            .filter(|d| !d.is_empty())
+            .collect();
+        domains.sort();
+        domains.dedup();
+        if let Some(domain) = domains.iter().find(|d| !project.domains.contains(d)) {
+            return Err(format!("Domain '{}' is not bound to project '{}'", domain, project.id));
         }
-        
-        // 前缀匹配（用于静态资源）
-        for (site_route, id) in routes.iter() {
-            if route.starts_with(site_route) || route.starts_with(&format!("{}/", site_route)) {
-                return self.sites.read().await.get(id).cloned();
-            }
+
+        let mut sites = self.sites.write().await;
+        if let Some(domain) = domains.iter().find(|d| {
+            sites.values().any(|s| s.id != site_id && s.domains.contains(d))
+        }) {
+            return Err(format!("Domain '{}' is already used by another site", domain));
         }
-        
-        None
+
+        let site = sites
+            .get_mut(site_id)
+            .filter(|s| s.project_id == project.id)
+            .ok_or("Site not found")?;
+        site.domains = domains;
+        site.updated_at = Utc::now();
+
+        let site = site.clone();
+        drop(sites);
+        if let Err(e) = self.save().await {
+            eprintln!("[SiteStore] 保存失败: {}", e);
+        }
+
+        Ok(site)
+    }
+
+    /// 设置或移除站点的中间件函数（函数由调用方校验）
+    pub async fn set_middleware(
+        &self,
+        project_id: &str,
+        site_id: &str,
+        middleware: Option<SiteMiddleware>,
+    ) -> Result<Site, String> {
+        if let Some(middleware) = &middleware {
+            middleware.validate()?;
+        }
+
+        let mut sites = self.sites.write().await;
+        let site = sites
+            .get_mut(site_id)
+            .filter(|s| s.project_id == project_id)
+            .ok_or("Site not found")?;
+        site.middleware = middleware;
+        site.updated_at = Utc::now();
+
+        let site = site.clone();
+        drop(sites);
+        if let Err(e) = self.save().await {
+            eprintln!("[SiteStore] 保存失败: {}", e);
+        }
+
+        Ok(site)
     }
     
-    /// 列出所有站点
-    pub async fn list(&self) -> Vec<Site> {
+    /// 列出所有项目的站点
+    pub async fn list_all(&self) -> Vec<Site> {
         self.sites.read().await.values().cloned().collect()
     }
+
+    /// 列出项目内的所有站点
+    pub async fn list(&self, project_id: &str) -> Vec<Site> {
+        self.sites
+            .read()
+            .await
+            .values()
+            .filter(|s| s.project_id == project_id)
+            .cloned()
+            .collect()
+    }
     
     /// 删除站点
     pub async fn delete(&self, id: &str) -> Result<(), String> {
@@ -250,13 +1213,14 @@ impl SiteStore {
         let mut routes = self.routes.write().await;
         
         if let Some(site) = sites.remove(id) {
-            routes.remove(&site.route);
+            routes.remove(&Self::route_key(&site.project_id, &site.route));
             
             drop(sites);
             drop(routes);
             if let Err(e) = self.save().await {
                 eprintln!("[SiteStore] 保存失败: {}", e);
             }
+            self.collect_garbage().await;
             
             Ok(())
         } else {
@@ -264,6 +1228,14 @@ impl SiteStore {
         }
     }
     
+    /// 保存只在内存中累加的访问次数
+    pub async fn flush(&self) {
+        match self.save().await {
+            Ok(()) => println!("[SiteStore] 已保存"),
+            Err(e) => eprintln!("[SiteStore] 保存失败: {}", e),
+        }
+    }
+
     /// 记录访问
     pub async fn record_visit(&self, id: &str) {
         if let Some(site) = self.sites.write().await.get_mut(id) {
@@ -274,38 +1246,470 @@ impl SiteStore {
     /// 获取站点文件
     pub async fn get_file(&self, site_id: &str, file_path: &str) -> Option<SiteFile> {
         let sites = self.sites.read().await;
-        if let Some(site) = sites.get(site_id) {
-            // 规范化路径
-            let normalized_path = file_path.trim_start_matches('/');
-            
-            // 精确匹配
-            if let Some(file) = site.files.iter().find(|f| f.path == normalized_path) {
-                return Some(file.clone());
-            }
-            
-            // 如果是目录，尝试 index.html
-            let index_path = if normalized_path.is_empty() {
-                "index.html".to_string()
-            } else {
-                format!("{}/index.html", normalized_path.trim_end_matches('/'))
-            };
-            
-            if let Some(file) = site.files.iter().find(|f| f.path == index_path) {
-                return Some(file.clone());
-            }
-            
-            // SPA fallback - 返回 index.html
-            if let Some(file) = site.files.iter().find(|f| f.path == "index.html") {
-                return Some(file.clone());
-            }
-        }
-        None
+        let site = sites.get(site_id)?;
+        find_file(&site.files, file_path, site.mode).cloned()
+    }
+
+    /// 站点自带的 404 页面（找不到文件时作为响应体）
+    pub async fn get_not_found_page(&self, site_id: &str) -> Option<SiteFile> {
+        let sites = self.sites.read().await;
+        let site = sites.get(site_id)?;
+        site.files.iter().find(|f| f.path == NOT_FOUND_PAGE).cloned()
+    }
+
+    /// 获取请求路径对应的文件，不做 SPA 回退（用于判断重定向规则是否生效）
+    pub async fn get_exact_file(&self, site_id: &str, file_path: &str) -> Option<SiteFile> {
+        let sites = self.sites.read().await;
+        let site = sites.get(site_id)?;
+        find_exact(&site.files, file_path).cloned()
+    }
+
+    /// 按部署 ID 获取文件（用于预览），返回站点 ID、部署时间和文件
+    pub async fn get_deployment_file(
+        &self,
+        deployment_id: &str,
+        file_path: &str,
+    ) -> Option<(String, DateTime<Utc>, SiteFile)> {
+        let sites = self.sites.read().await;
+        sites.values().find_map(|site| {
+            let deployment = site.deployments.iter().find(|d| d.id == deployment_id)?;
+            find_file(&deployment.files, file_path, deployment.mode)
+                .map(|f| (site.id.clone(), deployment.created_at, f.clone()))
+        })
     }
 }
 
+/// 在文件集合中查找请求的路径
+fn find_file<'a>(files: &'a [SiteFile], file_path: &str, mode: SiteMode) -> Option<&'a SiteFile> {
+    if let Some(file) = find_exact(files, file_path) {
+        return Some(file);
+    }
+
+    // SPA fallback - 页面路由返回 index.html，带扩展名的资源缺失时不回退
+    if mode == SiteMode::Spa && !has_extension(file_path) {
+        return files.iter().find(|f| f.path == "index.html");
+    }
+    None
+}
+
+/// 路径的最后一段是否带扩展名（如 `app.js`）
+fn has_extension(path: &str) -> bool {
+    let name = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+    name.rsplit_once('.').is_some_and(|(stem, ext)| !stem.is_empty() && !ext.is_empty())
+}
+
+/// 查找请求路径对应的文件（不做 SPA 回退），规则文件不对外提供
+fn find_exact<'a>(files: &'a [SiteFile], file_path: &str) -> Option<&'a SiteFile> {
+    // 规范化路径
+    let normalized_path = file_path.trim_start_matches('/');
+    if normalized_path == rules::REDIRECTS_FILE || normalized_path == rules::HEADERS_FILE {
+        return None;
+    }
+    
+    // 精确匹配
+    if let Some(file) = files.iter().find(|f| f.path == normalized_path) {
+        return Some(file);
+    }
+    
+    // 如果是目录，尝试 index.html
+    let index_path = if normalized_path.is_empty() {
+        "index.html".to_string()
+    } else {
+        format!("{}/index.html", normalized_path.trim_end_matches('/'))
+    };
+    
+    files.iter().find(|f| f.path == index_path)
+}
+
 impl Default for SiteStore {
     fn default() -> Self {
         Self::new()
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::{ProjectQuotas, DEFAULT_PROJECT};
+
+    /// 临时数据目录
+    fn test_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("nexo-sites-{}", uuid::Uuid::new_v4()))
+    }
+
+    /// 使用临时目录的站点存储，测试结束时删除返回的目录
+    /// 绑定了指定域名的项目
+    fn test_project(id: &str, domains: &[&str]) -> Project {
+        Project {
+            id: id.to_string(),
+            name: id.to_string(),
+            route_prefix: None,
+            domains: domains.iter().map(|d| d.to_string()).collect(),
+            quotas: ProjectQuotas::default(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn test_store() -> (SiteStore, PathBuf) {
+        let dir = test_dir();
+        (SiteStore::with_storage_path(dir.join("sites.json")), dir)
+    }
+
+    /// 由 (路径, 内容) 构造的站点上传
+    fn upload(route: &str, files: &[(&str, &str)]) -> SiteUpload {
+        SiteUpload {
+            name: None,
+            route: Some(route.to_string()),
+            project_type: "html".to_string(),
+            files: files
+                .iter()
+                .map(|(path, content)| ArchiveEntry {
+                    path: path.to_string(),
+                    content: content.as_bytes().to_vec(),
+                })
+                .collect(),
+            rules: SiteRules::default(),
+            mode: None,
+            asset_prefix: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_binary_files_and_legacy_migration() {
+        let dir = test_dir();
+        let storage_path = dir.join("sites.json");
+
+        // 旧格式：文件内容内联在 sites.json 中
+        let legacy = serde_json::json!({
+            "sites": { "old": {
+                "id": "old", "name": "old", "route": "/old", "project_type": "html",
+                "created_at": Utc::now(), "updated_at": Utc::now(), "visits": 0,
+                "files": [{ "path": "index.html", "content": "<h1>hi</h1>", "mime_type": "text/html; charset=utf-8" }]
+            }},
+            "routes": {}
+        });
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&storage_path, legacy.to_string()).unwrap();
+
+        let store = SiteStore::with_storage_path(storage_path.clone());
+        let file = store.get_file("old", "/").await.unwrap();
+        assert_eq!(std::fs::read(store.blob_path(&file)).unwrap(), b"<h1>hi</h1>");
+        assert!(!std::fs::read_to_string(&storage_path).unwrap().contains("<h1>hi</h1>"));
+
+        let png = [0x89u8, b'P', b'N', b'G', 0x00, 0xff];
+        let site = store
+            .create(DEFAULT_PROJECT, CreateSiteRequest {
+                name: None,
+                route: Some("/img".to_string()),
+                files: vec![SiteFileRequest {
+                    path: "logo.png".to_string(),
+                    content: base64::engine::general_purpose::STANDARD.encode(png),
+                    encoding: FileEncoding::Base64,
+                }],
+                project_type: "html".to_string(),
+                rules: SiteRules::default(),
+                mode: None,
+                rewrite_assets: false,
+            }.into_upload().unwrap())
+            .await
+            .unwrap();
+        let file = store.get_file(&site.id, "logo.png").await.unwrap();
+        assert_eq!(file.mime_type, "image/png");
+        assert_eq!(file.size, png.len() as u64);
+        assert_eq!(std::fs::read(store.blob_path(&file)).unwrap(), png);
+
+        // 删除站点后清理不再引用的内容
+        store.delete(&site.id).await.unwrap();
+        assert!(!store.blob_path(&file).exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_incremental_deploy() {
+        let (store, dir) = test_store();
+
+        let shared = "body { color: red }";
+        let page = b"<h1>v2</h1>".to_vec();
+        store.create(DEFAULT_PROJECT, upload("/base", &[("style.css", shared)])).await.unwrap();
+
+        let manifest = SiteManifest {
+            name: None,
+            route: Some("/docs".to_string()),
+            project_type: "html".to_string(),
+            files: [
+                ("/index.html".to_string(), BlobStore::hash(&page)),
+                ("style.css".to_string(), BlobStore::hash(shared.as_bytes())),
+            ]
+            .into_iter()
+            .collect(),
+            site_id: None,
+            mode: None,
+        };
+
+        // 项目已有的内容不需要再上传；其他项目看不到这些内容是否存在
+        let plan = store.begin_deploy(DEFAULT_PROJECT, manifest.clone()).await.unwrap();
+        assert_eq!(plan.missing, vec![BlobStore::hash(&page)]);
+        let other = store.begin_deploy("other", manifest).await.unwrap();
+        assert_eq!(other.missing.len(), 2);
+        assert!(store.finalize_deploy("other", &other.deploy_id).await.unwrap_err().contains("Missing"));
+
+        // 失败的完成请求不会丢掉部署，补传后可以重试
+        assert!(store.finalize_deploy(DEFAULT_PROJECT, &plan.deploy_id).await.is_err());
+
+        let wrong = store
+            .upload_blob(DEFAULT_PROJECT, &plan.deploy_id, &plan.missing[0], b"tampered")
+            .await;
+        assert!(wrong.unwrap_err().contains("mismatch"));
+        store
+            .upload_blob(DEFAULT_PROJECT, &plan.deploy_id, &plan.missing[0], &page)
+            .await
+            .unwrap();
+
+        // 并发的完成请求只有一个成功
+        let (first, second) = tokio::join!(
+            store.finalize_deploy(DEFAULT_PROJECT, &plan.deploy_id),
+            store.finalize_deploy(DEFAULT_PROJECT, &plan.deploy_id),
+        );
+        assert!(first.is_ok() != second.is_ok());
+        let site = first.or(second).unwrap();
+        assert_eq!(site.files.len(), 2);
+        let index = store.get_file(&site.id, "index.html").await.unwrap();
+        assert_eq!(index.size, page.len() as u64);
+        assert_eq!(store.list(DEFAULT_PROJECT).await.len(), 2);
+
+        // 部署只能完成一次
+        assert!(store.finalize_deploy(DEFAULT_PROJECT, &plan.deploy_id).await.is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_reuse_compressed_variants() {
+        let (store, dir) = test_store();
+
+        let css = "body { color: red }\n".repeat(40);
+        let site = store.create(DEFAULT_PROJECT, upload("/a", &[("style.css", &css)])).await.unwrap();
+        let file = store.get_exact_file(&site.id, "style.css").await.unwrap();
+        assert!(!file.variants.is_empty());
+
+        // 已登记过的内容直接复用压缩版本，不再重新压缩
+        let mut known = KnownFiles::new();
+        let marker = SiteFile { variants: Vec::new(), ..file.clone() };
+        known.insert((file.hash.clone(), file.mime_type.clone()), marker);
+        let staged = store.store_file("/copy.css", css.as_bytes(), &known).unwrap();
+        assert_eq!(staged.path, "copy.css");
+        assert!(staged.variants.is_empty());
+
+        // 重新部署相同的内容得到相同的压缩版本
+        let site = store.update(DEFAULT_PROJECT, &site.id, upload("/a", &[("style.css", &css)])).await.unwrap();
+        let redeployed = store.get_exact_file(&site.id, "style.css").await.unwrap();
+        assert_eq!(redeployed.variants.len(), file.variants.len());
+        assert_eq!(redeployed.variants[0].hash, file.variants[0].hash);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_gc_keeps_staged_files() {
+        let (store, dir) = test_store();
+
+        // 内容已写入但站点尚未登记时，并发的回收要等登记完成
+        let staging = store.staging.read().await;
+        let files = store.stage_files(&upload("/staged", &[("index.html", "staged")]).files, None, &KnownFiles::new()).unwrap();
+        let gc = tokio::spawn({
+            let store = store.clone();
+            async move { store.collect_garbage().await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert!(!gc.is_finished());
+        assert!(store.blob_path(&files[0]).exists());
+
+        let deployment = store.new_deployment(files, SiteRules::default(), None).unwrap();
+        let site = store
+            .insert_site(DEFAULT_PROJECT, None, Some("/staged".to_string()), "html".to_string(), deployment)
+            .await
+            .unwrap();
+        drop(staging);
+        gc.await.unwrap();
+
+        let index = store.get_file(&site.id, "/").await.unwrap();
+        assert_eq!(std::fs::read(store.blob_path(&index)).unwrap(), b"staged");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_redeploy_and_rollback() {
+        let (store, dir) = test_store();
+
+        let site = store.create(DEFAULT_PROJECT, upload("/app", &[("index.html", "<h1>v1</h1>")])).await.unwrap();
+        let v1 = site.active_deployment.clone();
+
+        let mut v2 = upload("/app", &[("index.html", "<h1>v2</h1>")]);
+        v2.route = None;
+        let site = store.update(DEFAULT_PROJECT, &site.id, v2).await.unwrap();
+        assert_eq!(site.route, "/app");
+        assert_eq!(site.deployments.len(), 2);
+        assert_ne!(site.active_deployment, v1);
+        let live = store.get_file(&site.id, "/").await.unwrap();
+        assert_eq!(std::fs::read(store.blob_path(&live)).unwrap(), b"<h1>v2</h1>");
+
+        // 旧部署仍可预览，内容没有被回收
+        let (site_id, _, old) = store.get_deployment_file(&v1, "/").await.unwrap();
+        assert_eq!(site_id, site.id);
+        assert_eq!(std::fs::read(store.blob_path(&old)).unwrap(), b"<h1>v1</h1>");
+
+        let site = store.rollback(DEFAULT_PROJECT, &site.id, &v1).await.unwrap();
+        assert_eq!(site.active_deployment, v1);
+        assert_eq!(store.get_file(&site.id, "/").await.unwrap().hash, old.hash);
+        assert!(store.rollback(DEFAULT_PROJECT, &site.id, "missing").await.is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_rules_from_files() {
+        let (store, dir) = test_store();
+
+        let with_redirects = |redirects: &str| {
+            let mut upload = upload("/rules", &[
+                ("index.html", "<h1>home</h1>"),
+                ("_redirects", redirects),
+                ("_headers", "/*\n  X-Frame-Options: DENY\n"),
+            ]);
+            upload.rules.redirects = rules::parse_redirects("/first /from-json 302").unwrap();
+            upload
+        };
+
+        let site = store
+            .create(DEFAULT_PROJECT, with_redirects("/old /new\n/api/* /fn/api/:splat 200"))
+            .await
+            .unwrap();
+        assert_eq!(site.rules.redirects.len(), 3);
+        assert_eq!(site.rules.redirects[0].to, "/from-json");
+        assert_eq!(site.rules.redirect("/api/users").unwrap().to, "/fn/api/users");
+        assert_eq!(site.rules.headers_for("/index.html"), vec![("X-Frame-Options", "DENY")]);
+
+        // 规则文件本身不对外提供
+        assert!(store.get_exact_file(&site.id, "_redirects").await.is_none());
+        assert!(store.get_exact_file(&site.id, "old").await.is_none());
+
+        // 规则无效时拒绝部署
+        let err = store.update(DEFAULT_PROJECT, &site.id, with_redirects("/a /b 418")).await.unwrap_err();
+        assert!(err.contains("_redirects line 1"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_site_modes() {
+        let (store, dir) = test_store();
+
+        let with_mode = |route: &str, files: &[(&str, &str)], mode: Option<SiteMode>| {
+            let mut upload = upload(route, files);
+            upload.mode = mode;
+            upload
+        };
+        let index = ("index.html", "index");
+        let not_found = ("404.html", "404");
+
+        // 单页应用：页面路由回退到 index.html，缺失的资源不回退
+        let spa = store.create(DEFAULT_PROJECT, upload("/spa", &[index])).await.unwrap();
+        assert_eq!(spa.mode, SiteMode::Spa);
+        assert_eq!(store.get_file(&spa.id, "settings/profile").await.unwrap().path, "index.html");
+        assert!(store.get_file(&spa.id, "assets/missing.js").await.is_none());
+
+        let plain = with_mode("/static", &[index], Some(SiteMode::Static));
+        let plain = store.create(DEFAULT_PROJECT, plain).await.unwrap();
+        assert!(store.get_file(&plain.id, "about").await.is_none());
+        assert_eq!(store.get_file(&plain.id, "/").await.unwrap().path, "index.html");
+
+        // 带 404.html 的站点自动使用它作为错误页
+        let docs = store.create(DEFAULT_PROJECT, upload("/docs", &[index, not_found])).await.unwrap();
+        assert_eq!(docs.mode, SiteMode::NotFoundPage);
+        assert!(store.get_file(&docs.id, "about").await.is_none());
+        assert_eq!(store.get_not_found_page(&docs.id).await.unwrap().path, "404.html");
+
+        let invalid = with_mode("/invalid", &[index], Some(SiteMode::NotFoundPage));
+        assert!(store.create(DEFAULT_PROJECT, invalid).await.unwrap_err().contains("404.html"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_routes_and_domains() {
+        let (store, dir) = test_store();
+
+        let site = |route: &str, html: &str| upload(route, &[("index.html", html), ("assets/app.js", "1")]);
+
+        let root = store.create(DEFAULT_PROJECT, site("/", "root")).await.unwrap();
+        let mut docs = site("/docs/", r#"<script src="/assets/app.js"></script>"#);
+        docs.asset_prefix = Some(SITE_PREFIX.to_string());
+        let docs = store.create(DEFAULT_PROJECT, docs).await.unwrap();
+        assert_eq!(docs.route, "/docs");
+        assert!(store.create(DEFAULT_PROJECT, site("docs", "x")).await.is_err());
+
+        // 路由前缀匹配，不再跨站点查找文件
+        let (site, path) = store.resolve(DEFAULT_PROJECT, "/docs/assets/app.js").await.unwrap();
+        assert_eq!((site.id.as_str(), path.as_str()), (docs.id.as_str(), "assets/app.js"));
+        let (site, path) = store.resolve(DEFAULT_PROJECT, "/assets/app.js").await.unwrap();
+        assert_eq!((site.id.as_str(), path.as_str()), (root.id.as_str(), "assets/app.js"));
+        let (site, _) = store.resolve(DEFAULT_PROJECT, "/docsx").await.unwrap();
+        assert_eq!(site.id, root.id);
+        assert!(store.resolve("other", "/docs").await.is_none());
+
+        // 部署时改写绝对资源路径
+        let index = store.get_file(&docs.id, "/").await.unwrap();
+        let html = std::fs::read_to_string(store.blob_path(&index)).unwrap();
+        assert_eq!(html, r#"<script src="/site/docs/assets/app.js"></script>"#);
+
+        let project = test_project(DEFAULT_PROJECT, &["docs.example.com"]);
+        let site = store
+            .set_domains(&project, &docs.id, vec!["Docs.Example.com:443".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(site.domains, vec!["docs.example.com"]);
+        assert_eq!(store.get_by_domain("docs.example.com:8080").await.unwrap().id, docs.id);
+        let taken = store.set_domains(&project, &root.id, vec!["docs.example.com".to_string()]).await;
+        assert!(taken.unwrap_err().contains("already used"));
+
+        // 只能绑定本项目的域名
+        let unbound = store.set_domains(&project, &root.id, vec!["www.example.com".to_string()]).await;
+        assert!(unbound.unwrap_err().contains("not bound"));
+        let other = store.create("other", upload("/", &[("index.html", "other")])).await.unwrap();
+        let stolen = store.set_domains(&project, &other.id, vec!["docs.example.com".to_string()]).await;
+        assert!(stolen.unwrap_err().contains("already used"));
+        let foreign = test_project("other", &["other.example.com"]);
+        let foreign = store.set_domains(&foreign, &docs.id, vec!["other.example.com".to_string()]).await;
+        assert_eq!(foreign.unwrap_err(), "Site not found");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_site_middleware() {
+        let (store, dir) = test_store();
+        let site = store.create(DEFAULT_PROJECT, upload("/admin", &[("index.html", "admin")])).await.unwrap();
+
+        let middleware = SiteMiddleware { function_id: "fn-auth".to_string(), paths: vec!["/admin/*".to_string()] };
+        let updated = store.set_middleware(DEFAULT_PROJECT, &site.id, Some(middleware.clone())).await.unwrap();
+        assert_eq!(updated.middleware, Some(middleware.clone()));
+
+        // 重新部署不影响中间件，重新加载后仍然保留
+        store.update(DEFAULT_PROJECT, &site.id, upload("/admin", &[("index.html", "v2")])).await.unwrap();
+        let reloaded = SiteStore::with_storage_path(dir.join("sites.json"));
+        assert_eq!(reloaded.get(&site.id).await.unwrap().middleware, Some(middleware));
+
+        let invalid = SiteMiddleware { function_id: "fn-auth".to_string(), paths: vec!["admin".to_string()] };
+        assert!(store.set_middleware(DEFAULT_PROJECT, &site.id, Some(invalid)).await.is_err());
+        assert!(store.set_middleware("other", &site.id, None).await.is_err());
+
+        let cleared = store.set_middleware(DEFAULT_PROJECT, &site.id, None).await.unwrap();
+        assert!(cleared.middleware.is_none());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
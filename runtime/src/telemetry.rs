@@ -0,0 +1,121 @@
+//! Telemetry - OpenTelemetry 链路追踪
+//!
+//! 设置 `OTEL_EXPORTER_OTLP_ENDPOINT` 后，`tracing` 的 span 通过 OTLP 导出。
+//! 网关从请求头中读取 W3C `traceparent` 作为父级，并把网关 span 的
+//! `traceparent` 写回传给函数的请求头（`request.headers.traceparent`）。
+//!
+//! TODO: 出站传播尚未实现。函数通过出站 `fetch` 调用下游时应自动附加当前的
+//! `traceparent`，但 Isolate 目前没有出站 HTTP 绑定（`env.ASSETS.fetch` 只是站点中间件的
+//! 指令），这一项需要和出站 `fetch` 一起完成。在此之前传播只到函数收到的请求为止，
+//! 函数需要自己从请求头中取出 `traceparent` 并附加到下游请求上。
+
+use opentelemetry::global;
+use opentelemetry::trace::TraceError;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::{trace as sdktrace, Resource};
+use std::collections::HashMap;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// W3C Trace Context 请求头
+pub const TRACEPARENT: &str = "traceparent";
+
+/// 初始化追踪
+///
+/// 总是注册 W3C Trace Context 传播器；只有设置了 `OTEL_EXPORTER_OTLP_ENDPOINT`
+/// 时才创建 OTLP 导出器，服务名取 `OTEL_SERVICE_NAME`（默认 nexo-runtime）。
+pub fn init_tracer() -> Result<Option<sdktrace::Tracer>, TraceError> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let Some(endpoint) = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|e| !e.is_empty())
+    else {
+        return Ok(None);
+    };
+    let service_name =
+        std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "nexo-runtime".to_string());
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            sdktrace::config()
+                .with_resource(Resource::new(vec![KeyValue::new("service.name", service_name)])),
+        )
+        .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+
+    Ok(Some(tracer))
+}
+
+/// 导出剩余的 span 并关闭导出器
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// 以请求头中的 `traceparent` 作为 span 的父级（请求头的键为小写）
+pub fn set_parent_from_headers(span: &tracing::Span, headers: &HashMap<String, String>) {
+    if headers.contains_key(TRACEPARENT) {
+        let context = global::get_text_map_propagator(|p| p.extract(headers));
+        span.set_parent(context);
+    }
+}
+
+/// span 对应的 `traceparent`；没有启用导出时返回 None
+pub fn traceparent(span: &tracing::Span) -> Option<String> {
+    let context = span.context();
+    let mut carrier = HashMap::new();
+    global::get_text_map_propagator(|p| p.inject_context(&context, &mut carrier));
+    carrier.remove(TRACEPARENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_traceparent_propagation() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        // 用内存导出器代替 OTLP collector
+        let exporter = InMemorySpanExporter::default();
+        let provider = sdktrace::TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        let incoming = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let headers: HashMap<String, String> =
+            [(TRACEPARENT.to_string(), incoming.to_string())].into_iter().collect();
+
+        let outgoing = tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("gateway.invoke");
+            set_parent_from_headers(&span, &headers);
+            span.in_scope(|| tracing::info_span!("pool.execute").in_scope(|| {}));
+            traceparent(&span)
+        })
+        .unwrap();
+
+        // 同一条 trace，父级换成了网关 span
+        assert!(outgoing.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert_ne!(outgoing, incoming);
+
+        provider.force_flush();
+        let spans = exporter.get_finished_spans().unwrap();
+        let names: Vec<_> = spans.iter().map(|s| s.name.to_string()).collect();
+        assert!(names.contains(&"gateway.invoke".to_string()));
+        assert!(names.contains(&"pool.execute".to_string()));
+        assert!(spans
+            .iter()
+            .all(|s| s.span_context.trace_id().to_string() == "4bf92f3577b34da6a3ce929d0e0e4736"));
+    }
+}
@@ -0,0 +1,270 @@
+//! V8 Workers - 固定大小的 V8 执行线程池
+//!
+//! 所有 V8 工作都在固定数量的专用线程上执行，不占用 Tokio 的阻塞线程池
+//! （存储的文件 I/O 仍使用后者）。任务通过共享队列分发给空闲线程。
+//!
+//! 每个线程缓存一个最近使用的 V8 Isolate，同一函数的连续调用可以复用它，
+//! 省去创建 Isolate 的开销。每次执行仍使用新的 Context，且只在同一函数内复用，
+//! 不同函数之间不共享堆。
+
+use crate::isolate::{CancelToken, ExecutionResult, IsolateConfig, NexoIsolate};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use tokio::sync::oneshot;
+
+/// 单个 Isolate 最多复用的次数，超过后重建以回收堆内存
+const MAX_ISOLATE_REUSE: u32 = 1000;
+
+/// 工作线程配置
+#[derive(Debug, Clone)]
+pub struct WorkerConfig {
+    /// 线程数
+    pub threads: usize,
+    /// 是否把线程绑定到 CPU 核心
+    pub pin_cpus: bool,
+}
+
+impl WorkerConfig {
+    /// 默认按 CPU 核心数创建线程
+    pub fn new() -> Self {
+        Self {
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+            pin_cpus: false,
+        }
+    }
+
+    /// 从环境变量读取：NEXO_V8_WORKERS、NEXO_V8_PIN_CPUS
+    pub fn from_env() -> Self {
+        let defaults = Self::new();
+        Self {
+            threads: std::env::var("NEXO_V8_WORKERS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|&n| n > 0)
+                .unwrap_or(defaults.threads),
+            pin_cpus: std::env::var("NEXO_V8_PIN_CPUS").is_ok_and(|v| v == "1" || v == "true"),
+        }
+    }
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 线程缓存的 Isolate
+struct CachedIsolate {
+    function_id: String,
+    max_heap_size_bytes: usize,
+    uses: u32,
+    isolate: v8::OwnedIsolate,
+}
+
+/// 工作线程本地状态
+#[derive(Default)]
+pub struct Worker {
+    cached: Option<CachedIsolate>,
+}
+
+impl Worker {
+    /// 执行函数，同一函数且堆限制不变时复用缓存的 Isolate
+    pub fn execute(
+        &mut self,
+        config: IsolateConfig,
+        code: &str,
+        request_data: serde_json::Value,
+        cancel: &CancelToken,
+    ) -> ExecutionResult {
+        // 在队列中等待时已被取消
+        if let Some(result) = cancel.cancelled_result() {
+            return result;
+        }
+
+        let reusable = matches!(
+            &self.cached,
+            Some(c) if c.function_id == config.function_id
+                && c.max_heap_size_bytes == config.max_heap_size_bytes
+                && c.uses < MAX_ISOLATE_REUSE
+        );
+        // 先释放旧的 Isolate 再创建新的（OwnedIsolate 必须按创建的逆序释放）
+        if !reusable {
+            self.cached = None;
+        }
+
+        let nexo = NexoIsolate::new(config);
+        let cached = self.cached.get_or_insert_with(|| CachedIsolate {
+            function_id: nexo.config().function_id.clone(),
+            max_heap_size_bytes: nexo.config().max_heap_size_bytes,
+            uses: 0,
+            isolate: tracing::info_span!("isolate.create").in_scope(|| nexo.create_isolate()),
+        });
+        cached.uses += 1;
+
+        let result = nexo
+            .execute_in(&mut cached.isolate, code, request_data, cancel)
+            .unwrap_or_else(|e| ExecutionResult::failure(e.to_string()));
+
+        // 失败的执行可能让 Isolate 处于异常状态（如接近堆上限），不再复用
+        if !result.success {
+            self.cached = None;
+        }
+
+        result
+    }
+}
+
+type Job = Box<dyn FnOnce(&mut Worker) + Send>;
+
+/// V8 工作线程池
+pub struct WorkerPool {
+    sender: mpsc::Sender<Job>,
+    threads: usize,
+}
+
+impl WorkerPool {
+    /// 启动工作线程
+    pub fn new(config: WorkerConfig) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(parking_lot::Mutex::new(receiver));
+        let threads = config.threads.max(1);
+
+        let core_ids = if config.pin_cpus {
+            core_affinity::get_core_ids().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+
+        for index in 0..threads {
+            let receiver = Arc::clone(&receiver);
+            let core_id = (!core_ids.is_empty()).then(|| core_ids[index % core_ids.len()]);
+
+            thread::Builder::new()
+                .name(format!("nexo-v8-{}", index))
+                .spawn(move || {
+                    if let Some(core_id) = core_id {
+                        if !core_affinity::set_for_current(core_id) {
+                            eprintln!("[WorkerPool] 线程 {} 绑定 CPU {} 失败", index, core_id.id);
+                        }
+                    }
+                    crate::isolate::init_v8();
+
+                    let mut worker = Worker::default();
+                    loop {
+                        // 只在取任务时持有锁
+                        let job = receiver.lock().recv();
+                        let Ok(job) = job else { break };
+
+                        if panic::catch_unwind(AssertUnwindSafe(|| job(&mut worker))).is_err() {
+                            worker = Worker::default();
+                        }
+                    }
+                })
+                .expect("failed to spawn V8 worker thread");
+        }
+
+        println!(
+            "[WorkerPool] 启动 {} 个 V8 工作线程{}",
+            threads,
+            if core_ids.is_empty() { "" } else { "（绑核）" }
+        );
+
+        Self { sender, threads }
+    }
+
+    /// 在工作线程上执行任务
+    pub async fn run<F, R>(&self, f: F) -> Result<R, String>
+    where
+        F: FnOnce(&mut Worker) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move |worker| {
+            let _ = tx.send(f(worker));
+        });
+
+        self.sender
+            .send(job)
+            .map_err(|_| "V8 worker pool is shut down".to_string())?;
+        rx.await.map_err(|_| "V8 worker panicked".to_string())
+    }
+
+    /// 线程数
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::isolate::FailureKind;
+
+    #[tokio::test]
+    async fn test_isolate_reuse() {
+        let pool = WorkerPool::new(WorkerConfig { threads: 1, pin_cpus: false });
+        let config = IsolateConfig {
+            function_id: "counter".to_string(),
+            ..Default::default()
+        };
+
+        // 全局变量不会在两次执行之间保留
+        let code = r#"
+            var seen = typeof globalThis.counter !== 'undefined';
+            globalThis.counter = 1;
+            function handler() { return { seen: seen }; }
+        "#;
+
+        for _ in 0..2 {
+            let config = config.clone();
+            let result = pool
+                .run(move |worker| worker.execute(config, code, serde_json::json!({}), &CancelToken::new()))
+                .await
+                .unwrap();
+            assert!(result.success);
+            assert_eq!(result.output.unwrap()["seen"], false);
+        }
+
+        let reused = pool
+            .run(|worker| worker.cached.as_ref().map(|c| c.uses))
+            .await
+            .unwrap();
+        assert_eq!(reused, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_running_execution() {
+        let pool = WorkerPool::new(WorkerConfig { threads: 1, pin_cpus: false });
+        let config = IsolateConfig {
+            function_id: "spin".to_string(),
+            max_execution_time_ms: 60_000,
+            ..Default::default()
+        };
+
+        let cancel = CancelToken::new();
+        let token = cancel.clone();
+        let spin = config.clone();
+        let running = pool.run(move |worker| {
+            worker.execute(spin, "function handler() { while (true) {} }", serde_json::json!({}), &token)
+        });
+
+        let canceller = async {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            cancel.cancel(FailureKind::Cancelled, "client disconnected");
+        };
+        let (result, _) = tokio::join!(running, canceller);
+        let result = result.unwrap();
+        assert_eq!(result.failure_kind, Some(FailureKind::Cancelled));
+
+        // 线程可以继续执行其他任务
+        let result = pool
+            .run(move |worker| {
+                worker.execute(config, "function handler() { return 1; }", serde_json::json!({}), &CancelToken::new())
+            })
+            .await
+            .unwrap();
+        assert!(result.success);
+    }
+}